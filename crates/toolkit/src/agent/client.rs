@@ -2,6 +2,7 @@ use std::path::Path;
 use std::time::Duration;
 
 use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
 use tracing::info;
 
 /// CVM agent client for communicating with a deployed CVM.
@@ -30,16 +31,11 @@ impl AgentClient {
         format!("https://{}:8000", self.ip)
     }
 
-    /// Update workload on the running CVM.
-    pub fn update_workload(&self, workload_dir: &Path) -> Result<()> {
-        info!("Zipping workload directory...");
-
-        // Create a zip of the workload directory
-        let zip_data = create_workload_zip(workload_dir)?;
-
+    /// Update workload on the running CVM with a zip from `create_workload_zip`.
+    pub fn update_workload(&self, zip_data: &[u8]) -> Result<()> {
         info!(size = zip_data.len(), "Uploading workload to CVM...");
 
-        let part = reqwest::blocking::multipart::Part::bytes(zip_data)
+        let part = reqwest::blocking::multipart::Part::bytes(zip_data.to_vec())
             .file_name("workload.zip")
             .mime_str("application/zip")?;
         let form = reqwest::blocking::multipart::Form::new()
@@ -120,6 +116,8 @@ impl AgentClient {
             .header("Authorization", format!("Bearer {}", self.token))
             .send()
             .context("Failed to fetch offchain measurement")?
+            .error_for_status()
+            .context("Offchain measurement request failed")?
             .json::<serde_json::Value>()
             .context("Failed to parse offchain measurement")?;
 
@@ -128,12 +126,47 @@ impl AgentClient {
             .header("Authorization", format!("Bearer {}", self.token))
             .send()
             .context("Failed to fetch onchain measurement")?
+            .error_for_status()
+            .context("Onchain measurement request failed")?
             .json::<serde_json::Value>()
             .context("Failed to parse onchain measurement")?;
 
         Ok((offchain, onchain))
     }
 
+    /// Fetch the CVM's sealing key and the attestation evidence over it.
+    /// The evidence's report data must be SHA-256(key) followed by the nonce
+    /// sent. The quote itself is not verified, so this does not prove the key
+    /// belongs to the CVM; callers must compare it with a pinned key.
+    pub fn get_sealing_key(&self) -> Result<(Vec<u8>, serde_json::Value)> {
+        let nonce: [u8; 32] = rand::random();
+        let resp = self.client
+            .get(format!("{}/sealing-key?nonce={}", self.base_url(), hex::encode(nonce)))
+            .header("Authorization", format!("Bearer {}", self.token))
+            .send()
+            .context("Failed to fetch sealing key")?
+            .error_for_status()
+            .context("Sealing key request failed")?
+            .json::<serde_json::Value>()
+            .context("Failed to parse sealing key response")?;
+
+        let key = resp["public_key"].as_str()
+            .context("Sealing key response has no public_key")?;
+        let key = hex::decode(key.trim_start_matches("0x"))
+            .context("Sealing key is not hex")?;
+        let attestation = resp.get("attestation").cloned()
+            .context("Sealing key response has no attestation")?;
+        let report_data = attestation["report_data"].as_str()
+            .context("Sealing key attestation has no report_data")?;
+
+        let mut expected = Sha256::digest(&key).to_vec();
+        expected.extend_from_slice(&nonce);
+        if hex::decode(report_data.trim_start_matches("0x")).ok() != Some(expected) {
+            bail!("Sealing key is not bound to the CVM's attestation (report data mismatch)");
+        }
+        Ok((key, attestation))
+    }
+
     /// Deploy a livepatch.
     #[allow(dead_code)]
     pub fn deploy_livepatch(&self, livepatch_path: &Path) -> Result<()> {
@@ -160,7 +193,9 @@ impl AgentClient {
 }
 
 /// Create a zip archive of the workload directory.
-fn create_workload_zip(workload_dir: &Path) -> Result<Vec<u8>> {
+pub fn create_workload_zip(workload_dir: &Path) -> Result<Vec<u8>> {
+    info!("Zipping workload directory...");
+
     use std::io::Write;
     use walkdir::WalkDir;
     use zip::write::SimpleFileOptions;
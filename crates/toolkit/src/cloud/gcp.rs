@@ -2,7 +2,7 @@ use std::fs;
 use std::path::Path;
 
 use anyhow::{bail, Context, Result};
-// use base64::Engine;
+use base64::Engine;
 use tracing::info;
 
 use google_cloud_compute_v1::client::{
@@ -31,7 +31,13 @@ async fn get_auth_token() -> Result<String> {
 }
 
 /// Deploy a CVM to GCP.
-pub fn deploy(config: &Config, disk_path: &Path, state: &mut DeployState) -> Result<()> {
+/// `secure_boot_dir` holds the release's secure boot certs (PK/KEK/db), if any.
+pub fn deploy(
+    config: &Config,
+    disk_path: &Path,
+    secure_boot_dir: &Path,
+    state: &mut DeployState,
+) -> Result<()> {
     let project = config.project_id.as_deref()
         .context("project_id is required for GCP")?;
     let bucket = config.bucket.as_deref().unwrap_or(&config.vm_name);
@@ -48,7 +54,7 @@ pub fn deploy(config: &Config, disk_path: &Path, state: &mut DeployState) -> Res
 
         // Compute operations via SDK
         let image_name = format!("{}-image", config.vm_name);
-        create_image(project, &image_name, bucket, disk_path, config).await?;
+        create_image(project, &image_name, bucket, disk_path, secure_boot_dir, config).await?;
         state.image_name = Some(image_name.clone());
 
         let fw_name = format!("{}-fw", config.vm_name);
@@ -128,6 +134,33 @@ pub fn destroy(state: &DeployState) -> Result<()> {
     })
 }
 
+/// Read a Secret Manager secret version
+/// (`projects/<project>/secrets/<secret>/versions/<version>`).
+pub fn access_secret(name: &str) -> Result<Vec<u8>> {
+    block_on(async {
+        let token = get_auth_token().await?;
+        let resp = reqwest::Client::new()
+            .get(format!("{}/{}:access", SECRET_MANAGER_BASE, name))
+            .bearer_auth(&token)
+            .send().await
+            .context("Failed to reach Secret Manager")?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let err = resp.text().await.unwrap_or_default();
+            bail!("Failed to access secret '{}' ({}): {}", name, status, err);
+        }
+
+        let body: serde_json::Value = resp.json().await?;
+        let data = body["payload"]["data"].as_str()
+            .with_context(|| format!("Secret '{}' has no payload", name))?;
+        base64::engine::general_purpose::STANDARD.decode(data)
+            .with_context(|| format!("Secret '{}' payload is not base64", name))
+    })
+}
+
+const SECRET_MANAGER_BASE: &str = "https://secretmanager.googleapis.com/v1";
+
 // --- GCS REST API helpers ---
 
 const GCS_BASE: &str = "https://storage.googleapis.com/storage/v1";
@@ -269,6 +302,7 @@ async fn create_image(
     image_name: &str,
     bucket: &str,
     disk_path: &Path,
+    cert_dir: &Path,
     _config: &Config,
 ) -> Result<()> {
     let filename = disk_path.file_name()
@@ -312,8 +346,6 @@ async fn create_image(
         .set_guest_os_features(features);
 
     // Secure boot certs
-    let disk_dir = Config::disk_cache_dir()?;
-    let cert_dir = disk_dir.join("secure_boot");
     if cert_dir.join("PK.crt").exists() {
         let pk = read_cert_as_file_content_buffer(&cert_dir.join("PK.crt"))?;
         let kek = read_cert_as_file_content_buffer(&cert_dir.join("KEK.crt"))?;
@@ -0,0 +1,176 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use indexmap::IndexMap;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::controller::secrets::ControllerSecrets;
+use crate::disk;
+use crate::disk::artifact::{self, ArtifactManifest, PredictedMeasurements};
+use crate::disk::cache::{CacheManifest, CachedDisk};
+use crate::disk::prepare::DiskMode;
+use crate::workload;
+use crate::workload::resolve::ResolvedWorkload;
+use crate::workload::snapshot::WorkloadSnapshot;
+
+/// A disk image with the workload and API token hash baked in.
+pub struct Prepared {
+    pub token: String,
+    pub cached: CachedDisk,
+    pub workload_hash: [u8; 48],
+    /// Image reference per compose service, as packaged
+    pub images: IndexMap<String, String>,
+    pub snapshot: WorkloadSnapshot,
+    /// Key `.env` and `secrets/` were sealed to (`seal_secrets`)
+    pub sealing_key: Option<String>,
+    /// The workload baked into the disk (temp dir lives as long as this)
+    pub workload: ResolvedWorkload,
+}
+
+/// Resolve the workload, fetch the release disk and prepare a copy of it at `dest`.
+/// Runtime data in the snapshot is hashed with `snapshot_key`.
+pub fn prepare(
+    config: &Config,
+    dest: &Path,
+    controller: &ControllerSecrets,
+    snapshot_key: &str,
+    mode: DiskMode,
+) -> Result<Prepared> {
+    // 1. Resolve workload
+    let workload = workload::resolve::resolve(config, controller)?;
+    info!(path = %workload.path.display(), "Workload resolved");
+    workload::signatures::verify(config, &workload.manifest)?;
+    let files = workload::measure::files(&workload.path)?;
+    let workload_hash = workload::measure::measure(&workload.path, &files)?;
+    let snapshot = WorkloadSnapshot::capture(&workload.path, snapshot_key)?;
+    let sealing_key = match workload::seal::configured_key(config)? {
+        Some(key) => {
+            workload::seal::seal(&workload.path, &key)?;
+            Some(workload::seal::key_hex(&key))
+        }
+        None => None,
+    };
+
+    // 2. Download disk image (cached)
+    let cached = disk::download::download_disk(config)?;
+    info!(tag = %cached.tag, path = %cached.path.display(), "Disk image ready");
+    if config.require_provenance {
+        disk::provenance::require(config, &cached)?;
+    }
+
+    // 3. Copy disk to the destination (don't modify cached copy)
+    info!("Copying disk image...");
+    std::fs::copy(&cached.path, dest)
+        .with_context(|| format!("Failed to copy disk image to {}", dest.display()))?;
+
+    // 4. Prepare disk: inject workload + generate token (single repack cycle)
+    //    Native via e2fsprogs when available, else the disktools container
+    let token = disk::prepare::prepare_disk(
+        config,
+        dest,
+        &workload.path,
+        &cached.raw_cache_dir()?,
+        mode,
+    )?;
+
+    Ok(Prepared {
+        token,
+        cached,
+        workload_hash,
+        images: workload.manifest.images(),
+        snapshot,
+        sealing_key,
+        workload,
+    })
+}
+
+/// Build a prepared disk artifact that `deploy --image` can roll out as-is.
+/// The API token, controller secrets and snapshot key are taken from the
+/// artifact at `secrets_from` when given, so the rebuild can be compared
+/// with it byte for byte.
+pub fn run(config: Config, output: PathBuf, secrets_from: Option<PathBuf>) -> Result<()> {
+    if !output.to_string_lossy().ends_with(".tar.gz") || config.csp != "gcp" {
+        bail!("Only GCP disk artifacts (.tar.gz) can be built for now");
+    }
+    info!(vm_name = %config.vm_name, output = %output.display(), "Building disk artifact");
+
+    let (token, controller, snapshot_key, earlier) = match secrets_from {
+        Some(ref earlier) => {
+            let manifest = ArtifactManifest::load(earlier)?;
+            let controller = artifact::load_controller_secrets(earlier)?
+                .with_context(|| format!("{} has no controller secrets", earlier.display()))?;
+            let snapshot_key = artifact::load_snapshot_key(earlier)?
+                .with_context(|| format!("{} has no snapshot key", earlier.display()))?;
+            (artifact::load_token(earlier)?, controller, snapshot_key, Some(manifest))
+        }
+        None => (
+            disk::prepare::generate_token(),
+            ControllerSecrets::generate(),
+            workload::snapshot::generate_key(),
+            None,
+        ),
+    };
+    if config.seal_secrets {
+        warn!("Sealing uses fresh ephemeral keys, so sealed builds are not byte-identical");
+    }
+    let prepared = prepare(&config, &output, &controller, &snapshot_key, DiskMode::Build { token: &token })?;
+
+    let workload = Some(&prepared.workload_hash);
+    let measurements = match config.confidential_compute_type() {
+        "TDX" => PredictedMeasurements {
+            platform: "tdx".to_string(),
+            rtmr3: Some(hex::encode(workload::measure::rtmr3(workload))),
+            host_data: None,
+        },
+        _ => PredictedMeasurements {
+            platform: "snp".to_string(),
+            rtmr3: None,
+            host_data: Some(hex::encode(workload::measure::host_data(workload))),
+        },
+    };
+
+    let base = CacheManifest::for_disk(&prepared.cached.path);
+    let manifest = ArtifactManifest {
+        csp: config.csp.clone(),
+        release_tag: prepared.cached.tag.clone(),
+        base_sha256: base.map(|m| m.sha256),
+        sha256: disk::cache::sha256_file(&output)?,
+        size: std::fs::metadata(&output)?.len(),
+        vm_name: config.vm_name.clone(),
+        workload_hash: hex::encode(prepared.workload_hash),
+        token_hash: hex::encode(Sha256::digest(prepared.token.as_bytes())),
+        images: prepared.images.clone(),
+        measurements,
+        sealing_key: prepared.sealing_key.clone(),
+        workload: Some(prepared.snapshot.clone()),
+        built_at: chrono::Utc::now().to_rfc3339(),
+        toolkit_version: env!("CARGO_PKG_VERSION").to_string(),
+    };
+    manifest.save(&output)?;
+    let token_path = artifact::save_token(&output, &prepared.token)?;
+    artifact::save_controller_secrets(&output, &controller)?;
+    artifact::save_snapshot_key(&output, &snapshot_key)?;
+
+    if let Some(earlier) = earlier {
+        if earlier.sha256 == manifest.sha256 {
+            info!(sha256 = %manifest.sha256, "Rebuild is byte-identical to the earlier artifact");
+        } else {
+            warn!(earlier = %earlier.sha256, rebuilt = %manifest.sha256, "Rebuild differs from the earlier artifact");
+        }
+    }
+
+    info!("Disk artifact built");
+    println!("Artifact:      {}", output.display());
+    println!("SHA256:        {}", manifest.sha256);
+    println!("Release:       {}", manifest.release_tag);
+    println!("Workload hash: {}", manifest.workload_hash);
+    if let Some(ref key) = manifest.sealing_key {
+        println!("Sealed to:     {}", key);
+    }
+    println!("Manifest:      {}", ArtifactManifest::path(&output).display());
+    println!("API token:     {} (keep it with the artifact, like the controller secrets)", token_path.display());
+    println!("Deploy with:   toolkit deploy --config <cvm.yaml> --image {}", output.display());
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use anyhow::Result;
+use indicatif::HumanBytes;
+use tracing::info;
+
+use crate::config::{self, Config};
+use crate::disk::{cache, download};
+
+/// Show cached disk images per release tag.
+pub fn list(verify: bool) -> Result<()> {
+    let mut entries = cache::entries()?;
+    let legacy = cache::legacy_paths()?;
+
+    if entries.is_empty() && legacy.is_empty() {
+        println!("Disk cache is empty ({})", Config::disk_cache_dir()?.display());
+        return Ok(());
+    }
+
+    if verify {
+        for entry in &mut entries {
+            entry.verify()?;
+        }
+    }
+
+    println!("{:<16} {:>10}  {:<12} {:<20} FILE", "TAG", "SIZE", "STATUS", "LAST USED");
+    for entry in &entries {
+        let last_used = entry.last_used()
+            .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "-".to_string());
+        let files = entry.manifests.iter().map(|m| m.filename.as_str()).collect::<Vec<_>>();
+        let file = if files.is_empty() { "-".to_string() } else { files.join(", ") };
+        println!(
+            "{:<16} {:>10}  {:<12} {:<20} {}",
+            entry.tag,
+            HumanBytes(entry.size).to_string(),
+            entry.status.to_string(),
+            last_used,
+            file
+        );
+    }
+
+    if !legacy.is_empty() {
+        let size: u64 = legacy.iter().map(|p| cache::dir_size(p)).sum();
+        println!(
+            "{:<16} {:>10}  {:<12} {:<20} removed by `cache prune`",
+            "(old layout)",
+            HumanBytes(size).to_string(),
+            "-",
+            "-"
+        );
+    }
+
+    let total: u64 = cache::dir_size(&Config::disk_cache_dir()?);
+    println!("\nTotal: {} in {}", HumanBytes(total), Config::disk_cache_dir()?.display());
+    Ok(())
+}
+
+/// Keep the `keep` most recently used tags and remove the rest.
+pub fn prune(keep: usize) -> Result<()> {
+    let entries = cache::entries()?;
+    let mut freed = 0;
+
+    for entry in entries.iter().skip(keep) {
+        info!(tag = %entry.tag, size = %HumanBytes(entry.size), "Removing cached release");
+        cache::remove(&entry.dir)?;
+        freed += entry.size;
+    }
+    for path in cache::legacy_paths()? {
+        info!(path = %path.display(), "Removing old-layout cache entry");
+        freed += cache::dir_size(&path);
+        cache::remove(&path)?;
+    }
+
+    info!(freed = %HumanBytes(freed), kept = entries.len().min(keep), "Disk cache pruned");
+    Ok(())
+}
+
+/// Remove everything from the disk cache.
+pub fn clear() -> Result<()> {
+    let root = Config::disk_cache_dir()?;
+    let size = cache::dir_size(&root);
+    cache::remove(&root)?;
+    info!(freed = %HumanBytes(size), path = %root.display(), "Disk cache cleared");
+    Ok(())
+}
+
+/// Download a release's disk image into the cache ahead of time.
+pub fn prefetch(config: Option<Config>, tag: Option<String>, csp: Option<String>) -> Result<()> {
+    let tag = tag
+        .or_else(|| config.as_ref().map(|c| c.release_tag.clone()))
+        .unwrap_or_else(|| "latest".to_string());
+    let csp = csp
+        .or_else(|| config.as_ref().map(|c| c.csp.clone()))
+        .unwrap_or_else(|| "gcp".to_string());
+    let connections = config.as_ref().map_or(1, |c| c.download_connections);
+
+    let cached = download::download_release_disk(&tag, config::disk_filename(&csp), connections)?;
+    info!(tag = %cached.tag, path = %cached.path.display(), "Disk image cached");
+    Ok(())
+}
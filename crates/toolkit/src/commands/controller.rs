@@ -0,0 +1,59 @@
+use std::io::{self, BufRead, IsTerminal};
+
+use anyhow::{bail, Context, Result};
+use tracing::info;
+
+use crate::config::Config;
+use crate::controller::client::ControllerClient;
+use crate::state::{self, DeployState};
+
+/// Show the controller's mode.
+pub fn status(config: Config) -> Result<()> {
+    let controller = client(&config)?;
+    let status = controller.status()?;
+
+    println!("Controller: {}", controller.base_url());
+    println!("Mode:       {}", status.status);
+    if let Some(message) = status.message {
+        println!("Message:    {}", message);
+    }
+    Ok(())
+}
+
+/// Print just the mode (tool-node, internet, error or switching).
+pub fn mode(config: Config) -> Result<()> {
+    println!("{}", client(&config)?.mode()?);
+    Ok(())
+}
+
+/// Enter (internet mode) or leave (tool-node mode) maintenance mode.
+pub fn maintenance(config: Config, enable: bool) -> Result<()> {
+    let mode = client(&config)?.set_maintenance(enable)?;
+    println!("Mode: {}", mode);
+    Ok(())
+}
+
+/// Store the controller API key for this VM, read from stdin.
+pub fn set_key(config: Config) -> Result<()> {
+    // Must exist, so the key is not kept for a VM that was never deployed
+    DeployState::load(&config.vm_name)?;
+
+    if io::stdin().is_terminal() {
+        eprint!("Controller API key: ");
+    }
+    let mut key = String::new();
+    io::stdin().lock().read_line(&mut key).context("Failed to read the API key")?;
+    let key = key.trim();
+    if key.is_empty() {
+        bail!("No API key given");
+    }
+
+    let path = DeployState::save_secret(&config.vm_name, state::CONTROLLER_KEY, key)?;
+    info!(vm_name = %config.vm_name, path = %path.display(), "Controller API key saved");
+    Ok(())
+}
+
+fn client(config: &Config) -> Result<ControllerClient> {
+    let state = DeployState::load(&config.vm_name)?;
+    ControllerClient::for_deployment(config, &state)
+}
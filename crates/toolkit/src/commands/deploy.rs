@@ -1,59 +1,110 @@
-use anyhow::{Context, Result};
-use tracing::info;
+use std::path::{Path, PathBuf};
 
-use crate::agent::client::AgentClient;
+use anyhow::{bail, Context, Result};
+use indexmap::IndexMap;
+use tracing::{info, warn};
+
+use crate::agent::client::{self, AgentClient};
 use crate::cloud;
 use crate::config::Config;
+use crate::controller::secrets::ControllerSecrets;
 use crate::disk;
-use crate::state::DeployState;
+use crate::disk::artifact::{self, ArtifactManifest};
+use crate::disk::prepare::DiskMode;
+use crate::state::{self, DeployState};
 use crate::workload;
+use crate::workload::snapshot::WorkloadSnapshot;
+
+use super::build;
+
+/// The disk `deploy` rolls out and what goes with it.
+struct DeployDisk {
+    path: PathBuf,
+    secure_boot_dir: PathBuf,
+    token: String,
+    /// Image reference per compose service
+    images: IndexMap<String, String>,
+    sealing_key: Option<String>,
+    /// Set when the disk was prepared here rather than taken from `--image`
+    prepared: Option<build::Prepared>,
+    controller: Option<ControllerSecrets>,
+    snapshot: Option<WorkloadSnapshot>,
+}
 
-pub fn run(config: Config) -> Result<()> {
+pub fn run(config: Config, image: Option<PathBuf>, allow_shared: bool) -> Result<()> {
     info!(vm_name = %config.vm_name, csp = %config.csp, region = %config.region, "Starting deployment");
 
-    // 1. Resolve workload
-    let workload = workload::resolve::resolve(&config)?;
-    info!(path = %workload.path.display(), "Workload resolved");
-
-    // 2. Download disk image (cached)
-    let disk_path = disk::download::download_disk(&config)?;
-
-    // 3. Ensure disktools Docker image
-    disk::docker_ops::ensure_image(&config)?;
-
-    // 4. Copy disk to working directory (don't modify cached copy)
+    // 1-4. Prepare a disk for this VM, or take a prebuilt artifact as-is
     let work_dir = tempfile::tempdir().context("Failed to create temp dir")?;
-    let work_disk = work_dir.path().join(config.disk_filename());
-    info!("Copying disk image to working directory...");
-    std::fs::copy(&disk_path, &work_disk)
-        .context("Failed to copy disk image")?;
-
-    // 5. Prepare disk: inject workload + generate token (single mount/repack cycle)
-    //    Uses raw disk cache (pigz for parallel compression)
-    let token = disk::docker_ops::prepare_disk(&config, &work_disk, &workload.path)?;
+    let target = match image {
+        Some(image) => {
+            let (secure_boot_dir, token, manifest) = use_artifact(&config, &image, allow_shared)?;
+            let controller = artifact::load_controller_secrets(&image)?;
+            if let Some(key) = artifact::load_snapshot_key(&image)? {
+                DeployState::save_secret(&config.vm_name, state::SNAPSHOT_KEY, &key)?;
+            }
+            DeployDisk {
+                path: image,
+                secure_boot_dir,
+                token,
+                images: manifest.images,
+                sealing_key: manifest.sealing_key,
+                prepared: None,
+                controller,
+                snapshot: manifest.workload,
+            }
+        }
+        None => {
+            let controller = ControllerSecrets::load_or_generate(&config.vm_name)?;
+            let work_disk = work_dir.path().join(config.disk_filename());
+            let snapshot_key = workload::snapshot::vm_key(&config.vm_name)?;
+            let prepared = build::prepare(&config, &work_disk, &controller, &snapshot_key, DiskMode::Deploy)?;
+            DeployDisk {
+                path: work_disk,
+                secure_boot_dir: prepared.cached.secure_boot_dir(),
+                token: prepared.token.clone(),
+                images: prepared.images.clone(),
+                sealing_key: prepared.sealing_key.clone(),
+                snapshot: Some(prepared.snapshot.clone()),
+                prepared: Some(prepared),
+                controller: Some(controller),
+            }
+        }
+    };
 
-    // 6. Create deployment state
+    // 5. Create deployment state
     let mut state = DeployState::from_config(&config);
-    state.api_token = Some(token.clone());
+    state.api_token = Some(target.token.clone());
+    state.image_digests = workload::digests::pinned(&target.images);
+    state.sealing_key = target.sealing_key.clone();
 
-    // 7. Deploy to cloud
+    // 6. Deploy to cloud
     match config.csp.as_str() {
         "gcp" => {
-            cloud::gcp::deploy(&config, &work_disk, &mut state)?;
+            cloud::gcp::deploy(&config, &target.path, &target.secure_boot_dir, &mut state)?;
         }
         other => {
             anyhow::bail!("CSP '{}' not yet supported", other);
         }
     }
 
-    // 8. Save state
+    // 7. Keep the controller secrets for `update` and `toolkit controller`,
+    //    archive the workload (the first `rollback` target) and save state
+    if let Some(controller) = &target.controller {
+        controller.save(&config.vm_name)?;
+    }
+    if let Some(prepared) = &target.prepared {
+        let zip_data = client::create_workload_zip(&prepared.workload.path)?;
+        state.archive_workload(&zip_data, &prepared.snapshot.hash, target.controller.as_ref())?;
+    }
+    state.workload = target.snapshot;
     state.save()?;
     info!(state_file = %DeployState::state_path(&config.vm_name)?.display(), "State saved");
 
-    // 9. Fetch golden measurements
+    // 8. Fetch golden measurements
     if let Some(ref ip) = state.ip {
         info!(ip, "Fetching golden measurements...");
-        let client = AgentClient::new(ip, &token)?;
+        let client = AgentClient::new(ip, &target.token)?;
         match client.get_measurements() {
             Ok((offchain, onchain)) => {
                 let measurements_dir = Config::state_dir()?.join("measurements");
@@ -81,3 +132,77 @@ pub fn run(config: Config) -> Result<()> {
 
     Ok(())
 }
+
+/// Check a `toolkit build` artifact against its manifest and token.
+/// Returns the release's secure boot cert dir, the API token and the manifest.
+/// Every VM deployed from one artifact has the same API token and controller
+/// secrets, so that takes `allow_shared`.
+fn use_artifact(config: &Config, image: &Path, allow_shared: bool) -> Result<(PathBuf, String, ArtifactManifest)> {
+    let manifest = ArtifactManifest::load(image)?;
+    manifest.verify(image)?;
+    if manifest.csp != config.csp {
+        bail!("{} was built for {}, config is for {}", image.display(), manifest.csp, config.csp);
+    }
+
+    let token = artifact::load_token(image)?;
+    if !manifest.matches_token(&token) {
+        bail!("{} does not match the token hash in the build manifest", artifact::token_path(image).display());
+    }
+
+    let mut sharing = deployed_with(&token, &config.vm_name)?;
+    if manifest.vm_name != config.vm_name && !sharing.contains(&manifest.vm_name) {
+        sharing.insert(0, manifest.vm_name.clone());
+    }
+    if !sharing.is_empty() {
+        if !allow_shared {
+            bail!(
+                "{} already belongs to {}, and VMs deployed from one artifact share its API token \
+                 and controller secrets. Build an artifact per VM, or pass --allow-shared-artifact",
+                image.display(),
+                sharing.join(", ")
+            );
+        }
+        warn!(
+            built_for = %manifest.vm_name,
+            shared_with = %sharing.join(", "),
+            "Deploying a shared artifact (secrets/identity.env keeps the built VM name)"
+        );
+    }
+
+    // Secure boot certs come with the release the artifact was built from
+    let release = disk::download::download_release_disk(
+        &manifest.release_tag,
+        config.disk_filename(),
+        config.download_connections,
+    )?;
+    info!(
+        image = %image.display(),
+        tag = %manifest.release_tag,
+        workload_hash = %manifest.workload_hash,
+        "Using prebuilt disk artifact"
+    );
+    Ok((release.secure_boot_dir(), token, manifest))
+}
+
+/// Other deployed VMs whose state holds `token`.
+fn deployed_with(token: &str, vm_name: &str) -> Result<Vec<String>> {
+    let dir = Config::state_dir()?;
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return Ok(Vec::new());
+    };
+    let mut names = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()).and_then(|n| n.strip_suffix(".yaml")) else {
+            continue;
+        };
+        if name == vm_name {
+            continue;
+        }
+        if DeployState::load(name).is_ok_and(|s| s.api_token.as_deref() == Some(token)) {
+            names.push(name.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
@@ -0,0 +1,118 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use indicatif::HumanBytes;
+use tracing::info;
+
+use crate::disk::{cache, inspect, prepare};
+
+/// Print what is baked into a disk image: partitions, boot kernel and
+/// cmdline, token hash and the workload tree.
+pub fn inspect(image: Option<PathBuf>, tag: Option<String>) -> Result<()> {
+    let image = match (image, tag) {
+        (Some(image), _) => image,
+        (None, Some(tag)) => cached_image(&tag)?,
+        (None, None) => bail!("Pass a disk image or --tag"),
+    };
+    if !image.exists() {
+        bail!("{} not found", image.display());
+    }
+
+    let name = image.to_string_lossy();
+    let work_dir = tempfile::tempdir().context("Failed to create temp dir")?;
+    let raw = if name.ends_with(".tar.gz") {
+        let raw = work_dir.path().join(prepare::RAW_NAME);
+        prepare::extract_raw(&image, &raw)?;
+        raw
+    } else if name.ends_with(".vmdk") || name.ends_with(".vhd") {
+        bail!("Only .tar.gz and raw disk images can be inspected");
+    } else {
+        image.clone()
+    };
+
+    let report = inspect::inspect(&raw)?;
+
+    println!("Image: {} ({})", image.display(), HumanBytes(report.size));
+
+    println!("\nPartitions:");
+    println!("  {:<3} {:>11} {:>11}  {:<28} {:<9} NAME", "#", "START", "SIZE", "TYPE", "FS");
+    for (part, fs) in &report.partitions {
+        println!(
+            "  {:<3} {:>11} {:>11}  {:<28} {:<9} {}",
+            part.index,
+            HumanBytes(part.offset()).to_string(),
+            HumanBytes(part.size()).to_string(),
+            part.type_name(),
+            fs,
+            part.name
+        );
+    }
+
+    println!("\nBoot:");
+    if report.boot.is_empty() {
+        println!("  no kernel found");
+    }
+    for entry in &report.boot {
+        println!("  {} ({})", entry.source, entry.kind);
+        if let Some(ref kernel) = entry.kernel {
+            println!("    Kernel:  {}", kernel);
+        }
+        if let Some(ref os) = entry.os {
+            println!("    OS:      {}", os);
+        }
+        if let Some(ref cmdline) = entry.cmdline {
+            println!("    Cmdline: {}", cmdline);
+        }
+    }
+
+    let Some(data) = report.data else {
+        println!("\nData partition: not found (expected ext4 on p{})", prepare::DATA_PARTITION);
+        return Ok(());
+    };
+    let used = data.used.map_or("unknown".to_string(), |u| HumanBytes(u).to_string());
+    println!("\nData partition (p{}): {} used of {}", data.index, used, HumanBytes(data.size));
+    println!("  token_hash: {}", data.token_hash.as_deref().unwrap_or("not set"));
+
+    if data.workload.is_empty() {
+        println!("  workload:   not present");
+        return Ok(());
+    }
+    println!("  workload:");
+    for (depth, entry) in &data.workload {
+        let indent = "  ".repeat(depth + 2);
+        if entry.is_dir() {
+            println!("  {}{}/", indent, entry.name);
+        } else {
+            println!(
+                "  {}{:<width$} {:>10}  {:o} {}:{}",
+                indent,
+                entry.name,
+                HumanBytes(entry.size).to_string(),
+                entry.mode & 0o7777,
+                entry.uid,
+                entry.gid,
+                width = 40usize.saturating_sub(indent.len()),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The last prepared raw disk of a cached release, else its downloaded image.
+fn cached_image(tag: &str) -> Result<PathBuf> {
+    let entry = cache::entries()?
+        .into_iter()
+        .find(|e| e.tag == tag)
+        .with_context(|| format!("Release {} is not in the disk cache", tag))?;
+
+    let raw = entry.dir.join("raw_cache").join(prepare::RAW_NAME);
+    if raw.exists() {
+        info!(path = %raw.display(), "Inspecting last prepared disk");
+        return Ok(raw);
+    }
+    let manifest = entry
+        .manifests
+        .first()
+        .with_context(|| format!("Cached release {} has no manifest", tag))?;
+    Ok(entry.dir.join(&manifest.filename))
+}
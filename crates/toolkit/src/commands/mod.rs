@@ -1,7 +1,13 @@
+pub mod build;
+pub mod cache;
+pub mod controller;
 pub mod deploy;
 pub mod destroy;
+pub mod disk;
 pub mod init;
 pub mod logs;
 pub mod measurements;
+pub mod provenance;
+pub mod rollback;
 pub mod sim_agent;
 pub mod update;
@@ -0,0 +1,43 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use tracing::info;
+
+use crate::config::Config;
+use crate::disk;
+
+/// Download the build provenance bundle for the configured release.
+pub fn fetch(config: Config) -> Result<()> {
+    let path = disk::provenance::fetch(&config)?;
+    println!("{}", path.display());
+    Ok(())
+}
+
+/// Verify the cached disk image against its build provenance.
+pub fn verify(config: Config, trust_root: Option<PathBuf>) -> Result<()> {
+    let trust_root = trust_root
+        .or_else(|| config.trust_root_path())
+        .context("No trust root: pass --trust-root or set 'trust_root' in the config")?;
+
+    let cached = disk::download::download_disk(&config)?;
+    if !disk::provenance::bundle_path(&cached).exists() {
+        disk::provenance::fetch(&config)?;
+    }
+    let p = disk::provenance::verify(&cached, &trust_root)?;
+
+    info!("Build provenance verified");
+    let unknown = |v: &Option<String>| v.clone().unwrap_or_else(|| "unknown".to_string());
+    println!("Release:     {}", p.tag);
+    println!("Disk SHA256: {}", p.sha256);
+    println!("Signed by:   {}", p.signer);
+    println!("Signed at:   {}", p.signed_at);
+    println!("Rekor index: {}", p.log_index);
+    println!("Predicate:   {}", p.predicate_type);
+    println!("Repository:  {}", unknown(&p.repository));
+    println!("Ref:         {}", unknown(&p.git_ref));
+    println!("Commit:      {}", unknown(&p.commit));
+    println!("Workflow:    {}", unknown(&p.workflow));
+    println!("Builder:     {}", unknown(&p.builder));
+    println!("Started:     {}", unknown(&p.started_on));
+    Ok(())
+}
@@ -0,0 +1,185 @@
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::agent::client::AgentClient;
+use crate::config::Config;
+use crate::controller::client::ControllerClient;
+use crate::controller::secrets;
+use crate::state::{self, DeployState, Rollback};
+use crate::types::WorkloadManifest;
+use crate::workload;
+use crate::workload::manifest::MANIFEST_FILE;
+use crate::workload::snapshot::WorkloadSnapshot;
+
+use super::update;
+
+/// Re-upload an archived workload version (by default the one before the
+/// current version) and record the rollback in state. Image signatures are
+/// checked like `update` does, and the stored controller secrets follow the
+/// restored workload. With `maintenance`, the upload happens in the
+/// controller's maintenance mode.
+pub fn run(config: Config, to: Option<u32>, maintenance: bool) -> Result<()> {
+    let mut state = DeployState::load(&config.vm_name)?;
+
+    let ip = state.ip.clone()
+        .ok_or_else(|| anyhow::anyhow!("No IP found in state for '{}'", config.vm_name))?;
+    let token = state.api_token.clone()
+        .ok_or_else(|| anyhow::anyhow!("No API token found in state for '{}'", config.vm_name))?;
+
+    let current = state.workload_version;
+    let target = match to {
+        Some(version) => version,
+        None => {
+            let current = current.context("No current workload version recorded, pass --to")?;
+            state.workload_versions.iter()
+                .map(|v| v.version)
+                .filter(|v| *v < current)
+                .max()
+                .with_context(|| format!("No archived workload before version {}", current))?
+        }
+    };
+    if current == Some(target) {
+        bail!("Workload version {} is already on '{}'", target, config.vm_name);
+    }
+    if !state.workload_versions.iter().any(|v| v.version == target) {
+        bail!("Workload version {} is not archived (see `toolkit rollback --list`)", target);
+    }
+
+    let path = state.workload_zip_path(target)?;
+    let zip_data = fs::read(&path)
+        .with_context(|| format!("Failed to read archived workload {}", path.display()))?;
+
+    // Unpack to record what goes on the VM, like `update` does
+    let unpacked = tempfile::tempdir().context("Failed to create temp dir")?;
+    zip::ZipArchive::new(Cursor::new(&zip_data))
+        .and_then(|mut archive| archive.extract(unpacked.path()))
+        .with_context(|| format!("Invalid workload archive {}", path.display()))?;
+    let dir = unpacked.path().join("workload");
+    let manifest_path = dir.join(MANIFEST_FILE);
+    let manifest: WorkloadManifest = fs::read_to_string(&manifest_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| Ok(serde_json::from_str(&content)?))
+        .with_context(|| format!("Invalid workload archive {} (no {})", path.display(), MANIFEST_FILE))?;
+    workload::signatures::verify(&config, &manifest)?;
+    let snapshot = WorkloadSnapshot::capture(&dir, &workload::snapshot::vm_key(&config.vm_name)?)?;
+    let controller_secrets = archived_controller_secrets(&state, target, &dir)?;
+
+    info!(vm_name = %config.vm_name, from = ?current, to = target, "Rolling back workload...");
+    let client = AgentClient::new(&ip, &token)?;
+    let controller = if maintenance {
+        let key = controller_secrets.as_ref().and_then(|s| s.api_key.as_deref()).with_context(|| {
+            format!("The controller key of workload version {} is unknown, roll back without --maintenance", target)
+        })?;
+        let jwt_secret = controller_secrets.as_ref().and_then(|s| s.jwt_secret.as_deref());
+        let current = ControllerClient::for_deployment(&config, &state)?;
+        let restored = current.with_workload_key(key, jwt_secret)?;
+        Some((current, restored))
+    } else {
+        None
+    };
+    let record = |state: &mut DeployState| -> Result<()> {
+        restore_controller_secrets(&state.vm_name, controller_secrets.as_ref())?;
+        state.workload_version = Some(target);
+        state.rollbacks.push(Rollback {
+            from: current,
+            to: target,
+            at: Utc::now().to_rfc3339(),
+        });
+        state.image_digests = workload::digests::pinned(&manifest.images());
+        state.workload = Some(snapshot.clone());
+        state.save()
+    };
+    match &controller {
+        Some((current, restored)) => {
+            update::upload_in_maintenance(&client, current, restored, &zip_data, || record(&mut state))?
+        }
+        None => {
+            client.update_workload(&zip_data)?;
+            record(&mut state)?
+        }
+    }
+
+    info!(vm_name = %config.vm_name, ip, version = target, "Workload rolled back");
+    Ok(())
+}
+
+/// Print the archived workload versions.
+pub fn list(config: Config) -> Result<()> {
+    let state = DeployState::load(&config.vm_name)?;
+    if state.workload_versions.is_empty() {
+        println!("No archived workloads for '{}'.", config.vm_name);
+        return Ok(());
+    }
+    for version in &state.workload_versions {
+        let marker = if state.workload_version == Some(version.version) { "*" } else { " " };
+        println!(
+            "{} v{:<4} {}  {}",
+            marker,
+            version.version,
+            version.uploaded_at,
+            &version.hash[..16.min(version.hash.len())]
+        );
+    }
+    Ok(())
+}
+
+/// Controller secrets of an archived workload, as far as they are known.
+struct ArchivedSecrets {
+    api_key: Option<String>,
+    jwt_secret: Option<String>,
+}
+
+/// What the workload of `version` was provisioned with: the secrets archived
+/// with it, else the JWT secret from its `secrets/` and the stored API key if
+/// it matches the archived hash. `None` when the workload has no controller
+/// secrets (archived before they were generated).
+fn archived_controller_secrets(state: &DeployState, version: u32, dir: &Path) -> Result<Option<ArchivedSecrets>> {
+    if let Some(archived) = state.archived_controller_secrets(version)? {
+        return Ok(Some(ArchivedSecrets {
+            api_key: Some(archived.api_key),
+            jwt_secret: Some(archived.jwt_secret),
+        }));
+    }
+    let secrets_dir = dir.join("secrets");
+    let provisioned = [secrets::API_KEY_HASH_FILE, secrets::JWT_SECRET_FILE].iter().any(|name| {
+        secrets_dir.join(name).exists()
+            || secrets_dir.join(format!("{}{}", name, workload::seal::SEALED_SUFFIX)).exists()
+    });
+    if !provisioned {
+        return Ok(None);
+    }
+
+    let read = |name: &str| fs::read_to_string(secrets_dir.join(name)).ok().map(|v| v.trim().to_string());
+    let api_key = match (read(secrets::API_KEY_HASH_FILE), DeployState::load_secret(&state.vm_name, state::CONTROLLER_KEY)?) {
+        (Some(hash), Some(key)) if hex::encode(Sha256::digest(key.as_bytes())) == hash => Some(key),
+        _ => None,
+    };
+    Ok(Some(ArchivedSecrets {
+        api_key,
+        jwt_secret: read(secrets::JWT_SECRET_FILE),
+    }))
+}
+
+/// Make the stored controller secrets match the restored workload: restore
+/// what is known and clear the rest, so no key of another version lingers.
+fn restore_controller_secrets(vm_name: &str, archived: Option<&ArchivedSecrets>) -> Result<()> {
+    let (api_key, jwt_secret) = archived.map_or((None, None), |a| (a.api_key.as_deref(), a.jwt_secret.as_deref()));
+    if archived.is_some() && api_key.is_none() {
+        warn!(vm_name, "The controller key of the restored workload is unknown, set it with `toolkit controller set-key`");
+    }
+    for (name, value) in [(state::CONTROLLER_KEY, api_key), (state::JWT_SECRET, jwt_secret)] {
+        match value {
+            Some(value) => {
+                DeployState::save_secret(vm_name, name, value)?;
+            }
+            None => DeployState::remove_secret(vm_name, name)?,
+        }
+    }
+    Ok(())
+}
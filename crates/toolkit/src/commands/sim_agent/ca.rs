@@ -0,0 +1,89 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DistinguishedName, DnType, IsCa, KeyPair,
+    KeyUsagePurpose,
+};
+use tracing::info;
+
+const CA_KEY_FILE: &str = "test-ca.key";
+const CA_CERT_FILE: &str = "test-ca.crt";
+
+/// Local test CA standing in for the Intel / AMD roots that sign attestation evidence.
+/// Persisted so verifiers under test can pin it across sim-agent restarts.
+pub struct TestCa {
+    cert: Certificate,
+    key: KeyPair,
+    /// PEM of the persisted CA certificate.
+    pub pem: String,
+}
+
+impl TestCa {
+    /// Load the CA from `dir`, creating it on first use.
+    pub fn load_or_create(dir: &Path) -> Result<Self> {
+        let key_path = dir.join(CA_KEY_FILE);
+        let cert_path = dir.join(CA_CERT_FILE);
+
+        if key_path.exists() && cert_path.exists() {
+            let key = KeyPair::from_pem(&fs::read_to_string(&key_path)?)
+                .with_context(|| format!("Invalid test CA key: {}", key_path.display()))?;
+            // Issuing only needs the CA's name and key, so rebuild it from the stored key.
+            let cert = ca_params()?.self_signed(&key)?;
+            let pem = fs::read_to_string(&cert_path)?;
+            info!(path = %cert_path.display(), "Loaded sim-agent test CA");
+            return Ok(Self { cert, key, pem });
+        }
+
+        let key = KeyPair::generate_for(&rcgen::PKCS_ECDSA_P384_SHA384)
+            .context("Failed to generate test CA key")?;
+        let cert = ca_params()?.self_signed(&key)?;
+        let pem = cert.pem();
+
+        fs::create_dir_all(dir)?;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&key_path)
+            .with_context(|| format!("Failed to write {}", key_path.display()))?;
+        file.write_all(key.serialize_pem().as_bytes())
+            .with_context(|| format!("Failed to write {}", key_path.display()))?;
+        fs::write(&cert_path, &pem)
+            .with_context(|| format!("Failed to write {}", cert_path.display()))?;
+        info!(path = %cert_path.display(), "Created sim-agent test CA");
+
+        Ok(Self { cert, key, pem })
+    }
+
+    /// Issue a leaf certificate for a PKCS#8 key, returned as PEM.
+    pub fn issue(&self, common_name: &str, pkcs8: &[u8]) -> Result<String> {
+        let key = KeyPair::try_from(pkcs8).context("Invalid leaf key")?;
+        let mut params = CertificateParams::new(Vec::<String>::new())?;
+        params.distinguished_name = name(common_name);
+        params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        let cert = params
+            .signed_by(&key, &self.cert, &self.key)
+            .with_context(|| format!("Failed to issue certificate for {}", common_name))?;
+        Ok(cert.pem())
+    }
+}
+
+fn ca_params() -> Result<CertificateParams> {
+    let mut params = CertificateParams::new(Vec::<String>::new())?;
+    params.distinguished_name = name("toolkit sim-agent test CA");
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+    Ok(params)
+}
+
+fn name(common_name: &str) -> DistinguishedName {
+    let mut dn = DistinguishedName::new();
+    dn.push(DnType::OrganizationName, "toolkit sim-agent (NOT FOR PRODUCTION)");
+    dn.push(DnType::CommonName, common_name);
+    dn
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Route key matching every route.
+pub const ANY_ROUTE: &str = "*";
+
+/// A fault to inject into responses of a route.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Fault {
+    /// Respond with this HTTP status instead of running the handler.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+
+    /// Delay before responding, in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delay_ms: Option<u64>,
+
+    /// Drop the connection without sending a response.
+    #[serde(default)]
+    pub reset: bool,
+
+    /// Cut the response body off after this many bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncate: Option<usize>,
+
+    /// Only affect the next N requests (every request if omitted).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<u32>,
+}
+
+impl Fault {
+    /// Parse a fault spec like `status=503,delay=2000,count=3`, `reset` or `truncate=16`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut fault = Fault::default();
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = part.split_once('=').unwrap_or((part, ""));
+            let num = || value.parse::<u64>()
+                .with_context(|| format!("Invalid value for '{}': '{}'", key, value));
+            match key {
+                "status" => fault.status = Some(num()?.try_into()?),
+                "delay" => fault.delay_ms = Some(num()?),
+                "reset" => fault.reset = true,
+                "truncate" => fault.truncate = Some(num()?.try_into()?),
+                "count" => fault.count = Some(num()?.try_into()?),
+                other => bail!("Unknown fault option '{}' (expected status, delay, reset, truncate, count)", other),
+            }
+        }
+        Ok(fault)
+    }
+}
+
+/// Parse a `ROUTE=SPEC` CLI argument, e.g. `/container-logs=status=500`.
+pub fn parse_route_fault(arg: &str) -> Result<(String, Fault)> {
+    let (route, spec) = arg
+        .split_once('=')
+        .with_context(|| format!("Invalid fault '{}', expected ROUTE=SPEC", arg))?;
+    Ok((route.to_string(), Fault::parse(spec)?))
+}
+
+/// Per-route fault rules plus the simulated boot delay.
+pub struct FaultTable {
+    rules: Mutex<HashMap<String, Fault>>,
+    ready_at: Mutex<Instant>,
+}
+
+impl FaultTable {
+    pub fn new(rules: HashMap<String, Fault>, boot_delay: Duration) -> Result<Self> {
+        Ok(Self {
+            rules: Mutex::new(rules),
+            ready_at: Mutex::new(ready_at(boot_delay)?),
+        })
+    }
+
+    /// Fault for a request path, consuming one use of a counted fault.
+    /// Exact route rules take precedence over the `*` rule.
+    pub fn take(&self, path: &str) -> Option<Fault> {
+        let mut rules = self.rules.lock().unwrap();
+        let key = if rules.contains_key(path) { path } else { ANY_ROUTE };
+        let fault = rules.get_mut(key)?;
+
+        let taken = fault.clone();
+        if let Some(ref mut count) = fault.count {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                rules.remove(key);
+            }
+        }
+        Some(taken)
+    }
+
+    pub fn set(&self, route: String, fault: Fault) {
+        self.rules.lock().unwrap().insert(route, fault);
+    }
+
+    pub fn clear(&self, route: Option<&str>) {
+        let mut rules = self.rules.lock().unwrap();
+        match route {
+            Some(route) => {
+                rules.remove(route);
+            }
+            None => rules.clear(),
+        }
+    }
+
+    pub fn rules(&self) -> HashMap<String, Fault> {
+        self.rules.lock().unwrap().clone()
+    }
+
+    /// Time left until the simulated boot finishes, if still booting.
+    pub fn not_ready_for(&self) -> Option<Duration> {
+        let ready_at = *self.ready_at.lock().unwrap();
+        ready_at.checked_duration_since(Instant::now()).filter(|d| !d.is_zero())
+    }
+
+    /// Restart the simulated boot delay from now.
+    pub fn set_boot_delay(&self, delay: Duration) -> Result<()> {
+        *self.ready_at.lock().unwrap() = ready_at(delay)?;
+        Ok(())
+    }
+}
+
+fn ready_at(delay: Duration) -> Result<Instant> {
+    Instant::now()
+        .checked_add(delay)
+        .with_context(|| format!("Boot delay of {}s is out of range", delay.as_secs()))
+}
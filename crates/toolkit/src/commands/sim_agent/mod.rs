@@ -1,17 +1,204 @@
+mod ca;
+mod faults;
+mod recording;
 mod routes;
+mod sealing;
+mod session;
+mod state;
+mod tee;
+mod workload;
 
-use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use axum_server::tls_rustls::RustlsConfig;
 use tracing::info;
 
-pub fn run(port: u16) -> Result<()> {
+use crate::config::Config;
+use crate::state::DeployState;
+use faults::FaultTable;
+use sealing::SealingKey;
+use state::SimState;
+use tee::{FirmwareConfig, SimTee};
+
+pub use tee::Platform;
+
+/// Address written to deployment state when the sim-agent is registered for a config.
+const SIM_IP: &str = "127.0.0.1";
+
+/// Sim-agent options from the CLI.
+pub struct Options {
+    /// Port for the internal (plain HTTP) API.
+    pub port: u16,
+    /// Port for the management API over TLS (the real agent uses 8000).
+    pub tls_port: u16,
+    /// API token; a random one is generated if omitted.
+    pub token: Option<String>,
+    /// Register the sim-agent as the deployment for this config.
+    pub config: Option<Config>,
+    /// Run uploaded workloads on the host with `docker compose up`.
+    pub run_workload: bool,
+    /// Signing session lifetime in seconds.
+    pub session_ttl: u64,
+    /// Faults to inject, as `ROUTE=SPEC` (see [`faults::Fault::parse`]).
+    pub faults: Vec<String>,
+    /// Seconds to report "not ready" after startup, simulating a booting CVM.
+    pub boot_delay: u64,
+    /// TEE platform whose evidence is simulated.
+    pub tee: Platform,
+    /// YAML file overriding simulated firmware measurements.
+    pub firmware: Option<PathBuf>,
+    /// Proxy to the real agent at this IP and record the traffic.
+    pub record: Option<String>,
+    /// File the recording is written to.
+    pub recording: PathBuf,
+    /// Serve responses from a recording instead of simulating.
+    pub replay: Option<PathBuf>,
+}
+
+pub fn run(opts: Options) -> Result<()> {
+    // Install default rustls crypto provider (required when multiple TLS backends coexist)
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    // Recorded requests go to the real agent as-is, so the registered state
+    // must carry the real agent's token, not a random local one
+    if opts.record.is_some() && opts.config.is_some() && opts.token.is_none() {
+        bail!("--record with --config needs --token (the real agent's API token)");
+    }
+    let token = opts.token.clone().unwrap_or_else(|| hex::encode(rand::random::<[u8; 16]>()));
+
+    if let Some(ref config) = opts.config {
+        register_state(config, &token)?;
+    }
+
+    if opts.record.is_some() || opts.replay.is_some() {
+        return run_recording(&opts, &token);
+    }
+
+    let session_ttl = i64::try_from(opts.session_ttl)
+        .ok()
+        .and_then(chrono::TimeDelta::try_seconds)
+        .context("--session-ttl is out of range")?;
+    let rules = opts
+        .faults
+        .iter()
+        .map(|f| faults::parse_route_fault(f))
+        .collect::<Result<_>>()?;
+    let fault_table = FaultTable::new(rules, Duration::from_secs(opts.boot_delay))
+        .context("--boot-delay is out of range")?;
+    let firmware = match opts.firmware {
+        Some(ref path) => FirmwareConfig::load(path)?,
+        None => FirmwareConfig::default(),
+    };
+    let sim_dir = Config::sim_agent_dir()?;
+    let tee = SimTee::new(opts.tee, &firmware, &sim_dir)?;
+    let sealing = SealingKey::load_or_create(&sim_dir)?;
+    let state = Arc::new(SimState::new(&token, opts.run_workload, session_ttl, fault_table, tee, sealing)?);
+
     let rt = tokio::runtime::Runtime::new()?;
     rt.block_on(async {
-        let app = routes::router();
-        let addr = std::net::SocketAddr::from(([0, 0, 0, 0], port));
-        info!(%addr, "sim-agent listening");
+        let tls = self_signed_tls().await?;
+
+        let addr = std::net::SocketAddr::from(([0, 0, 0, 0], opts.port));
+        let tls_addr = std::net::SocketAddr::from(([0, 0, 0, 0], opts.tls_port));
+        info!(%addr, "sim-agent internal API listening");
+        info!(addr = %tls_addr, "sim-agent management API listening (TLS)");
+        println!("API token: {}", token);
 
         let listener = tokio::net::TcpListener::bind(addr).await?;
-        axum::serve(listener, app).await?;
+        let internal = async {
+            axum::serve(listener, routes::router(state.clone())).await?;
+            Ok::<_, anyhow::Error>(())
+        };
+        let external = async {
+            axum_server::bind_rustls(tls_addr, tls)
+                .serve(routes::api_router(state.clone()).into_make_service())
+                .await?;
+            Ok::<_, anyhow::Error>(())
+        };
+
+        tokio::select! {
+            result = async { tokio::try_join!(internal, external) } => { result?; }
+            _ = tokio::signal::ctrl_c() => info!("Shutting down sim-agent"),
+        }
+
+        // Don't leave containers behind once the scratch dir is gone.
+        if state.workload.lock().unwrap().as_ref().is_some_and(|w| w.running) {
+            workload::compose_down(&state.workload_dir())?;
+        }
         Ok(())
     })
 }
+
+/// Record or replay mode: only the management API is served, either proxied
+/// to a real agent or answered from a recording.
+fn run_recording(opts: &Options, token: &str) -> Result<()> {
+    let app = match (&opts.record, &opts.replay) {
+        (Some(target), _) => recording::record_router(target, &opts.recording)?,
+        (None, Some(path)) => recording::replay_router(path)?,
+        (None, None) => unreachable!(),
+    };
+
+    let rt = tokio::runtime::Runtime::new()?;
+    rt.block_on(async {
+        let tls = self_signed_tls().await?;
+        let tls_addr = std::net::SocketAddr::from(([0, 0, 0, 0], opts.tls_port));
+        info!(addr = %tls_addr, "sim-agent management API listening (TLS)");
+        if opts.record.is_some() {
+            // Requests are forwarded as-is, so clients must present the real agent's token.
+            println!("Forwarding requests; use the real agent's API token");
+        } else {
+            println!("API token: {} (not checked in replay mode)", token);
+        }
+
+        tokio::select! {
+            result = axum_server::bind_rustls(tls_addr, tls).serve(app.into_make_service()) => { result?; }
+            _ = tokio::signal::ctrl_c() => info!("Shutting down sim-agent"),
+        }
+        Ok(())
+    })
+}
+
+/// Generate a throwaway self-signed certificate for localhost.
+async fn self_signed_tls() -> Result<RustlsConfig> {
+    let cert = rcgen::generate_simple_self_signed(vec![
+        "localhost".to_string(),
+        SIM_IP.to_string(),
+    ])
+    .context("Failed to generate self-signed certificate")?;
+
+    RustlsConfig::from_pem(
+        cert.cert.pem().into_bytes(),
+        cert.key_pair.serialize_pem().into_bytes(),
+    )
+    .await
+    .context("Failed to load TLS config")
+}
+
+/// Write deployment state pointing at the sim-agent, so `update`, `logs` and
+/// `measurements` can be run against it with the same config.
+fn register_state(config: &Config, token: &str) -> Result<()> {
+    if let Ok(existing) = DeployState::load(&config.vm_name) {
+        if existing.ip.as_deref() != Some(SIM_IP) {
+            bail!(
+                "Deployment state for '{}' points at {}; refusing to overwrite it with the sim-agent",
+                config.vm_name,
+                existing.ip.as_deref().unwrap_or("an unknown IP")
+            );
+        }
+    }
+
+    let mut state = DeployState::from_config(config);
+    state.ip = Some(SIM_IP.to_string());
+    state.api_token = Some(token.to_string());
+    // No cloud resources belong to the sim-agent
+    state.bucket = None;
+    state.disk_name = None;
+    state.static_ip_name = None;
+    state.save()?;
+
+    info!(vm_name = %config.vm_name, "Registered sim-agent in deployment state");
+    Ok(())
+}
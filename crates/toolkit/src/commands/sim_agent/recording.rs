@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use base64::Engine;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+const REDACTED: &str = "<redacted>";
+
+/// Request/response pairs captured from a real CVM agent.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Recording {
+    pub target: String,
+    pub recorded_at: String,
+    pub exchanges: Vec<Exchange>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Exchange {
+    pub method: String,
+    /// Path including the query string.
+    pub path: String,
+    /// Scrubbed `Authorization` header, if the client sent one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authorization: Option<String>,
+    pub request: RecordedBody,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    pub response: RecordedBody,
+    pub duration_ms: u64,
+}
+
+/// A recorded body. Binary request bodies (e.g. workload zips) are kept as a digest only.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RecordedBody {
+    Empty,
+    Text { text: String },
+    Base64 { data: String },
+    Digest { size: usize, sha256: String },
+}
+
+impl RecordedBody {
+    fn capture(bytes: &[u8], keep_binary: bool, token: Option<&str>) -> Self {
+        if bytes.is_empty() {
+            return RecordedBody::Empty;
+        }
+        match std::str::from_utf8(bytes) {
+            Ok(text) => RecordedBody::Text { text: scrub(text, token) },
+            Err(_) if keep_binary => RecordedBody::Base64 {
+                data: base64::engine::general_purpose::STANDARD.encode(bytes),
+            },
+            Err(_) => RecordedBody::Digest {
+                size: bytes.len(),
+                sha256: hex::encode(Sha256::digest(bytes)),
+            },
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            RecordedBody::Empty | RecordedBody::Digest { .. } => Vec::new(),
+            RecordedBody::Text { text } => text.clone().into_bytes(),
+            RecordedBody::Base64 { data } => base64::engine::general_purpose::STANDARD
+                .decode(data)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+fn scrub(text: &str, token: Option<&str>) -> String {
+    match token {
+        Some(token) if !token.is_empty() => text.replace(token, REDACTED),
+        _ => text.to_string(),
+    }
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read recording: {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse recording: {}", path.display()))
+    }
+
+    /// Write the recording owner-only: response bodies hold container logs and
+    /// attestation evidence.
+    fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)
+            .with_context(|| format!("Failed to write recording: {}", path.display()))?;
+        // `mode` only applies to new files
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        file.write_all(content.as_bytes())
+            .with_context(|| format!("Failed to write recording: {}", path.display()))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Record: proxy to a real agent
+// ---------------------------------------------------------------------------
+
+struct Recorder {
+    target: String,
+    path: PathBuf,
+    client: reqwest::Client,
+    recording: Mutex<Recording>,
+}
+
+/// Router proxying every request to the agent at `target` (port 8000) and
+/// appending the exchange to the recording at `path`.
+pub fn record_router(target: &str, path: &Path) -> Result<Router> {
+    let client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_secs(120))
+        .build()
+        .context("Failed to create HTTP client")?;
+
+    let recording = Recording {
+        target: target.to_string(),
+        recorded_at: Utc::now().to_rfc3339(),
+        exchanges: Vec::new(),
+    };
+    recording.save(path)?;
+    info!(target, path = %path.display(), "Recording agent traffic");
+
+    let recorder = Arc::new(Recorder {
+        target: target.to_string(),
+        path: path.to_path_buf(),
+        client,
+        recording: Mutex::new(recording),
+    });
+
+    Ok(Router::new().fallback(proxy_handler).with_state(recorder))
+}
+
+async fn proxy_handler(State(recorder): State<Arc<Recorder>>, req: Request) -> Response {
+    let (parts, body) = req.into_parts();
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    let path = parts.uri.path_and_query().map_or("/", |p| p.as_str()).to_string();
+    let url = format!("https://{}:8000{}", recorder.target, path);
+
+    let mut upstream = recorder.client.request(parts.method.clone(), &url).body(body.clone());
+    for (name, value) in &parts.headers {
+        if name != header::HOST && name != header::CONTENT_LENGTH {
+            upstream = upstream.header(name, value);
+        }
+    }
+
+    let started = Instant::now();
+    let resp = match upstream.send().await {
+        Ok(resp) => resp,
+        Err(e) => {
+            warn!(error = %e, url, "Upstream request failed (not recorded)");
+            return (StatusCode::BAD_GATEWAY, format!("Upstream request failed: {}", e)).into_response();
+        }
+    };
+    let status = resp.status();
+    let content_type = resp.headers().get(header::CONTENT_TYPE).cloned();
+    let resp_body = match resp.bytes().await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return (StatusCode::BAD_GATEWAY, format!("Upstream body failed: {}", e)).into_response();
+        }
+    };
+    let duration_ms = started.elapsed().as_millis() as u64;
+
+    let token = parts
+        .headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim_start_matches("Bearer ").to_string());
+
+    let exchange = Exchange {
+        method: parts.method.to_string(),
+        path: scrub(&path, token.as_deref()),
+        authorization: token.as_ref().map(|_| format!("Bearer {}", REDACTED)),
+        request: RecordedBody::capture(&body, false, token.as_deref()),
+        status: status.as_u16(),
+        content_type: content_type.as_ref().and_then(|v| v.to_str().ok()).map(String::from),
+        response: RecordedBody::capture(&resp_body, true, token.as_deref()),
+        duration_ms,
+    };
+    info!(method = %exchange.method, path = %exchange.path, status = exchange.status, "Recorded exchange");
+
+    // Saving rewrites the file, so keep it off the async workers. The lock
+    // is held while writing so saves land in order.
+    let saver = recorder.clone();
+    let saved = tokio::task::spawn_blocking(move || {
+        let mut recording = saver.recording.lock().unwrap();
+        recording.exchanges.push(exchange);
+        recording.save(&saver.path)
+    })
+    .await;
+    match saved {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => warn!(error = %e, "Failed to save recording"),
+        Err(e) => warn!(error = %e, "Failed to save recording"),
+    }
+
+    let mut response = Response::new(Body::from(resp_body));
+    *response.status_mut() = status;
+    if let Some(content_type) = content_type {
+        response.headers_mut().insert(header::CONTENT_TYPE, content_type);
+    }
+    response
+}
+
+// ---------------------------------------------------------------------------
+// Replay: serve a recording back
+// ---------------------------------------------------------------------------
+
+struct Replayer {
+    exchanges: Vec<Exchange>,
+    /// Next index per (method, path), so repeated requests step through the recording.
+    cursors: Mutex<HashMap<(String, String), usize>>,
+}
+
+/// Router answering requests from a recording. Exchanges are matched by method
+/// and path (query included, then without); repeated requests replay the
+/// recorded responses in order, repeating the last one.
+pub fn replay_router(path: &Path) -> Result<Router> {
+    let recording = Recording::load(path)?;
+    info!(
+        path = %path.display(),
+        target = %recording.target,
+        exchanges = recording.exchanges.len(),
+        "Replaying recorded agent traffic"
+    );
+
+    let replayer = Arc::new(Replayer {
+        exchanges: recording.exchanges,
+        cursors: Mutex::new(HashMap::new()),
+    });
+
+    Ok(Router::new().fallback(replay_handler).with_state(replayer))
+}
+
+async fn replay_handler(State(replayer): State<Arc<Replayer>>, req: Request) -> Response {
+    let method = req.method().to_string();
+    let full_path = req.uri().path_and_query().map_or("/", |p| p.as_str()).to_string();
+    let bare_path = req.uri().path().to_string();
+
+    let Some(exchange) = replayer
+        .next(&method, &full_path)
+        .or_else(|| replayer.next(&method, &bare_path))
+    else {
+        warn!(method, path = %full_path, "No recorded exchange");
+        return (
+            StatusCode::NOT_FOUND,
+            format!("No recorded exchange for {} {}", method, full_path),
+        )
+            .into_response();
+    };
+
+    let mut response = Response::new(Body::from(exchange.response.to_bytes()));
+    *response.status_mut() = StatusCode::from_u16(exchange.status).unwrap_or(StatusCode::OK);
+    if let Some(value) = exchange.content_type.as_deref().and_then(|v| HeaderValue::from_str(v).ok()) {
+        response.headers_mut().insert(header::CONTENT_TYPE, value);
+    }
+    response
+}
+
+impl Replayer {
+    fn next(&self, method: &str, path: &str) -> Option<Exchange> {
+        let matches: Vec<&Exchange> = self
+            .exchanges
+            .iter()
+            .filter(|e| e.method == method && (e.path == path || e.path.split('?').next() == Some(path)))
+            .collect();
+        if matches.is_empty() {
+            return None;
+        }
+
+        let mut cursors = self.cursors.lock().unwrap();
+        let cursor = cursors.entry((method.to_string(), path.to_string())).or_insert(0);
+        let exchange = matches[(*cursor).min(matches.len() - 1)].clone();
+        *cursor += 1;
+        Some(exchange)
+    }
+}
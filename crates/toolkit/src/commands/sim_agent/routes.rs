@@ -1,13 +1,139 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::{Body, Bytes};
+use axum::extract::{DefaultBodyLimit, Multipart, Query, RawQuery, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
 use axum::routing::{get, post};
 use axum::{Json, Router};
+use serde::Deserialize;
 use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use super::faults::Fault;
+use super::session::Session;
+use super::state::{SimState, UploadedWorkload};
+use super::{tee, workload};
+
+type AppState = Arc<SimState>;
 
-pub fn router() -> Router {
+/// Internal agent API (plain HTTP, reachable only from inside the CVM).
+/// Also hosts the `/_sim/*` control API, which is never subject to faults.
+pub fn router(state: AppState) -> Router {
     Router::new()
         .route("/health", get(health_handler))
         .route("/sign", post(sign_handler))
         .route("/session", get(session_handler))
+        .route("/session/rotate", post(rotate_session_handler))
         .route("/attestation", get(attestation_handler))
+        .route("/attestation/ca", get(attestation_ca_handler))
+        .layer(middleware::from_fn_with_state(state.clone(), inject_faults))
+        .route("/_sim/faults", get(list_faults_handler).post(set_fault_handler).delete(clear_faults_handler))
+        .route("/_sim/boot-delay", post(boot_delay_handler))
+        .with_state(state)
+}
+
+/// External management API (HTTPS on port 8000, bearer token required).
+pub fn api_router(state: AppState) -> Router {
+    let protected = Router::new()
+        .route("/update-workload", post(update_workload_handler))
+        .route("/container-logs", get(container_logs_handler))
+        .route("/livepatch", post(livepatch_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token));
+
+    Router::new()
+        .route("/health", get(health_handler))
+        .route("/offchain/golden-measurement", get(offchain_measurement_handler))
+        .route("/onchain/golden-measurement", get(onchain_measurement_handler))
+        .route("/sealing-key", get(sealing_key_handler))
+        .merge(protected)
+        .layer(middleware::from_fn_with_state(state.clone(), inject_faults))
+        // Workload zips with image tars easily exceed axum's 2 MB default.
+        .layer(DefaultBodyLimit::disable())
+        .with_state(state)
+}
+
+/// Apply the simulated boot delay and any fault configured for the request path.
+async fn inject_faults(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if let Some(remaining) = state.faults.not_ready_for() {
+        return error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            &format!("Agent not ready ({}s until boot completes)", remaining.as_secs() + 1),
+        );
+    }
+
+    let Some(fault) = state.faults.take(req.uri().path()) else {
+        return next.run(req).await;
+    };
+    info!(path = %req.uri().path(), ?fault, "Injecting fault");
+
+    if let Some(ms) = fault.delay_ms {
+        tokio::time::sleep(Duration::from_millis(ms)).await;
+    }
+
+    if fault.reset {
+        // A body that fails before its first frame makes hyper close the
+        // connection without flushing the response head.
+        let reset = [Err::<Bytes, _>(std::io::Error::new(
+            std::io::ErrorKind::ConnectionReset,
+            "injected connection reset",
+        ))];
+        return Response::new(Body::from_stream(futures_util::stream::iter(reset)));
+    }
+
+    let response = match fault.status {
+        Some(code) => {
+            let status = StatusCode::from_u16(code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            error_response(status, "Injected fault")
+        }
+        None => next.run(req).await,
+    };
+
+    match fault.truncate {
+        Some(limit) => truncate_body(response, limit).await,
+        None => response,
+    }
+}
+
+/// Send only the first `limit` bytes of the body while advertising the full length,
+/// then abort the connection, like a CVM dropping mid-response.
+async fn truncate_body(response: Response, limit: usize) -> Response {
+    let (mut parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    };
+    if bytes.len() <= limit {
+        return Response::from_parts(parts, Body::from(bytes));
+    }
+
+    parts.headers.insert(header::CONTENT_LENGTH, bytes.len().into());
+    let chunks = [
+        Ok(bytes.slice(..limit)),
+        Err(std::io::Error::new(std::io::ErrorKind::ConnectionReset, "injected truncation")),
+    ];
+    Response::from_parts(parts, Body::from_stream(futures_util::stream::iter(chunks)))
+}
+
+/// Reject requests without a valid `Authorization: Bearer <token>` header.
+async fn require_token(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    match token {
+        Some(token) if state.verify_token(token) => next.run(req).await,
+        _ => error_response(StatusCode::UNAUTHORIZED, "Invalid or missing API token"),
+    }
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "status": "error", "message": message }))).into_response()
 }
 
 async fn health_handler() -> Json<Value> {
@@ -17,33 +143,334 @@ async fn health_handler() -> Json<Value> {
     }))
 }
 
-async fn sign_handler(Json(body): Json<Value>) -> Json<Value> {
+async fn sign_handler(State(state): State<AppState>, Json(body): Json<Value>) -> Response {
     let message = body
         .get("message")
         .and_then(|v| v.as_str())
         .unwrap_or("0x");
 
-    // Generate a deterministic mock signature from the message.
+    // Hex messages ("0x...") are signed as raw bytes, anything else as UTF-8 text.
+    let bytes = match message.strip_prefix("0x") {
+        Some(h) => match hex::decode(h) {
+            Ok(bytes) => bytes,
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, &format!("Invalid hex message: {}", e)),
+        },
+        None => message.as_bytes().to_vec(),
+    };
+
+    let session = state.session();
+    let (signature, digest) = match session.sign(&bytes) {
+        Ok(signed) => signed,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, &format!("{:#}", e)),
+    };
+
     Json(json!({
-        "session_id": "0x00000000000000000000000000000000000000000000000000000000deadbeef",
-        "signature": format!("0x{}", "ab".repeat(65)),
-        "session_public_key": format!("0x{}", "cd".repeat(33)),
-        "message": message
+        "session_id": format!("0x{}", hex::encode(session.id)),
+        "signature": format!("0x{}", hex::encode(signature)),
+        "session_public_key": format!("0x{}", hex::encode(session.public_key())),
+        "address": format!("0x{}", hex::encode(session.address())),
+        "message": message,
+        "message_hash": format!("0x{}", hex::encode(digest))
     }))
+    .into_response()
+}
+
+async fn session_handler(State(state): State<AppState>) -> Json<Value> {
+    session_json(&state.session())
+}
+
+async fn rotate_session_handler(State(state): State<AppState>) -> Json<Value> {
+    session_json(&state.rotate_session())
 }
 
-async fn session_handler() -> Json<Value> {
+fn session_json(session: &Session) -> Json<Value> {
     Json(json!({
-        "session_id": "0x00000000000000000000000000000000000000000000000000000000deadbeef",
-        "is_active": true,
-        "expires_at": "2099-12-31T23:59:59Z"
+        "session_id": format!("0x{}", hex::encode(session.id)),
+        "session_public_key": format!("0x{}", hex::encode(session.public_key())),
+        "address": format!("0x{}", hex::encode(session.address())),
+        "is_active": !session.is_expired(),
+        "created_at": session.created_at.to_rfc3339(),
+        "expires_at": session.expires_at.to_rfc3339()
     }))
 }
 
-async fn attestation_handler() -> Json<Value> {
+#[derive(Deserialize)]
+struct AttestationQuery {
+    /// Caller nonce (hex) placed in the second half of the report data.
+    nonce: Option<String>,
+}
+
+async fn attestation_handler(
+    State(state): State<AppState>,
+    Query(query): Query<AttestationQuery>,
+) -> Response {
+    let public_key = state.session().public_key();
+    let report_data = match tee::report_data(&public_key, query.nonce.as_deref()) {
+        Ok(data) => data,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, &format!("{:#}", e)),
+    };
+
+    let measurement = state.workload_measurement();
+    match state.tee.evidence(&report_data, measurement.as_ref()) {
+        Ok(mut evidence) => {
+            evidence["session_public_key"] = json!(format!("0x{}", hex::encode(public_key)));
+            evidence["measurements"] = state.tee.measurements(measurement.as_ref());
+            Json(evidence).into_response()
+        }
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &format!("{:#}", e)),
+    }
+}
+
+/// The key workload secrets are sealed to, with evidence binding it
+/// (report data = SHA-256(key) || nonce).
+async fn sealing_key_handler(
+    State(state): State<AppState>,
+    Query(query): Query<AttestationQuery>,
+) -> Response {
+    let public_key = state.sealing.public_key();
+    let report_data = match tee::report_data(&public_key, query.nonce.as_deref()) {
+        Ok(data) => data,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, &format!("{:#}", e)),
+    };
+
+    let measurement = state.workload_measurement();
+    match state.tee.evidence(&report_data, measurement.as_ref()) {
+        Ok(evidence) => Json(json!({
+            "public_key": format!("0x{}", hex::encode(public_key)),
+            "attestation": evidence,
+            "measurements": state.tee.measurements(measurement.as_ref())
+        }))
+        .into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &format!("{:#}", e)),
+    }
+}
+
+async fn attestation_ca_handler(State(state): State<AppState>) -> Response {
+    (
+        [(header::CONTENT_TYPE, "application/x-pem-file")],
+        state.tee.ca_pem().to_string(),
+    )
+        .into_response()
+}
+
+async fn update_workload_handler(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Response {
+    let mut data = None;
+    loop {
+        match multipart.next_field().await {
+            Ok(Some(field)) if field.name() == Some("file") => match field.bytes().await {
+                Ok(bytes) => data = Some(bytes),
+                Err(e) => return error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+            },
+            Ok(Some(_)) => continue,
+            Ok(None) => break,
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+        }
+    }
+
+    let Some(data) = data else {
+        return error_response(StatusCode::BAD_REQUEST, "Missing 'file' field");
+    };
+
+    let result = tokio::task::spawn_blocking(move || install_workload(&state, &data)).await;
+    match result {
+        Ok(Ok(workload)) => Json(json!({
+            "status": "ok",
+            "message": "Workload updated",
+            "sha256": workload.sha256,
+            "running": workload.running
+        }))
+        .into_response(),
+        Ok(Err((status, message))) => error_response(status, &message),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    }
+}
+
+/// Unpack, validate and (optionally) start an uploaded workload zip.
+/// Holds the workload lock throughout so concurrent uploads are serialized.
+fn install_workload(
+    state: &SimState,
+    data: &[u8],
+) -> Result<UploadedWorkload, (StatusCode, String)> {
+    let mut current = state.workload.lock().unwrap();
+    let dir = state.workload_dir();
+
+    if current.as_ref().is_some_and(|w| w.running) {
+        if let Err(e) = workload::compose_down(&dir) {
+            warn!(error = %e, "Failed to stop previous workload");
+        }
+    }
+    *current = None;
+
+    let bad_request = |e: anyhow::Error| (StatusCode::BAD_REQUEST, format!("{:#}", e));
+    let mut files = workload::unpack(data, &dir).map_err(bad_request)?;
+    let unsealed = state.sealing.unseal(&dir).map_err(bad_request)?;
+    if !unsealed.is_empty() {
+        info!(files = unsealed.len(), "Unsealed workload secrets");
+        files = crate::workload::measure::files(&dir).map_err(bad_request)?;
+    }
+    let containers = workload::validate(&dir).map_err(bad_request)?;
+    let measurement = crate::workload::measure::measure(&dir, &files).map_err(bad_request)?;
+
+    let running = if state.run_workload {
+        workload::compose_up(&dir)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)))?;
+        true
+    } else {
+        false
+    };
+
+    let uploaded = UploadedWorkload {
+        sha256: hex::encode(Sha256::digest(data)),
+        size: data.len(),
+        files,
+        containers,
+        running,
+        measurement,
+    };
+    info!(
+        size = uploaded.size,
+        files = uploaded.files.len(),
+        sha256 = %uploaded.sha256,
+        running,
+        dir = %dir.display(),
+        "Workload installed"
+    );
+    *current = Some(uploaded.clone());
+    Ok(uploaded)
+}
+
+async fn container_logs_handler(
+    State(state): State<AppState>,
+    RawQuery(query): RawQuery,
+) -> Response {
+    let requested: Vec<String> = query
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|pair| pair.strip_prefix("name="))
+        .map(|name| urlencoding::decode(name).map(|s| s.into_owned()).unwrap_or_default())
+        .filter(|name| !name.is_empty())
+        .collect();
+
+    let result = tokio::task::spawn_blocking(move || collect_logs(&state, requested)).await;
+    match result {
+        Ok(Ok(logs)) => Json(logs).into_response(),
+        Ok(Err((status, message))) => error_response(status, &message),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    }
+}
+
+/// Gather logs for the requested containers (all workload containers if none given).
+fn collect_logs(state: &SimState, requested: Vec<String>) -> Result<Value, (StatusCode, String)> {
+    let current = state.workload.lock().unwrap();
+    let Some(workload) = current.as_ref() else {
+        return Err((StatusCode::NOT_FOUND, "No workload uploaded".to_string()));
+    };
+
+    let names = if requested.is_empty() {
+        workload.containers.iter().map(|c| c.name.clone()).collect()
+    } else {
+        requested
+    };
+
+    let mut logs = Vec::new();
+    for name in names {
+        let Some(container) = workload.containers.iter().find(|c| c.name == name) else {
+            return Err((StatusCode::NOT_FOUND, format!("Unknown container: {}", name)));
+        };
+        let log = if workload.running {
+            workload::service_logs(&state.workload_dir(), &container.service)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)))?
+        } else {
+            format!(
+                "sim-agent: workload {} unpacked, containers are not run (start with --run-workload)\n",
+                &workload.sha256[..12]
+            )
+        };
+        logs.push(json!({ "name": name, "log": log }));
+    }
+
+    Ok(Value::Array(logs))
+}
+
+async fn livepatch_handler(State(state): State<AppState>, body: Bytes) -> Response {
+    if body.is_empty() {
+        return error_response(StatusCode::BAD_REQUEST, "Empty livepatch");
+    }
+
+    let mut count = state.livepatches.lock().unwrap();
+    *count += 1;
+    info!(size = body.len(), count = *count, "Livepatch received");
+
+    Json(json!({ "status": "ok", "message": "Livepatch applied" })).into_response()
+}
+
+async fn offchain_measurement_handler(State(state): State<AppState>) -> Json<Value> {
+    let measurement = state.workload_measurement();
     Json(json!({
-        "attestation_report": "mock-attestation-base64-data",
-        "platform": "sim",
-        "tdx_version": "simulated"
+        "platform": state.tee.platform.as_str(),
+        "workload_measurement": measurement.map(hex::encode),
+        "measurements": state.tee.measurements(measurement.as_ref())
     }))
 }
+
+async fn onchain_measurement_handler(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({
+        "platform": state.tee.platform.as_str(),
+        "measurements": state.tee.measurements(state.workload_measurement().as_ref())
+    }))
+}
+
+#[derive(Deserialize)]
+struct SetFaultRequest {
+    route: String,
+    #[serde(flatten)]
+    fault: Fault,
+}
+
+#[derive(Deserialize)]
+struct ClearFaultsQuery {
+    route: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BootDelayRequest {
+    seconds: u64,
+}
+
+async fn list_faults_handler(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({
+        "rules": state.faults.rules(),
+        "not_ready_for_secs": state.faults.not_ready_for().map(|d| d.as_secs_f64())
+    }))
+}
+
+async fn set_fault_handler(
+    State(state): State<AppState>,
+    Json(req): Json<SetFaultRequest>,
+) -> Json<Value> {
+    info!(route = %req.route, fault = ?req.fault, "Fault configured");
+    state.faults.set(req.route, req.fault);
+    Json(json!({ "status": "ok" }))
+}
+
+async fn clear_faults_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ClearFaultsQuery>,
+) -> Json<Value> {
+    state.faults.clear(query.route.as_deref());
+    Json(json!({ "status": "ok" }))
+}
+
+async fn boot_delay_handler(
+    State(state): State<AppState>,
+    Json(req): Json<BootDelayRequest>,
+) -> Response {
+    if let Err(e) = state.faults.set_boot_delay(Duration::from_secs(req.seconds)) {
+        return error_response(StatusCode::BAD_REQUEST, &format!("{:#}", e));
+    }
+    info!(seconds = req.seconds, "Simulating boot delay");
+    Json(json!({ "status": "ok" })).into_response()
+}
@@ -0,0 +1,59 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use k256::SecretKey;
+use rand::rngs::OsRng;
+use tracing::info;
+
+const SEALING_KEY_FILE: &str = "sealing.key";
+
+/// Key workload secrets are sealed to, standing in for the key the real agent
+/// derives inside the TEE. Persisted so artifacts built with a fetched key
+/// still unseal after sim-agent restarts.
+pub struct SealingKey {
+    key: SecretKey,
+}
+
+impl SealingKey {
+    /// Load the key from `dir`, creating it on first use.
+    pub fn load_or_create(dir: &Path) -> Result<Self> {
+        let path = dir.join(SEALING_KEY_FILE);
+
+        if path.exists() {
+            let bytes = hex::decode(fs::read_to_string(&path)?.trim())
+                .with_context(|| format!("Invalid sealing key: {}", path.display()))?;
+            let key = SecretKey::from_slice(&bytes)
+                .with_context(|| format!("Invalid sealing key: {}", path.display()))?;
+            info!(path = %path.display(), "Loaded sim-agent sealing key");
+            return Ok(Self { key });
+        }
+
+        let key = SecretKey::random(&mut OsRng);
+        fs::create_dir_all(dir)?;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        file.write_all(hex::encode(key.to_bytes()).as_bytes())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        info!(path = %path.display(), "Created sim-agent sealing key");
+        Ok(Self { key })
+    }
+
+    /// Compressed SEC1 public key (33 bytes).
+    pub fn public_key(&self) -> Vec<u8> {
+        self.key.public_key().to_encoded_point(true).as_bytes().to_vec()
+    }
+
+    /// Unseal the `.sealed` files of an unpacked workload in place.
+    pub fn unseal(&self, dir: &Path) -> Result<Vec<String>> {
+        crate::workload::seal::unseal(dir, &self.key)
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use k256::ecdsa::SigningKey;
+use rand::rngs::OsRng;
+use sha3::{Digest, Keccak256};
+
+/// Ephemeral signing session, like the session key the real agent keeps in TEE memory.
+pub struct Session {
+    pub id: [u8; 32],
+    key: SigningKey,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Session {
+    /// Start a new session with a fresh secp256k1 key. A TTL past the end of
+    /// time never expires.
+    pub fn new(ttl: Duration) -> Self {
+        let created_at = Utc::now();
+        Self {
+            id: rand::random(),
+            key: SigningKey::random(&mut OsRng),
+            created_at,
+            expires_at: created_at.checked_add_signed(ttl).unwrap_or(DateTime::<Utc>::MAX_UTC),
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        Utc::now() >= self.expires_at
+    }
+
+    /// Compressed SEC1 public key (33 bytes).
+    pub fn public_key(&self) -> Vec<u8> {
+        self.key.verifying_key().to_encoded_point(true).as_bytes().to_vec()
+    }
+
+    /// Ethereum address of the session key.
+    pub fn address(&self) -> [u8; 20] {
+        let point = self.key.verifying_key().to_encoded_point(false);
+        let hash = Keccak256::digest(&point.as_bytes()[1..]);
+        let mut address = [0u8; 20];
+        address.copy_from_slice(&hash[12..]);
+        address
+    }
+
+    /// Sign `message` as an EIP-191 personal message.
+    /// Returns the 65-byte `r || s || v` signature (v = 27/28) and the signed digest.
+    pub fn sign(&self, message: &[u8]) -> Result<([u8; 65], [u8; 32])> {
+        let digest: [u8; 32] = personal_message_hash(message);
+        let (signature, recovery_id) = self
+            .key
+            .sign_prehash_recoverable(&digest)
+            .context("Failed to sign message")?;
+
+        let mut out = [0u8; 65];
+        out[..64].copy_from_slice(&signature.to_bytes());
+        out[64] = 27 + recovery_id.to_byte();
+        Ok((out, digest))
+    }
+}
+
+/// keccak256("\x19Ethereum Signed Message:\n" || len(message) || message)
+fn personal_message_hash(message: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(format!("\x19Ethereum Signed Message:\n{}", message.len()).as_bytes());
+    hasher.update(message);
+    hasher.finalize().into()
+}
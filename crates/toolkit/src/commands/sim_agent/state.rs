@@ -0,0 +1,108 @@
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use tempfile::TempDir;
+use tracing::info;
+
+use super::faults::FaultTable;
+use super::sealing::SealingKey;
+use super::session::Session;
+use super::tee::SimTee;
+use super::workload::Container;
+
+/// Shared state for the simulated CVM agent.
+pub struct SimState {
+    /// SHA-256 of the API token (hex), mirroring `token_hash` on the data partition.
+    token_hash: String,
+    /// Scratch directory holding the unpacked workload.
+    scratch: TempDir,
+    /// Run uploaded workloads with `docker compose up`.
+    pub run_workload: bool,
+    /// Last workload uploaded via `/update-workload`.
+    pub workload: Mutex<Option<UploadedWorkload>>,
+    /// Number of livepatches received via `/livepatch`.
+    pub livepatches: Mutex<u32>,
+    /// Current signing session for `/sign`.
+    session: Mutex<Session>,
+    /// Lifetime of a signing session before it is rotated.
+    session_ttl: chrono::Duration,
+    /// Injected faults and simulated boot delay.
+    pub faults: FaultTable,
+    /// Fake TEE producing attestation evidence.
+    pub tee: SimTee,
+    /// Key uploaded workload secrets are unsealed with.
+    pub sealing: SealingKey,
+}
+
+/// Summary of an uploaded workload zip.
+#[derive(Debug, Clone)]
+pub struct UploadedWorkload {
+    pub sha256: String,
+    pub size: usize,
+    pub files: Vec<String>,
+    /// Containers declared in the workload's docker-compose.yml.
+    pub containers: Vec<Container>,
+    /// Whether the workload was started with `docker compose up`.
+    pub running: bool,
+    /// Workload measurement extended into RTMR3 / SNP host data.
+    pub measurement: [u8; 48],
+}
+
+impl SimState {
+    pub fn new(
+        token: &str,
+        run_workload: bool,
+        session_ttl: chrono::Duration,
+        faults: FaultTable,
+        tee: SimTee,
+        sealing: SealingKey,
+    ) -> Result<Self> {
+        Ok(Self {
+            token_hash: hex::encode(Sha256::digest(token.as_bytes())),
+            scratch: TempDir::new().context("Failed to create sim-agent scratch directory")?,
+            run_workload,
+            workload: Mutex::new(None),
+            livepatches: Mutex::new(0),
+            session: Mutex::new(Session::new(session_ttl)),
+            session_ttl,
+            faults,
+            tee,
+            sealing,
+        })
+    }
+
+    /// Current signing session, replaced with a fresh one if it has expired.
+    pub fn session(&self) -> MutexGuard<'_, Session> {
+        let mut session = self.session.lock().unwrap();
+        if session.is_expired() {
+            *session = Session::new(self.session_ttl);
+            info!(session_id = %hex::encode(session.id), "Session expired, rotated signing key");
+        }
+        session
+    }
+
+    /// Discard the current session and start a new one.
+    pub fn rotate_session(&self) -> MutexGuard<'_, Session> {
+        let mut session = self.session.lock().unwrap();
+        *session = Session::new(self.session_ttl);
+        info!(session_id = %hex::encode(session.id), "Rotated signing session");
+        session
+    }
+
+    /// Directory the uploaded workload is unpacked into.
+    pub fn workload_dir(&self) -> PathBuf {
+        self.scratch.path().join("workload")
+    }
+
+    /// Measurement of the current workload, if one was uploaded.
+    pub fn workload_measurement(&self) -> Option<[u8; 48]> {
+        self.workload.lock().unwrap().as_ref().map(|w| w.measurement)
+    }
+
+    /// Check a bearer token against the stored hash.
+    pub fn verify_token(&self, token: &str) -> bool {
+        hex::encode(Sha256::digest(token.as_bytes())) == self.token_hash
+    }
+}
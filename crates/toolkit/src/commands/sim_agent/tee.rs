@@ -0,0 +1,338 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use ring::rand::SystemRandom;
+use ring::signature::{
+    EcdsaKeyPair, KeyPair as _, ECDSA_P256_SHA256_FIXED_SIGNING, ECDSA_P384_SHA384_FIXED_SIGNING,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256, Sha384};
+
+use crate::workload::measure::{host_data, rtmr3};
+
+use super::ca::TestCa;
+
+/// Intel's QE vendor ID, as found in every DCAP quote header.
+const INTEL_QE_VENDOR_ID: [u8; 16] = [
+    0x93, 0x9a, 0x72, 0x33, 0xf7, 0x9b, 0x4c, 0xa9, 0x94, 0x0a, 0x0d, 0xb3, 0x95, 0x7f, 0x06, 0x07,
+];
+
+/// Size of the signed part of an SNP attestation report.
+const SNP_SIGNED_LEN: usize = 0x2a0;
+
+/// Simulated TEE platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Platform {
+    Tdx,
+    Snp,
+}
+
+impl Platform {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Platform::Tdx => "tdx",
+            Platform::Snp => "snp",
+        }
+    }
+}
+
+/// Firmware values reported in evidence (hex strings). Omitted values get
+/// deterministic defaults so golden measurements are stable between runs.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FirmwareConfig {
+    // TDX
+    pub mrseam: Option<String>,
+    pub mrtd: Option<String>,
+    pub rtmr0: Option<String>,
+    pub rtmr1: Option<String>,
+    pub rtmr2: Option<String>,
+    pub tee_tcb_svn: Option<String>,
+    // SEV-SNP
+    pub measurement: Option<String>,
+    pub chip_id: Option<String>,
+    pub policy: Option<u64>,
+    pub tcb_version: Option<u64>,
+}
+
+impl FirmwareConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read firmware config: {}", path.display()))?;
+        serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse firmware config: {}", path.display()))
+    }
+}
+
+/// Resolved firmware values.
+struct Firmware {
+    mrseam: [u8; 48],
+    mrtd: [u8; 48],
+    rtmr: [[u8; 48]; 3],
+    tee_tcb_svn: [u8; 16],
+    measurement: [u8; 48],
+    chip_id: [u8; 64],
+    policy: u64,
+    tcb_version: u64,
+}
+
+impl Firmware {
+    fn resolve(cfg: &FirmwareConfig) -> Result<Self> {
+        Ok(Self {
+            mrseam: hex_or_default(&cfg.mrseam, "mrseam")?,
+            mrtd: hex_or_default(&cfg.mrtd, "mrtd")?,
+            rtmr: [
+                hex_or_default(&cfg.rtmr0, "rtmr0")?,
+                hex_or_default(&cfg.rtmr1, "rtmr1")?,
+                hex_or_default(&cfg.rtmr2, "rtmr2")?,
+            ],
+            tee_tcb_svn: hex_or_default(&cfg.tee_tcb_svn, "tee_tcb_svn")?,
+            measurement: hex_or_default(&cfg.measurement, "measurement")?,
+            chip_id: hex_or_default(&cfg.chip_id, "chip_id")?,
+            // SMT allowed, ABI 0.0 (the usual GCP guest policy)
+            policy: cfg.policy.unwrap_or(0x30000),
+            tcb_version: cfg.tcb_version.unwrap_or(0),
+        })
+    }
+}
+
+/// Parse a fixed-size hex field, or derive a stable default from its name.
+fn hex_or_default<const N: usize>(value: &Option<String>, field: &str) -> Result<[u8; N]> {
+    match value {
+        Some(s) => {
+            let bytes = hex::decode(s.trim_start_matches("0x"))
+                .with_context(|| format!("Firmware value '{}' is not hex", field))?;
+            bytes.try_into().map_err(|b: Vec<u8>| {
+                anyhow::anyhow!("Firmware value '{}' must be {} bytes, got {}", field, N, b.len())
+            })
+        }
+        None => {
+            let mut out = [0u8; N];
+            let mut seed = Sha384::digest(format!("sim-agent:{}", field)).to_vec();
+            while seed.len() < N {
+                seed.extend_from_slice(&Sha384::digest(&seed));
+            }
+            out.copy_from_slice(&seed[..N]);
+            Ok(out)
+        }
+    }
+}
+
+/// Fake TEE producing structurally valid evidence signed by the test CA chain.
+pub struct SimTee {
+    pub platform: Platform,
+    fw: Firmware,
+    rng: SystemRandom,
+    ca_pem: String,
+    /// TDX: quote signing (attestation) key and the PCK key endorsing it.
+    attestation_key: EcdsaKeyPair,
+    pck_key: EcdsaKeyPair,
+    pck_cert_pem: String,
+    /// SNP: versioned chip endorsement key.
+    vcek: EcdsaKeyPair,
+    vcek_cert_pem: String,
+}
+
+impl SimTee {
+    pub fn new(platform: Platform, firmware: &FirmwareConfig, ca_dir: &Path) -> Result<Self> {
+        let rng = SystemRandom::new();
+        let ca = TestCa::load_or_create(ca_dir)?;
+
+        let (attestation_key, _) = generate_key(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)?;
+        let (pck_key, pck_pkcs8) = generate_key(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)?;
+        let (vcek, vcek_pkcs8) = generate_key(&ECDSA_P384_SHA384_FIXED_SIGNING, &rng)?;
+
+        Ok(Self {
+            platform,
+            fw: Firmware::resolve(firmware)?,
+            pck_cert_pem: ca.issue("sim-agent PCK Certificate", &pck_pkcs8)?,
+            vcek_cert_pem: ca.issue("sim-agent SEV-VCEK", &vcek_pkcs8)?,
+            ca_pem: ca.pem,
+            rng,
+            attestation_key,
+            pck_key,
+            vcek,
+        })
+    }
+
+    /// PEM of the test CA that roots all evidence.
+    pub fn ca_pem(&self) -> &str {
+        &self.ca_pem
+    }
+
+    /// Attestation evidence binding `report_data`, with the workload measured in.
+    pub fn evidence(&self, report_data: &[u8; 64], workload: Option<&[u8; 48]>) -> Result<Value> {
+        let b64 = base64::engine::general_purpose::STANDARD;
+        match self.platform {
+            Platform::Tdx => Ok(json!({
+                "platform": "tdx",
+                "quote": b64.encode(self.tdx_quote(report_data, workload)?),
+                "report_data": hex::encode(report_data),
+                "cert_chain": format!("{}{}", self.pck_cert_pem, self.ca_pem),
+            })),
+            Platform::Snp => Ok(json!({
+                "platform": "snp",
+                "attestation_report": b64.encode(self.snp_report(report_data, workload)?),
+                "report_data": hex::encode(report_data),
+                "cert_chain": format!("{}{}", self.vcek_cert_pem, self.ca_pem),
+            })),
+        }
+    }
+
+    /// Golden measurements the evidence will carry for this workload.
+    pub fn measurements(&self, workload: Option<&[u8; 48]>) -> Value {
+        match self.platform {
+            Platform::Tdx => json!({
+                "mrseam": hex::encode(self.fw.mrseam),
+                "mrtd": hex::encode(self.fw.mrtd),
+                "rtmr0": hex::encode(self.fw.rtmr[0]),
+                "rtmr1": hex::encode(self.fw.rtmr[1]),
+                "rtmr2": hex::encode(self.fw.rtmr[2]),
+                "rtmr3": hex::encode(rtmr3(workload)),
+            }),
+            Platform::Snp => json!({
+                "measurement": hex::encode(self.fw.measurement),
+                "host_data": hex::encode(host_data(workload)),
+                "policy": format!("0x{:x}", self.fw.policy),
+            }),
+        }
+    }
+
+    /// Intel TDX DCAP quote v4 (header, TD report body, ECDSA-P256 signature data
+    /// with QE report certification data and a PEM chain).
+    fn tdx_quote(&self, report_data: &[u8; 64], workload: Option<&[u8; 48]>) -> Result<Vec<u8>> {
+        let mut quote = Vec::with_capacity(1024);
+
+        // Header (48 bytes)
+        quote.extend_from_slice(&4u16.to_le_bytes()); // version
+        quote.extend_from_slice(&2u16.to_le_bytes()); // attestation key type: ECDSA-256-with-P-256
+        quote.extend_from_slice(&0x81u32.to_le_bytes()); // TEE type: TDX
+        quote.extend_from_slice(&[0u8; 4]); // reserved
+        quote.extend_from_slice(&INTEL_QE_VENDOR_ID);
+        quote.extend_from_slice(&[0u8; 20]); // user data
+
+        // TD quote body (584 bytes)
+        quote.extend_from_slice(&self.fw.tee_tcb_svn);
+        quote.extend_from_slice(&self.fw.mrseam);
+        quote.extend_from_slice(&[0u8; 48]); // mrsignerseam (Intel module)
+        quote.extend_from_slice(&[0u8; 8]); // seam attributes
+        quote.extend_from_slice(&[0u8; 8]); // td attributes (debug off)
+        quote.extend_from_slice(&0xe7u64.to_le_bytes()); // xfam
+        quote.extend_from_slice(&self.fw.mrtd);
+        quote.extend_from_slice(&[0u8; 48 * 3]); // mrconfigid, mrowner, mrownerconfig
+        for rtmr in &self.fw.rtmr {
+            quote.extend_from_slice(rtmr);
+        }
+        quote.extend_from_slice(&rtmr3(workload));
+        quote.extend_from_slice(report_data);
+        debug_assert_eq!(quote.len(), 48 + 584);
+
+        let quote_sig = self.sign(&self.attestation_key, &quote)?;
+        let attestation_pub = &self.attestation_key.public_key().as_ref()[1..]; // raw x || y
+
+        // QE report certification data (type 6): the QE report binds the attestation key
+        // and is signed by the PCK key, whose chain follows as type 5 data.
+        let qe_auth_data: &[u8] = &[];
+        let qe_report = qe_report(attestation_pub, qe_auth_data);
+        let qe_report_sig = self.sign(&self.pck_key, &qe_report)?;
+        let chain = format!("{}{}", self.pck_cert_pem, self.ca_pem);
+
+        let mut qe_cert_data = Vec::new();
+        qe_cert_data.extend_from_slice(&qe_report);
+        qe_cert_data.extend_from_slice(&qe_report_sig);
+        qe_cert_data.extend_from_slice(&(qe_auth_data.len() as u16).to_le_bytes());
+        qe_cert_data.extend_from_slice(qe_auth_data);
+        qe_cert_data.extend_from_slice(&5u16.to_le_bytes()); // PCK cert chain
+        qe_cert_data.extend_from_slice(&(chain.len() as u32).to_le_bytes());
+        qe_cert_data.extend_from_slice(chain.as_bytes());
+
+        let mut sig_data = Vec::new();
+        sig_data.extend_from_slice(&quote_sig);
+        sig_data.extend_from_slice(attestation_pub);
+        sig_data.extend_from_slice(&6u16.to_le_bytes());
+        sig_data.extend_from_slice(&(qe_cert_data.len() as u32).to_le_bytes());
+        sig_data.extend_from_slice(&qe_cert_data);
+
+        quote.extend_from_slice(&(sig_data.len() as u32).to_le_bytes());
+        quote.extend_from_slice(&sig_data);
+        Ok(quote)
+    }
+
+    /// AMD SEV-SNP attestation report v2 (0x4A0 bytes) signed with the VCEK (ECDSA P-384).
+    fn snp_report(&self, report_data: &[u8; 64], workload: Option<&[u8; 48]>) -> Result<Vec<u8>> {
+        let mut report = vec![0u8; 0x4a0];
+        let put = |report: &mut Vec<u8>, offset: usize, bytes: &[u8]| {
+            report[offset..offset + bytes.len()].copy_from_slice(bytes);
+        };
+
+        put(&mut report, 0x00, &2u32.to_le_bytes()); // version
+        put(&mut report, 0x08, &self.fw.policy.to_le_bytes());
+        put(&mut report, 0x34, &1u32.to_le_bytes()); // signature algo: ECDSA P-384 with SHA-384
+        put(&mut report, 0x38, &self.fw.tcb_version.to_le_bytes()); // current TCB
+        put(&mut report, 0x50, report_data);
+        put(&mut report, 0x90, &self.fw.measurement);
+        put(&mut report, 0xc0, &host_data(workload));
+        put(&mut report, 0x140, &Sha256::digest(self.fw.chip_id)); // report id
+        put(&mut report, 0x180, &self.fw.tcb_version.to_le_bytes()); // reported TCB
+        put(&mut report, 0x1a0, &self.fw.chip_id);
+        put(&mut report, 0x1e0, &self.fw.tcb_version.to_le_bytes()); // committed TCB
+        put(&mut report, 0x1f0, &self.fw.tcb_version.to_le_bytes()); // launch TCB
+
+        // Signature: r and s as 72-byte little-endian fields
+        let sig = self.sign(&self.vcek, &report[..SNP_SIGNED_LEN])?;
+        let (r, s) = sig.split_at(48);
+        let mut r_le = r.to_vec();
+        let mut s_le = s.to_vec();
+        r_le.reverse();
+        s_le.reverse();
+        put(&mut report, SNP_SIGNED_LEN, &r_le);
+        put(&mut report, SNP_SIGNED_LEN + 72, &s_le);
+        Ok(report)
+    }
+
+    fn sign(&self, key: &EcdsaKeyPair, message: &[u8]) -> Result<Vec<u8>> {
+        key.sign(&self.rng, message)
+            .map(|sig| sig.as_ref().to_vec())
+            .map_err(|_| anyhow::anyhow!("Failed to sign evidence"))
+    }
+}
+
+fn generate_key(
+    alg: &'static ring::signature::EcdsaSigningAlgorithm,
+    rng: &SystemRandom,
+) -> Result<(EcdsaKeyPair, Vec<u8>)> {
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(alg, rng)
+        .map_err(|_| anyhow::anyhow!("Failed to generate attestation key"))?;
+    let key = EcdsaKeyPair::from_pkcs8(alg, pkcs8.as_ref(), rng)
+        .map_err(|_| anyhow::anyhow!("Failed to load attestation key"))?;
+    Ok((key, pkcs8.as_ref().to_vec()))
+}
+
+/// SGX report body of the quoting enclave; report data is
+/// SHA-256(attestation key || QE auth data) padded to 64 bytes.
+fn qe_report(attestation_pub: &[u8], qe_auth_data: &[u8]) -> [u8; 384] {
+    let mut report = [0u8; 384];
+    let mut hasher = Sha256::new();
+    hasher.update(attestation_pub);
+    hasher.update(qe_auth_data);
+    report[320..352].copy_from_slice(&hasher.finalize());
+    report
+}
+
+/// Build the 64-byte report data from an optional caller nonce (hex, up to 32 bytes),
+/// prefixed by the hash of the signing session key (or the sealing key) so
+/// evidence binds it.
+pub fn report_data(public_key: &[u8], nonce: Option<&str>) -> Result<[u8; 64]> {
+    let mut data = [0u8; 64];
+    data[..32].copy_from_slice(&Sha256::digest(public_key));
+    if let Some(nonce) = nonce {
+        let bytes = hex::decode(nonce.trim_start_matches("0x")).context("Nonce is not hex")?;
+        if bytes.len() > 32 {
+            bail!("Nonce must be at most 32 bytes");
+        }
+        data[32..32 + bytes.len()].copy_from_slice(&bytes);
+    }
+    Ok(data)
+}
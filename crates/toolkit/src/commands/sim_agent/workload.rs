@@ -0,0 +1,164 @@
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use tracing::info;
+
+use crate::types::DockerCompose;
+
+/// Compose project name used for workloads run by the sim-agent.
+const PROJECT: &str = "sim-agent";
+
+/// Unpack a workload zip (entries prefixed with `workload/`) into `dest`,
+/// replacing any previous contents. Returns the unpacked file paths.
+pub fn unpack(data: &[u8], dest: &Path) -> Result<Vec<String>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data))
+        .context("Invalid workload zip")?;
+
+    if dest.exists() {
+        fs::remove_dir_all(dest)
+            .with_context(|| format!("Failed to clear {}", dest.display()))?;
+    }
+    fs::create_dir_all(dest)?;
+
+    let mut files = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let name = entry.name().to_string();
+        let enclosed = entry
+            .enclosed_name()
+            .with_context(|| format!("Unsafe path in workload zip: {}", name))?;
+        let relative = enclosed
+            .strip_prefix("workload")
+            .map_err(|_| anyhow::anyhow!("Zip entry '{}' is not under workload/", name))?
+            .to_path_buf();
+
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+
+        let target = dest.join(&relative);
+        if entry.is_dir() {
+            fs::create_dir_all(&target)?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut out = fs::File::create(&target)
+            .with_context(|| format!("Failed to create {}", target.display()))?;
+        std::io::copy(&mut entry, &mut out)?;
+        files.push(relative.to_string_lossy().into_owned());
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// A compose service and the container name the agent API knows it by.
+#[derive(Debug, Clone)]
+pub struct Container {
+    /// `container_name`, or the service name when not set
+    pub name: String,
+    pub service: String,
+}
+
+/// Parse and sanity-check `docker-compose.yml` in an unpacked workload.
+/// Returns the containers defined by the compose file.
+pub fn validate(dir: &Path) -> Result<Vec<Container>> {
+    let compose_path = dir.join("docker-compose.yml");
+    let content = fs::read_to_string(&compose_path)
+        .context("Workload has no docker-compose.yml")?;
+
+    if let Some(pos) = content.find("{{") {
+        let end = content[pos..].find("}}").map_or(content.len(), |e| pos + e + 2);
+        bail!("docker-compose.yml has an unresolved placeholder: {}", &content[pos..end]);
+    }
+
+    let compose: DockerCompose = serde_yaml::from_str(&content)
+        .context("Failed to parse docker-compose.yml")?;
+
+    if compose.services.is_empty() {
+        bail!("docker-compose.yml defines no services");
+    }
+
+    let mut containers = Vec::new();
+    for (name, service) in &compose.services {
+        if service.image.is_none() && service.build.is_none() {
+            bail!("Service '{}' has neither 'image' nor 'build'", name);
+        }
+        let container = service
+            .extra
+            .get("container_name")
+            .and_then(|v| v.as_str())
+            .unwrap_or(name);
+        containers.push(Container {
+            name: container.to_string(),
+            service: name.clone(),
+        });
+    }
+
+    Ok(containers)
+}
+
+/// Start the workload with `docker compose up -d`.
+pub fn compose_up(dir: &Path) -> Result<()> {
+    info!(dir = %dir.display(), "Starting workload with docker compose...");
+    run_compose(dir, &["up", "-d", "--remove-orphans"])
+}
+
+/// Stop a previously started workload.
+pub fn compose_down(dir: &Path) -> Result<()> {
+    info!(dir = %dir.display(), "Stopping workload...");
+    run_compose(dir, &["down", "--remove-orphans"])
+}
+
+fn run_compose(dir: &Path, args: &[&str]) -> Result<()> {
+    let output = compose(dir)
+        .args(args)
+        .output()
+        .context("Failed to run docker compose. Is Docker installed and running?")?;
+
+    if !output.status.success() {
+        bail!(
+            "docker compose {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Fetch logs for a single compose service via `docker compose logs`, which
+/// works whether or not the service sets `container_name`.
+pub fn service_logs(dir: &Path, service: &str) -> Result<String> {
+    let output = compose(dir)
+        .args(["logs", "--no-color", "--no-log-prefix", "--tail", "1000", service])
+        .output()
+        .context("Failed to run docker compose logs. Is Docker installed and running?")?;
+
+    if !output.status.success() {
+        bail!(
+            "docker compose logs {} failed: {}",
+            service,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    // Containers write to both streams; the real agent returns them combined.
+    let mut log = String::from_utf8_lossy(&output.stdout).into_owned();
+    log.push_str(&String::from_utf8_lossy(&output.stderr));
+    Ok(log)
+}
+
+/// `docker compose` for the sim-agent project and the workload's compose file.
+fn compose(dir: &Path) -> Command {
+    let mut command = Command::new("docker");
+    command
+        .args(["compose", "-p", PROJECT, "-f"])
+        .arg(dir.join("docker-compose.yml"))
+        .current_dir(dir);
+    command
+}
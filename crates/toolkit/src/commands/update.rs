@@ -1,26 +1,197 @@
-use anyhow::Result;
-use tracing::info;
+use std::io::{self, BufRead, Write};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::agent::client::AgentClient;
+use anyhow::{bail, Context, Result};
+use k256::PublicKey;
+use tracing::{info, warn};
+
+use crate::agent::client::{self, AgentClient};
 use crate::config::Config;
+use crate::controller::client::{ControllerClient, MODE_INTERNET, MODE_TOOL_NODE};
+use crate::controller::secrets::ControllerSecrets;
 use crate::state::DeployState;
 use crate::workload;
+use crate::workload::snapshot::{WorkloadDiff, WorkloadSnapshot};
+
+/// How long to wait for the workload to come back after a maintenance update.
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(300);
+const HEALTH_POLL: Duration = Duration::from_secs(10);
 
-pub fn run(config: Config) -> Result<()> {
-    let state = DeployState::load(&config.vm_name)?;
+/// Push the resolved workload to the CVM. With `diff`, show what changes since
+/// the last upload and ask before uploading (unless `yes`). With `maintenance`,
+/// the upload happens in the controller's maintenance mode.
+pub fn run(config: Config, diff: bool, yes: bool, maintenance: bool) -> Result<()> {
+    let mut state = DeployState::load(&config.vm_name)?;
 
-    let ip = state.ip.as_deref()
+    let ip = state.ip.clone()
         .ok_or_else(|| anyhow::anyhow!("No IP found in state for '{}'", config.vm_name))?;
-    let token = state.api_token.as_deref()
+    let token = state.api_token.clone()
         .ok_or_else(|| anyhow::anyhow!("No API token found in state for '{}'", config.vm_name))?;
 
     // Resolve workload (pass IP for identity.env)
-    let workload = workload::resolve::resolve_with_ip(&config, Some(ip))?;
+    let secrets = ControllerSecrets::load_or_generate(&config.vm_name)?;
+    let workload = workload::resolve::resolve_with_ip(&config, Some(&ip), &secrets)?;
+    workload::signatures::verify(&config, &workload.manifest)?;
+    let snapshot = WorkloadSnapshot::capture(&workload.path, &workload::snapshot::vm_key(&config.vm_name)?)?;
+
+    if diff {
+        match &state.workload {
+            Some(previous) => {
+                println!("Changes since {}:", previous.uploaded_at);
+                let changes = WorkloadDiff::new(previous, &snapshot);
+                changes.print();
+                if changes.is_empty() {
+                    return Ok(());
+                }
+            }
+            None => println!("No previous upload recorded for '{}', the whole workload is new.", config.vm_name),
+        }
+        if !yes && !confirm("Upload this workload?")? {
+            bail!("Update cancelled");
+        }
+    }
+
+    let client = AgentClient::new(&ip, &token)?;
+    if config.seal_secrets {
+        let key = sealing_key(&config, &client)?;
+        workload::seal::seal(&workload.path, &key)?;
+        state.sealing_key = Some(workload::seal::key_hex(&key));
+    }
 
     // Update via CVM agent
-    let client = AgentClient::new(ip, token)?;
-    client.update_workload(&workload.path)?;
+    let zip_data = client::create_workload_zip(&workload.path)?;
+    let controller = if maintenance {
+        let current = ControllerClient::for_deployment(&config, &state)?;
+        let updated = current.with_workload_key(&secrets.api_key, Some(&secrets.jwt_secret))?;
+        Some((current, updated))
+    } else {
+        None
+    };
+    // Recorded as soon as the workload is on the VM, even if leaving
+    // maintenance mode fails afterwards
+    let record = |state: &mut DeployState| -> Result<u32> {
+        secrets.save(&state.vm_name)?;
+        let version = state.archive_workload(&zip_data, &snapshot.hash, Some(&secrets))?;
+        state.image_digests = workload::digests::pinned(&workload.manifest.images());
+        state.workload = Some(snapshot.clone());
+        state.save()?;
+        Ok(version)
+    };
+    let version = match &controller {
+        Some((current, updated)) => {
+            upload_in_maintenance(&client, current, updated, &zip_data, || record(&mut state))?
+        }
+        None => {
+            client.update_workload(&zip_data)?;
+            record(&mut state)?
+        }
+    };
 
-    info!(vm_name = %config.vm_name, ip, "Workload updated");
+    info!(vm_name = %config.vm_name, ip, version, "Workload updated");
     Ok(())
 }
+
+/// The running CVM's sealing key. The agent's attestation evidence is only
+/// checked for the key hash and nonce in its report data, not against a
+/// verified quote, so the key is trusted only if it matches `sealing_key` from
+/// the config or the key recorded for the VM. The evidence is saved next to the
+/// golden measurements so an unknown key can be verified before pinning it.
+fn sealing_key(config: &Config, agent: &AgentClient) -> Result<PublicKey> {
+    let (key, attestation) = agent.get_sealing_key()?;
+    let key = PublicKey::from_sec1_bytes(&key)
+        .map_err(|_| anyhow::anyhow!("CVM sealing key is not a secp256k1 public key"))?;
+    let key_hex = workload::seal::key_hex(&key);
+
+    let measurements_dir = Config::state_dir()?.join("measurements");
+    std::fs::create_dir_all(&measurements_dir)?;
+    let path = measurements_dir.join(format!("{}-sealing-attestation.json", config.vm_name));
+    std::fs::write(&path, serde_json::to_string_pretty(&attestation)?)?;
+    info!(key = %key_hex, attestation = %path.display(), "Fetched CVM sealing key");
+
+    let trusted = workload::seal::configured_key(config).with_context(|| {
+        format!(
+            "The attestation of CVM sealing key {} is not verified; check {} and set 'sealing_key'",
+            key_hex,
+            path.display()
+        )
+    })?;
+    if trusted != Some(key) {
+        bail!(
+            "CVM sealing key {} differs from the pinned or recorded key; check {} before changing 'sealing_key'",
+            key_hex,
+            path.display()
+        );
+    }
+    Ok(key)
+}
+
+/// Enter maintenance mode, upload (then `uploaded`), wait for the workload to
+/// come back and return to tool-node mode. `updated` talks to the controller
+/// of the new workload, whose API key may differ. On failure the controller is
+/// put back in the mode it was in before.
+pub fn upload_in_maintenance<T>(
+    agent: &AgentClient,
+    controller: &ControllerClient,
+    updated: &ControllerClient,
+    zip_data: &[u8],
+    uploaded: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    let previous = controller.wait_settled().context("Failed to read the controller mode")?;
+    if previous != MODE_TOOL_NODE && previous != MODE_INTERNET {
+        bail!("Controller is in '{}' mode, not updating", previous);
+    }
+    info!(mode = %previous, "Entering maintenance mode for the update");
+
+    let mut on_vm = false;
+    let result = controller.set_maintenance(true)
+        .and_then(|_| agent.update_workload(zip_data))
+        .and_then(|_| {
+            on_vm = true;
+            uploaded()
+        })
+        .and_then(|value| {
+            wait_healthy(agent, updated)?;
+            updated.set_maintenance(false)?;
+            Ok(value)
+        });
+    if result.is_err() {
+        warn!(mode = %previous, "Update failed, restoring controller mode");
+        let controller = if on_vm { updated } else { controller };
+        if let Err(restore) = controller.set_mode(&previous) {
+            warn!(error = %format!("{:#}", restore), "Failed to restore controller mode");
+        }
+    }
+    result
+}
+
+/// Wait until the agent serves container logs again and the (restarted)
+/// controller reports a settled mode.
+fn wait_healthy(agent: &AgentClient, controller: &ControllerClient) -> Result<()> {
+    info!("Waiting for the workload to come up...");
+    let deadline = Instant::now() + HEALTH_TIMEOUT;
+    loop {
+        thread::sleep(HEALTH_POLL);
+        let problem = match agent.get_logs(&[]).and_then(|_| controller.status()) {
+            Ok(status) if status.status == MODE_TOOL_NODE || status.status == MODE_INTERNET => {
+                info!(mode = %status.status, "Workload is up");
+                return Ok(());
+            }
+            Ok(status) => format!("controller is {}", status.status),
+            Err(e) => format!("{:#}", e),
+        };
+        if Instant::now() >= deadline {
+            bail!("Workload did not come up within {}s: {}", HEALTH_TIMEOUT.as_secs(), problem);
+        }
+        info!(problem = %problem, "Not up yet");
+    }
+}
+
+/// Ask a yes/no question on the terminal (no answer means no).
+fn confirm(question: &str) -> Result<bool> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
@@ -3,8 +3,11 @@ use std::fs;
 use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Context, Result};
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
+use crate::secret_ref;
+
 /// Main configuration loaded from cvm.yaml
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Config {
@@ -72,6 +75,23 @@ pub struct Config {
     #[serde(default = "default_release_tag")]
     pub release_tag: String,
 
+    /// Parallel HTTP range connections for the disk image download (1 = single stream)
+    #[serde(default = "default_download_connections")]
+    pub download_connections: u32,
+
+    /// Refuse to deploy disk images without verified SLSA build provenance
+    #[serde(default)]
+    pub require_provenance: bool,
+
+    /// Sigstore trusted_root.json used to verify build provenance offline
+    #[serde(default)]
+    pub trust_root: Option<String>,
+
+    /// How the disk image is prepared: auto (native when e2fsprogs is installed,
+    /// else Docker), native (rootless e2fsprogs) or docker (privileged disktools)
+    #[serde(default)]
+    pub disk_backend: DiskBackend,
+
     /// Docker image for disk operations
     #[serde(default = "default_disktools_image")]
     pub disktools_image: String,
@@ -80,6 +100,11 @@ pub struct Config {
     #[serde(default)]
     pub image_tars: Vec<String>,
 
+    /// Compose images to pull and `docker save` into the workload, by service
+    /// name or image reference (`"*"` for all), for CVMs without registry access
+    #[serde(default)]
+    pub bundle_images: Vec<String>,
+
     /// Secret files to copy into workload/secrets/ (e.g. nodekey, leaders, authorized_keys)
     /// Map of filename -> local path
     #[serde(default)]
@@ -88,6 +113,31 @@ pub struct Config {
     /// Container images used in workload (resolved at build time, baked into compose)
     #[serde(default)]
     pub images: ImageConfig,
+
+    /// Rewrite compose images to `image@sha256:...` at build time so the same
+    /// config always deploys the same code (also `--pin-digests`)
+    #[serde(default)]
+    pub pin_digests: bool,
+
+    /// Encrypt `.env` and `secrets/` to the CVM's sealing key, so only the
+    /// attested CVM can read them (see `workload::seal`)
+    #[serde(default)]
+    pub seal_secrets: bool,
+
+    /// Sealing key (hex secp256k1) for `build` and `deploy`. `update` fetches
+    /// the running CVM's key and refuses to seal if it differs from this one
+    #[serde(default)]
+    pub sealing_key: Option<String>,
+}
+
+/// Disk preparation backend.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiskBackend {
+    #[default]
+    Auto,
+    Native,
+    Docker,
 }
 
 /// Container image references. All have defaults.
@@ -111,6 +161,25 @@ pub struct ImageConfig {
     pub socat: String,
     #[serde(default = "default_caddy_image")]
     pub caddy: String,
+    /// Cosign signature policies, keyed by image (`ghcr.io/org/app`, or a
+    /// prefix ending in `*`). Matching compose images must be signed.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub verify: IndexMap<String, ImageVerifyPolicy>,
+}
+
+/// How an image signature is checked: against a cosign public key, or as a
+/// keyless (Fulcio) signature from an identity, verified with `trust_root`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ImageVerifyPolicy {
+    /// PEM public key (`cosign.pub`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Prefix the signer's certificate identity (URI or email) must start with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+    /// OIDC issuer of the signer identity (default: GitHub Actions)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
 }
 
 impl Default for ImageConfig {
@@ -125,6 +194,7 @@ impl Default for ImageConfig {
             operator: default_operator_image(),
             socat: default_socat_image(),
             caddy: default_caddy_image(),
+            verify: IndexMap::new(),
         }
     }
 }
@@ -184,6 +254,10 @@ fn default_release_tag() -> String {
     "v0.0.9".to_string()
 }
 
+fn default_download_connections() -> u32 {
+    1
+}
+
 fn default_disktools_image() -> String {
     "ghcr.io/nuconstruct-ltd/toolkit-disktools:latest".to_string()
 }
@@ -216,28 +290,106 @@ fn default_caddy_image() -> String {
     "docker.io/library/caddy:latest".to_string()
 }
 
+/// A value as docker compose reads it back from `.env`. Plain values stay
+/// bare; others are single-quoted (taken literally, so `$` and `#` survive),
+/// or double-quoted with escapes when they contain a single quote.
+fn dotenv_value(key: &str, value: &str) -> Result<String> {
+    if value.contains(['\n', '\r', '\0']) {
+        bail!("env {} contains a line break or NUL byte, which .env can't hold", key);
+    }
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-.,:/@%+=".contains(c);
+    if value.chars().all(plain) {
+        Ok(value.to_string())
+    } else if !value.contains('\'') {
+        Ok(format!("'{}'", value))
+    } else {
+        let escaped = value.replace('\\', "\\\\").replace('"', "\\\"").replace('$', "\\$");
+        Ok(format!("\"{}\"", escaped))
+    }
+}
+
 impl Config {
     /// Load configuration from a YAML file.
     pub fn load(path: &Path) -> Result<Self> {
         let content = fs::read_to_string(path)
             .with_context(|| format!("Failed to read config file: {}", path.display()))?;
-        let config: Config = serde_yaml::from_str(&content)
+        let mut config: Config = serde_yaml::from_str(&content)
             .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
         config.validate()?;
+        config.resolve_paths(path.parent().unwrap_or(Path::new("")));
         Ok(config)
     }
 
+    /// Make every local path in the config relative to `base`, the directory
+    /// of the config file, rather than the current directory: the workload,
+    /// SSH key, trust root, image tars, secret files, `file:` references and
+    /// signature keys. `~` is expanded first; absolute paths are kept.
+    fn resolve_paths(&mut self, base: &Path) {
+        let join = |p: &mut String| {
+            *p = base.join(shellexpand::tilde(p.as_str()).as_ref()).to_string_lossy().into_owned();
+        };
+        let join_ref = |v: &mut String| {
+            if let Some(p) = v.strip_prefix("file:") {
+                let mut p = p.to_string();
+                join(&mut p);
+                *v = format!("file:{}", p);
+            } else if !secret_ref::is_reference(v) {
+                join(v);
+            }
+        };
+
+        for p in [&mut self.workload_dir, &mut self.ssh_public_key_file, &mut self.trust_root]
+            .into_iter()
+            .flatten()
+        {
+            join(p);
+        }
+        self.image_tars.iter_mut().for_each(join);
+        self.secret_files.values_mut().for_each(join_ref);
+        for key in self.images.verify.values_mut().filter_map(|v| v.key.as_mut()) {
+            join(key);
+        }
+        let env = &mut self.env;
+        for map in [
+            &mut env.tool_node,
+            &mut env.lighthouse,
+            &mut env.logging,
+            &mut env.metrics,
+            &mut env.caddy,
+            &mut env.operator,
+        ] {
+            for value in map.values_mut() {
+                if value.starts_with("file:") {
+                    join_ref(value);
+                }
+            }
+        }
+    }
+
     /// Validate the configuration.
     pub fn validate(&self) -> Result<()> {
         match self.csp.as_str() {
             "gcp" => self.validate_gcp()?,
             other => bail!("Unsupported CSP: '{}'. Currently only 'gcp' is supported.", other),
         }
+        if self.require_provenance && self.trust_root.is_none() {
+            bail!("'require_provenance' needs 'trust_root' (path to a Sigstore trusted_root.json)");
+        }
+        for (image, policy) in &self.images.verify {
+            match (&policy.key, &policy.identity) {
+                (Some(_), None) => {}
+                (None, Some(_)) if self.trust_root.is_some() => {}
+                (None, Some(_)) => {
+                    bail!("Keyless signature policy for '{}' needs 'trust_root' (path to a Sigstore trusted_root.json)", image)
+                }
+                _ => bail!("Signature policy for '{}' needs exactly one of 'key' or 'identity'", image),
+            }
+        }
         Ok(())
     }
 
     fn validate_gcp(&self) -> Result<()> {
-        if self.project_id.as_ref().map_or(true, |s| s.is_empty()) {
+        if self.project_id.as_ref().is_none_or(|s| s.is_empty()) {
             bail!("'project_id' is required for GCP deployments");
         }
 
@@ -305,23 +457,26 @@ impl Config {
         Ok(None)
     }
 
+    /// Get the Sigstore trust root path, if configured.
+    pub fn trust_root_path(&self) -> Option<PathBuf> {
+        self.trust_root.as_ref()
+            .map(|p| PathBuf::from(shellexpand::tilde(p).as_ref()))
+    }
+
     /// Generate .env file content from all env sections (flattened).
-    pub fn generate_dotenv(&self) -> String {
+    pub fn generate_dotenv(&self) -> Result<String> {
         let flat = self.env.flatten();
         let mut lines: Vec<String> = Vec::new();
         // Sort keys for deterministic output
         let mut keys: Vec<&String> = flat.keys().collect();
         keys.sort();
         for key in keys {
-            let value = &flat[key];
-            // Quote values that contain spaces or special chars
-            if value.contains(' ') || value.contains('"') || value.contains('\'') {
-                lines.push(format!("{}=\"{}\"", key, value.replace('"', "\\\"")));
-            } else {
-                lines.push(format!("{}={}", key, value));
-            }
+            // Secret references (env:, file:, cmd:, gcpsm:) are resolved here
+            let value = &secret_ref::resolve(&flat[key], self.project_id.as_deref())
+                .with_context(|| format!("Failed to resolve env {}", key))?;
+            lines.push(format!("{}={}", key, dotenv_value(key, value)?));
         }
-        lines.join("\n") + "\n"
+        Ok(lines.join("\n") + "\n")
     }
 
     /// Apply all template substitutions (images + operator ports).
@@ -362,12 +517,7 @@ impl Config {
 
     /// Get the disk filename for this CSP.
     pub fn disk_filename(&self) -> &str {
-        match self.csp.as_str() {
-            "gcp" => "gcp_disk.tar.gz",
-            "aws" => "aws_disk.vmdk",
-            "azure" => "azure_disk.vhd",
-            _ => "gcp_disk.tar.gz",
-        }
+        disk_filename(&self.csp)
     }
 
     /// Get the state directory (~/.toolkit/state/)
@@ -380,6 +530,16 @@ impl Config {
         Ok(dir)
     }
 
+    /// Get the sim-agent data directory (~/.toolkit/sim-agent/)
+    pub fn sim_agent_dir() -> Result<PathBuf> {
+        let dir = dirs::home_dir()
+            .context("Could not determine home directory")?
+            .join(".toolkit")
+            .join("sim-agent");
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
     /// Get the disk cache directory (~/.toolkit/disks/)
     pub fn disk_cache_dir() -> Result<PathBuf> {
         let dir = dirs::home_dir()
@@ -390,3 +550,13 @@ impl Config {
         Ok(dir)
     }
 }
+
+/// Release asset name of the disk image for a CSP.
+pub fn disk_filename(csp: &str) -> &'static str {
+    match csp {
+        "gcp" => "gcp_disk.tar.gz",
+        "aws" => "aws_disk.vmdk",
+        "azure" => "azure_disk.vhd",
+        _ => "gcp_disk.tar.gz",
+    }
+}
@@ -0,0 +1,229 @@
+use std::net::{IpAddr, SocketAddr};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tracing::info;
+
+use crate::config::Config;
+use crate::state::{self, DeployState};
+use super::secrets;
+
+/// How long to wait out a mode switch that is already in progress.
+const SWITCH_TIMEOUT: Duration = Duration::from_secs(300);
+const SWITCH_POLL: Duration = Duration::from_secs(5);
+
+/// Mode names reported by the controller.
+pub const MODE_TOOL_NODE: &str = "tool-node";
+pub const MODE_INTERNET: &str = "internet";
+pub const MODE_SWITCHING: &str = "switching";
+
+/// Body of every controller response.
+#[derive(Debug, Deserialize)]
+pub struct ControllerResponse {
+    /// Current mode: tool-node, internet, error or switching
+    pub status: String,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// Client for the controller, which switches the CVM between tool-node mode
+/// and internet (maintenance) mode.
+pub struct ControllerClient {
+    base_url: String,
+    api_key: String,
+    /// CVM IP when the controller is reached through its pinned certificate
+    /// rather than a domain
+    ip: Option<IpAddr>,
+    client: reqwest::blocking::Client,
+}
+
+impl ControllerClient {
+    pub fn new(base_url: &str, api_key: &str) -> Result<Self> {
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            ip: None,
+            client: builder().build().context("Failed to create HTTP client")?,
+        })
+    }
+
+    /// Controller at `https://<ip>:8081`, trusting only the certificate
+    /// derived from the workload's JWT secret (see
+    /// [`secrets::tls_identity`]).
+    fn pinned(ip: IpAddr, jwt_secret: &str, api_key: &str) -> Result<Self> {
+        let (cert, _) = secrets::tls_identity(jwt_secret)?;
+        let cert = reqwest::Certificate::from_pem(cert.as_bytes())
+            .context("Invalid controller TLS certificate")?;
+        let client = builder()
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(cert)
+            .resolve(secrets::TLS_NAME, SocketAddr::new(ip, secrets::TLS_PORT))
+            .build()
+            .context("Failed to create HTTP client")?;
+
+        Ok(Self {
+            base_url: format!("https://{}:{}", secrets::TLS_NAME, secrets::TLS_PORT),
+            api_key: api_key.to_string(),
+            ip: Some(ip),
+            client,
+        })
+    }
+
+    /// Controller of a deployed CVM, over TLS: through `CADDY_CONTROLLER_DOMAIN`
+    /// when it is set, else at the CVM IP with the pinned certificate of the
+    /// VM's JWT secret. The API key is `CONTROLLER_API_KEY`, else the VM's
+    /// stored key (generated at deploy or set with `controller set-key`). The
+    /// CVM API token is never sent to the controller.
+    pub fn for_deployment(config: &Config, state: &DeployState) -> Result<Self> {
+        let domain = config.env.caddy.get("CADDY_CONTROLLER_DOMAIN").filter(|d| !d.is_empty());
+        let api_key = match env_api_key() {
+            Some(key) => key,
+            None => DeployState::load_secret(&state.vm_name, state::CONTROLLER_KEY)?.with_context(|| {
+                format!(
+                    "No controller key for '{}': set CONTROLLER_API_KEY or run `toolkit controller set-key`",
+                    state.vm_name
+                )
+            })?,
+        };
+        if let Some(domain) = domain {
+            return Self::new(&format!("https://{}", domain), &api_key);
+        }
+
+        let ip = state.ip.as_deref()
+            .with_context(|| format!("No IP recorded for '{}': set CADDY_CONTROLLER_DOMAIN under env.caddy", state.vm_name))?;
+        let ip = ip.parse().with_context(|| format!("Invalid CVM IP '{}'", ip))?;
+        let jwt_secret = DeployState::load_secret(&state.vm_name, state::JWT_SECRET)?.with_context(|| {
+            format!(
+                "No JWT secret for '{}' to pin the controller certificate: set CADDY_CONTROLLER_DOMAIN under env.caddy or run `toolkit update`",
+                state.vm_name
+            )
+        })?;
+        Self::pinned(ip, &jwt_secret, &api_key)
+    }
+
+    /// The same controller once a workload provisioned with `api_key` and
+    /// `jwt_secret` is up (`CONTROLLER_API_KEY` still takes precedence). The
+    /// JWT secret is only needed when the controller is reached by IP.
+    pub fn with_workload_key(&self, api_key: &str, jwt_secret: Option<&str>) -> Result<Self> {
+        let api_key = env_api_key().unwrap_or_else(|| api_key.to_string());
+        match self.ip {
+            None => Self::new(&self.base_url, &api_key),
+            Some(ip) => {
+                let jwt_secret = jwt_secret.context(
+                    "The workload's controller certificate is unknown: set CADDY_CONTROLLER_DOMAIN under env.caddy",
+                )?;
+                Self::pinned(ip, jwt_secret, &api_key)
+            }
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Current mode (`GET /mode`).
+    pub fn mode(&self) -> Result<String> {
+        let resp = self.client
+            .get(format!("{}/mode", self.base_url))
+            .send()
+            .context("Failed to reach the controller")?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let body = resp.text().unwrap_or_default();
+            bail!("controller mode failed ({}): {}", status, body);
+        }
+
+        Ok(resp.text().context("Failed to read mode response")?.trim().to_string())
+    }
+
+    /// Current mode as JSON (`GET /status`).
+    pub fn status(&self) -> Result<ControllerResponse> {
+        let resp = self.client
+            .get(format!("{}/status", self.base_url))
+            .send()
+            .context("Failed to reach the controller")?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let body = resp.text().unwrap_or_default();
+            bail!("controller status failed ({}): {}", status, body);
+        }
+
+        resp.json().context("Failed to parse status response")
+    }
+
+    /// Enter (`enable`) or leave (`disable`) maintenance mode and return the
+    /// mode the controller switched to. A switch already in progress (409) is
+    /// waited out and the request retried.
+    pub fn set_maintenance(&self, enable: bool) -> Result<String> {
+        let action = if enable { "enable" } else { "disable" };
+        info!(action, "Switching controller maintenance mode...");
+
+        let deadline = Instant::now() + SWITCH_TIMEOUT;
+        loop {
+            let resp = self.client
+                .post(format!("{}/maintenance", self.base_url))
+                .header("Authorization", format!("Bearer {}", self.api_key))
+                .json(&serde_json::json!({ "action": action }))
+                .send()
+                .context("Failed to send maintenance request")?;
+
+            let status = resp.status();
+            let body = resp.text().unwrap_or_default();
+            let parsed: Option<ControllerResponse> = serde_json::from_str(&body).ok();
+            if status == StatusCode::CONFLICT && Instant::now() < deadline {
+                info!("Another mode switch is in progress, waiting...");
+                self.wait_settled()?;
+                continue;
+            }
+            if !status.is_success() {
+                let message = parsed.and_then(|r| r.message).unwrap_or(body);
+                bail!("maintenance {} failed ({}): {}", action, status, message);
+            }
+
+            let mode = parsed.context("Invalid maintenance response")?.status;
+            info!(mode = %mode, "Controller mode switched");
+            return Ok(mode);
+        }
+    }
+
+    /// Poll `GET /status` until the controller is no longer switching modes.
+    pub fn wait_settled(&self) -> Result<String> {
+        let deadline = Instant::now() + SWITCH_TIMEOUT;
+        loop {
+            let mode = self.status()?.status;
+            if mode != MODE_SWITCHING {
+                return Ok(mode);
+            }
+            if Instant::now() >= deadline {
+                bail!("Controller still switching modes after {}s", SWITCH_TIMEOUT.as_secs());
+            }
+            thread::sleep(SWITCH_POLL);
+        }
+    }
+
+    /// Switch to `mode` (tool-node or internet).
+    pub fn set_mode(&self, mode: &str) -> Result<String> {
+        match mode {
+            MODE_INTERNET => self.set_maintenance(true),
+            MODE_TOOL_NODE => self.set_maintenance(false),
+            other => bail!("Unknown controller mode '{}' (use {} or {})", other, MODE_TOOL_NODE, MODE_INTERNET),
+        }
+    }
+}
+
+/// HTTPS-only client. A mode switch runs to completion before
+/// POST /maintenance answers, hence the long timeout.
+fn builder() -> reqwest::blocking::ClientBuilder {
+    reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(120))
+        .https_only(true)
+}
+
+fn env_api_key() -> Option<String> {
+    std::env::var("CONTROLLER_API_KEY").ok().filter(|k| !k.is_empty())
+}
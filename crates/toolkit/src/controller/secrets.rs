@@ -0,0 +1,101 @@
+use anyhow::{Context, Result};
+use rand::RngCore;
+use rcgen::{CertificateParams, DnType, KeyPair};
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::state::{self, DeployState};
+
+/// Workload file with the SHA-256 of the controller API key.
+pub const API_KEY_HASH_FILE: &str = "controller_api_key_hash";
+/// Workload file with the engine API JWT secret shared by the controller,
+/// tool-node and lighthouse.
+pub const JWT_SECRET_FILE: &str = "jwtsecret";
+/// Workload files with the TLS certificate and key Caddy serves the
+/// controller with on the CVM IP.
+pub const TLS_CERT_FILE: &str = "controller_tls.crt";
+pub const TLS_KEY_FILE: &str = "controller_tls.key";
+
+/// Name in the controller's TLS certificate. Clients without
+/// `CADDY_CONTROLLER_DOMAIN` resolve it to the CVM IP.
+pub const TLS_NAME: &str = "controller.cvm.internal";
+/// Port Caddy serves the controller on with that certificate.
+pub const TLS_PORT: u16 = 8081;
+
+/// PKCS#8 v1 prefix of an Ed25519 private key, followed by the 32-byte seed.
+const ED25519_PKCS8_PREFIX: [u8; 16] = [
+    0x30, 0x2e, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20,
+];
+const TLS_KEY_DOMAIN: &[u8] = b"toolkit controller tls v1";
+
+/// Secrets the workload's controller is provisioned with.
+#[derive(Debug, Clone)]
+pub struct ControllerSecrets {
+    /// Bearer key for `POST /maintenance` (only its hash goes to the CVM)
+    pub api_key: String,
+    /// Engine API JWT secret (32 bytes, hex)
+    pub jwt_secret: String,
+}
+
+impl ControllerSecrets {
+    pub fn generate() -> Self {
+        Self {
+            api_key: random_hex(),
+            jwt_secret: random_hex(),
+        }
+    }
+
+    /// The secrets stored for a VM, generating whichever is missing.
+    pub fn load_or_generate(vm_name: &str) -> Result<Self> {
+        let api_key = DeployState::load_secret(vm_name, state::CONTROLLER_KEY)?;
+        let jwt_secret = DeployState::load_secret(vm_name, state::JWT_SECRET)?;
+        if api_key.is_none() || jwt_secret.is_none() {
+            info!(vm_name, "Generating missing controller secrets");
+        }
+        Ok(Self {
+            api_key: api_key.unwrap_or_else(random_hex),
+            jwt_secret: jwt_secret.unwrap_or_else(random_hex),
+        })
+    }
+
+    /// Store the secrets next to the VM's state (owner-only permissions).
+    pub fn save(&self, vm_name: &str) -> Result<()> {
+        DeployState::save_secret(vm_name, state::CONTROLLER_KEY, &self.api_key)?;
+        DeployState::save_secret(vm_name, state::JWT_SECRET, &self.jwt_secret)?;
+        Ok(())
+    }
+
+    /// What the controller checks bearer keys against (hex SHA-256).
+    pub fn api_key_hash(&self) -> String {
+        hex::encode(Sha256::digest(self.api_key.as_bytes()))
+    }
+
+    /// The controller's TLS certificate and key (PEM). The Ed25519 key is
+    /// derived from the JWT secret, and the certificate is self-signed with
+    /// fixed fields, so the toolkit rebuilds the exact certificate to trust
+    /// without storing another secret.
+    pub fn tls_identity(&self) -> Result<(String, String)> {
+        tls_identity(&self.jwt_secret)
+    }
+}
+
+/// See [`ControllerSecrets::tls_identity`].
+pub fn tls_identity(jwt_secret: &str) -> Result<(String, String)> {
+    let seed = Sha256::new()
+        .chain_update(TLS_KEY_DOMAIN)
+        .chain_update(jwt_secret.trim().as_bytes())
+        .finalize();
+    let pkcs8 = [&ED25519_PKCS8_PREFIX[..], &seed[..]].concat();
+    let key = KeyPair::try_from(&pkcs8[..]).context("Failed to derive the controller TLS key")?;
+
+    let mut params = CertificateParams::new(vec![TLS_NAME.to_string()])?;
+    params.distinguished_name.push(DnType::CommonName, TLS_NAME);
+    let cert = params.self_signed(&key).context("Failed to create the controller TLS certificate")?;
+    Ok((cert.pem(), key.serialize_pem()))
+}
+
+fn random_hex() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
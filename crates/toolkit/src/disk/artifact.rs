@@ -0,0 +1,187 @@
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::controller::secrets::ControllerSecrets;
+use crate::disk::cache;
+use crate::state;
+use crate::workload::snapshot::WorkloadSnapshot;
+
+/// Sidecar manifest of a prepared disk built with `toolkit build`, stored
+/// next to the artifact as `<artifact>.manifest.json`. Rebuilding with the
+/// same config, release and secrets (`build --secrets-from`) reproduces the
+/// artifact byte for byte, unless secrets are sealed.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ArtifactManifest {
+    pub csp: String,
+    /// Release the base disk image came from
+    pub release_tag: String,
+    /// SHA-256 of the base disk image
+    #[serde(default)]
+    pub base_sha256: Option<String>,
+    /// SHA-256 and size of the prepared artifact itself
+    pub sha256: String,
+    pub size: u64,
+    /// VM name baked into secrets/identity.env
+    pub vm_name: String,
+    /// Workload measurement (SHA-384, see `workload::measure`)
+    pub workload_hash: String,
+    /// SHA-256 of the API token whose hash is on the data partition
+    pub token_hash: String,
+    /// Container image reference per compose service the workload was built with
+    pub images: IndexMap<String, String>,
+    /// Predicted workload-dependent measurements
+    pub measurements: PredictedMeasurements,
+    /// Key `.env` and `secrets/` were sealed to (`seal_secrets`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealing_key: Option<String>,
+    /// The workload as built, recorded as the VM's last upload by `deploy --image`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workload: Option<WorkloadSnapshot>,
+    pub built_at: String,
+    pub toolkit_version: String,
+}
+
+/// Measurements that depend only on the workload, computed from
+/// `workload_hash` like the agent extends it. Firmware measurements (MRTD,
+/// RTMR0-2, SNP launch digest) come from the release, not the build.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PredictedMeasurements {
+    pub platform: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtmr3: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_data: Option<String>,
+}
+
+impl ArtifactManifest {
+    pub fn path(artifact: &Path) -> PathBuf {
+        sidecar(artifact, "manifest.json")
+    }
+
+    pub fn load(artifact: &Path) -> Result<Self> {
+        let path = Self::path(artifact);
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("No build manifest at {} (was the image made with `toolkit build`?)", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    pub fn save(&self, artifact: &Path) -> Result<()> {
+        let path = Self::path(artifact);
+        fs::write(&path, serde_json::to_string_pretty(self)? + "\n")
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Check the artifact is exactly the one this manifest describes.
+    pub fn verify(&self, artifact: &Path) -> Result<()> {
+        let size = fs::metadata(artifact)
+            .with_context(|| format!("{} not found", artifact.display()))?
+            .len();
+        if size != self.size {
+            bail!("{} is {} bytes, manifest says {}", artifact.display(), size, self.size);
+        }
+        info!(path = %artifact.display(), "Verifying artifact checksum...");
+        let actual = cache::sha256_file(artifact)?;
+        if actual != self.sha256 {
+            bail!(
+                "{} does not match its build manifest (sha256 {}, expected {})",
+                artifact.display(),
+                actual,
+                self.sha256
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether `token` is the one baked into the artifact.
+    pub fn matches_token(&self, token: &str) -> bool {
+        hex::encode(Sha256::digest(token.as_bytes())) == self.token_hash
+    }
+}
+
+/// Where the API token of an artifact is kept (owner-only permissions).
+pub fn token_path(artifact: &Path) -> PathBuf {
+    sidecar(artifact, "token")
+}
+
+pub fn save_token(artifact: &Path, token: &str) -> Result<PathBuf> {
+    let path = token_path(artifact);
+    write_private(&path, token)?;
+    Ok(path)
+}
+
+pub fn load_token(artifact: &Path) -> Result<String> {
+    let path = token_path(artifact);
+    let token = fs::read_to_string(&path)
+        .with_context(|| format!("API token for the image not found at {}", path.display()))?;
+    Ok(token.trim().to_string())
+}
+
+/// Store the controller secrets the artifact's workload was provisioned with
+/// (owner-only permissions).
+pub fn save_controller_secrets(artifact: &Path, controller: &ControllerSecrets) -> Result<()> {
+    write_private(&sidecar(artifact, state::CONTROLLER_KEY), &controller.api_key)?;
+    write_private(&sidecar(artifact, state::JWT_SECRET), &controller.jwt_secret)
+}
+
+/// Controller secrets of an artifact, `None` for artifacts built without them.
+pub fn load_controller_secrets(artifact: &Path) -> Result<Option<ControllerSecrets>> {
+    let read = |name: &str| -> Result<Option<String>> {
+        let path = sidecar(artifact, name);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let value = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(Some(value.trim().to_string()))
+    };
+    match (read(state::CONTROLLER_KEY)?, read(state::JWT_SECRET)?) {
+        (Some(api_key), Some(jwt_secret)) => Ok(Some(ControllerSecrets { api_key, jwt_secret })),
+        _ => Ok(None),
+    }
+}
+
+/// Store the key the manifest's workload snapshot was hashed with
+/// (owner-only permissions).
+pub fn save_snapshot_key(artifact: &Path, key: &str) -> Result<()> {
+    write_private(&sidecar(artifact, state::SNAPSHOT_KEY), key)
+}
+
+/// Snapshot key of an artifact, `None` for artifacts built without one.
+pub fn load_snapshot_key(artifact: &Path) -> Result<Option<String>> {
+    let path = sidecar(artifact, state::SNAPSHOT_KEY);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let key = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(Some(key.trim().to_string()))
+}
+
+fn write_private(path: &Path, content: &str) -> Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .mode(0o600)
+        .open(path)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    // `mode` only applies to new files
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    writeln!(file, "{}", content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn sidecar(artifact: &Path, suffix: &str) -> PathBuf {
+    let mut name = artifact.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
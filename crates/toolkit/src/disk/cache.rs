@@ -0,0 +1,379 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::config::Config;
+
+const MANIFEST_SUFFIX: &str = ".manifest.json";
+/// Single per-tag manifest of the earlier layout, read if it names the disk.
+const LEGACY_MANIFEST_FILE: &str = "manifest.json";
+
+/// Where the expected digest of a cached disk came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DigestSource {
+    /// `digest` field of the GitHub release asset.
+    Release,
+    /// A `<file>.sha256` asset published alongside the disk.
+    ChecksumAsset,
+    /// Nothing published; computed locally after download (trust on first use).
+    Local,
+}
+
+/// Integrity manifest stored next to a cached disk image (`<filename>.manifest.json`).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CacheManifest {
+    pub tag: String,
+    pub filename: String,
+    pub size: u64,
+    pub sha256: String,
+    pub digest_source: DigestSource,
+    pub downloaded_at: String,
+    /// Modification time (unix seconds) of the file when its hash was last checked.
+    #[serde(default)]
+    pub verified_mtime: Option<i64>,
+    /// Last time a deploy (or prefetch) used this disk.
+    #[serde(default)]
+    pub last_used: Option<String>,
+}
+
+/// A disk image in the cache, keyed by resolved release tag.
+#[derive(Debug, Clone)]
+pub struct CachedDisk {
+    pub tag: String,
+    pub path: PathBuf,
+}
+
+impl CachedDisk {
+    fn dir(&self) -> &Path {
+        self.path.parent().expect("cached disk lives in a tag directory")
+    }
+
+    /// Secure boot certificates shipped with this release.
+    pub fn secure_boot_dir(&self) -> PathBuf {
+        self.dir().join("secure_boot")
+    }
+
+    /// Expanded raw disk cache used by disktools for this release.
+    pub fn raw_cache_dir(&self) -> Result<PathBuf> {
+        let dir = self.dir().join("raw_cache");
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+}
+
+/// Cache directory for a release tag (~/.toolkit/disks/<tag>/).
+pub fn tag_dir(tag: &str) -> Result<PathBuf> {
+    if tag.is_empty() || tag == "." || tag.contains(['/', '\\']) || tag.contains("..") {
+        bail!("Invalid release tag '{}'", tag);
+    }
+    let dir = Config::disk_cache_dir()?.join(tag);
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+impl CacheManifest {
+    /// Manifest of `filename` in a tag directory.
+    pub fn load(dir: &Path, filename: &str) -> Option<Self> {
+        read_manifest(&dir.join(format!("{}{}", filename, MANIFEST_SUFFIX)))
+            .or_else(|| read_manifest(&dir.join(LEGACY_MANIFEST_FILE)))
+            .filter(|m| m.filename == filename)
+    }
+
+    /// Manifest of a cached disk, next to it.
+    pub fn for_disk(path: &Path) -> Option<Self> {
+        let dir = path.parent()?;
+        Self::load(dir, &path.file_name()?.to_string_lossy())
+    }
+
+    /// All manifests in a tag directory, by filename.
+    pub fn load_all(dir: &Path) -> Vec<Self> {
+        let mut manifests: Vec<Self> = fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().ends_with(MANIFEST_SUFFIX))
+            .filter_map(|e| read_manifest(&e.path()))
+            .collect();
+        if let Some(legacy) = read_manifest(&dir.join(LEGACY_MANIFEST_FILE)) {
+            if !manifests.iter().any(|m| m.filename == legacy.filename) {
+                manifests.push(legacy);
+            }
+        }
+        manifests.sort_by(|a, b| a.filename.cmp(&b.filename));
+        manifests
+    }
+
+    /// Write the manifest via a temp file + rename so it is never half-written.
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let name = format!("{}{}", self.filename, MANIFEST_SUFFIX);
+        let path = dir.join(&name);
+        let tmp = dir.join(format!("{}.tmp", name));
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        // Drop the per-tag manifest of the earlier layout once it is superseded
+        let legacy = dir.join(LEGACY_MANIFEST_FILE);
+        if read_manifest(&legacy).is_some_and(|m| m.filename == self.filename) {
+            fs::remove_file(&legacy)
+                .with_context(|| format!("Failed to remove {}", legacy.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Check a cached file against the manifest. The full SHA-256 is only
+    /// recomputed when the file changed since it was last verified.
+    pub fn verify(&mut self, path: &Path) -> Result<bool> {
+        let meta = match fs::metadata(path) {
+            Ok(meta) => meta,
+            Err(_) => return Ok(false),
+        };
+        if meta.len() != self.size {
+            warn!(path = %path.display(), expected = self.size, actual = meta.len(), "Cached disk has wrong size");
+            return Ok(false);
+        }
+
+        let mtime = mtime_secs(&meta);
+        if mtime.is_some() && mtime == self.verified_mtime {
+            return Ok(true);
+        }
+
+        info!(path = %path.display(), "Verifying cached disk checksum...");
+        let actual = sha256_file(path)?;
+        if actual != self.sha256 {
+            warn!(path = %path.display(), expected = %self.sha256, %actual, "Cached disk checksum mismatch");
+            return Ok(false);
+        }
+
+        self.verified_mtime = mtime;
+        if let Some(dir) = path.parent() {
+            self.save(dir)?;
+        }
+        Ok(true)
+    }
+
+    /// Cheap integrity status (size and mtime only).
+    pub fn status(&self, path: &Path) -> CacheStatus {
+        match fs::metadata(path) {
+            Err(_) => CacheStatus::Missing,
+            Ok(meta) if meta.len() != self.size => CacheStatus::Corrupt,
+            Ok(meta) => match mtime_secs(&meta) {
+                Some(mtime) if Some(mtime) == self.verified_mtime => CacheStatus::Verified,
+                _ => CacheStatus::Unchecked,
+            },
+        }
+    }
+
+    pub fn new(tag: &str, filename: &str, size: u64, sha256: String, source: DigestSource) -> Self {
+        let now = Utc::now().to_rfc3339();
+        Self {
+            tag: tag.to_string(),
+            filename: filename.to_string(),
+            size,
+            sha256,
+            digest_source: source,
+            downloaded_at: now.clone(),
+            verified_mtime: None,
+            last_used: Some(now),
+        }
+    }
+}
+
+fn read_manifest(path: &Path) -> Option<CacheManifest> {
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Integrity state of a cached disk, as far as it can be told without hashing.
+/// Ordered from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CacheStatus {
+    /// Size matches and the file is unchanged since its hash was last verified.
+    Verified,
+    /// Size matches but the file changed (or was never hashed); rehashed on next use.
+    Unchecked,
+    /// Manifest present but the disk file is gone (e.g. interrupted download).
+    Missing,
+    /// Size differs from the manifest.
+    Corrupt,
+    /// Disk without a manifest.
+    NoManifest,
+}
+
+impl std::fmt::Display for CacheStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CacheStatus::Verified => "verified",
+            CacheStatus::Unchecked => "unchecked",
+            CacheStatus::Corrupt => "corrupt",
+            CacheStatus::Missing => "missing",
+            CacheStatus::NoManifest => "no manifest",
+        })
+    }
+}
+
+/// One release tag directory in the cache.
+#[derive(Debug)]
+pub struct CacheEntry {
+    pub tag: String,
+    pub dir: PathBuf,
+    /// Total size of the directory (disks, raw cache, certs, provenance).
+    pub size: u64,
+    /// One manifest per cached disk (one per CSP).
+    pub manifests: Vec<CacheManifest>,
+    /// Worst status of the cached disks.
+    pub status: CacheStatus,
+}
+
+impl CacheEntry {
+    /// Last use of any disk, falling back to the download time.
+    pub fn last_used(&self) -> Option<&str> {
+        self.manifests
+            .iter()
+            .map(|m| m.last_used.as_deref().unwrap_or(&m.downloaded_at))
+            .max()
+    }
+
+    /// Full SHA-256 check of the cached disks, updating their manifests.
+    pub fn verify(&mut self) -> Result<()> {
+        let mut corrupt = false;
+        for manifest in &mut self.manifests {
+            let path = self.dir.join(&manifest.filename);
+            if !path.exists() {
+                continue;
+            }
+            manifest.verified_mtime = None;
+            corrupt |= !manifest.verify(&path)?;
+        }
+        self.status = if corrupt {
+            CacheStatus::Corrupt
+        } else {
+            entry_status(&self.dir, &self.manifests)
+        };
+        Ok(())
+    }
+}
+
+fn entry_status(dir: &Path, manifests: &[CacheManifest]) -> CacheStatus {
+    manifests
+        .iter()
+        .map(|m| m.status(&dir.join(&m.filename)))
+        .max()
+        .unwrap_or(CacheStatus::NoManifest)
+}
+
+/// All tag directories in the cache, most recently used first.
+pub fn entries() -> Result<Vec<CacheEntry>> {
+    let root = Config::disk_cache_dir()?;
+    let mut entries = Vec::new();
+
+    for dirent in fs::read_dir(&root)? {
+        let dirent = dirent?;
+        let tag = dirent.file_name().to_string_lossy().to_string();
+        if !dirent.file_type()?.is_dir() || LEGACY_DIRS.contains(&tag.as_str()) {
+            continue;
+        }
+
+        let dir = dirent.path();
+        let manifests = CacheManifest::load_all(&dir);
+        let status = entry_status(&dir, &manifests);
+        entries.push(CacheEntry { tag, size: dir_size(&dir), dir, manifests, status });
+    }
+
+    entries.sort_by(|a, b| b.last_used().cmp(&a.last_used()));
+    Ok(entries)
+}
+
+/// Directories the pre-tag cache layout kept directly under ~/.toolkit/disks/.
+const LEGACY_DIRS: &[&str] = &["raw_cache", "secure_boot"];
+
+/// Leftovers from the pre-tag cache layout (top-level disks, raw_cache, secure_boot).
+pub fn legacy_paths() -> Result<Vec<PathBuf>> {
+    let root = Config::disk_cache_dir()?;
+    let mut paths = Vec::new();
+    for dirent in fs::read_dir(&root)? {
+        let dirent = dirent?;
+        let name = dirent.file_name().to_string_lossy().to_string();
+        if !dirent.file_type()?.is_dir() || LEGACY_DIRS.contains(&name.as_str()) {
+            paths.push(dirent.path());
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Remove a file or directory from the cache.
+pub fn remove(path: &Path) -> Result<()> {
+    let result = if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    };
+    result.with_context(|| format!("Failed to remove {}", path.display()))
+}
+
+/// Total size of the files under `path`.
+pub fn dir_size(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.metadata().ok())
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// Look up a valid cached disk for `tag`, removing it if it fails verification.
+pub fn lookup(tag: &str, filename: &str) -> Result<Option<CachedDisk>> {
+    let dir = tag_dir(tag)?;
+    let path = dir.join(filename);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    match CacheManifest::load(&dir, filename) {
+        Some(mut manifest) => {
+            if manifest.verify(&path)? {
+                manifest.last_used = Some(Utc::now().to_rfc3339());
+                manifest.save(&dir)?;
+                return Ok(Some(CachedDisk { tag: tag.to_string(), path }));
+            }
+        }
+        _ => warn!(path = %path.display(), "Cached disk has no manifest"),
+    }
+
+    warn!(path = %path.display(), "Discarding invalid cached disk");
+    fs::remove_file(&path)
+        .with_context(|| format!("Failed to remove {}", path.display()))?;
+    Ok(None)
+}
+
+/// SHA-256 of a file, hex encoded.
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+pub fn mtime_secs(meta: &fs::Metadata) -> Option<i64> {
+    let modified = meta.modified().ok()?;
+    let secs = modified.duration_since(std::time::UNIX_EPOCH).ok()?.as_secs();
+    i64::try_from(secs).ok()
+}
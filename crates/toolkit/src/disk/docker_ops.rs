@@ -6,13 +6,6 @@ use tracing::info;
 
 use crate::config::Config;
 
-/// Get the disk cache directory for raw disk caching.
-fn cache_dir() -> Result<std::path::PathBuf> {
-    let dir = Config::disk_cache_dir()?.join("raw_cache");
-    std::fs::create_dir_all(&dir)?;
-    Ok(dir)
-}
-
 /// Ensure the disktools Docker image is available.
 pub fn ensure_image(config: &Config) -> Result<()> {
     let image = &config.disktools_image;
@@ -51,6 +44,7 @@ pub fn prepare_disk(
     config: &Config,
     disk_path: &Path,
     workload_dir: &Path,
+    raw_cache: &Path,
 ) -> Result<String> {
     info!(disk = %disk_path.display(), "Preparing disk (workload + token)...");
 
@@ -60,8 +54,6 @@ pub fn prepare_disk(
         .context("Disk path has no filename")?
         .to_string_lossy();
 
-    let cache = cache_dir()?;
-
     let mut cmd_args = vec![
         "prepare-disk".to_string(),
         disk_name.to_string(),
@@ -78,7 +70,7 @@ pub fn prepare_disk(
         .args(["run", "--rm", "--privileged"])
         .args(["-v", &format!("{}:/disk", disk_dir.display())])
         .args(["-v", &format!("{}:/workload:ro", workload_dir.display())])
-        .args(["-v", &format!("{}:/cache", cache.display())])
+        .args(["-v", &format!("{}:/cache", raw_cache.display())])
         .arg(&config.disktools_image)
         .args(&cmd_args)
         .output()
@@ -102,10 +94,12 @@ pub fn prepare_disk(
 }
 
 /// Update disk with workload files.
+#[allow(dead_code)]
 pub fn update_disk(
     config: &Config,
     disk_path: &Path,
     workload_dir: &Path,
+    raw_cache: &Path,
 ) -> Result<()> {
     info!(disk = %disk_path.display(), "Updating disk with workload...");
 
@@ -115,8 +109,6 @@ pub fn update_disk(
         .context("Disk path has no filename")?
         .to_string_lossy();
 
-    let cache = cache_dir()?;
-
     let mut cmd_args = vec![
         "update-workload".to_string(),
         disk_name.to_string(),
@@ -131,7 +123,7 @@ pub fn update_disk(
         .args(["run", "--rm", "--privileged"])
         .args(["-v", &format!("{}:/disk", disk_dir.display())])
         .args(["-v", &format!("{}:/workload:ro", workload_dir.display())])
-        .args(["-v", &format!("{}:/cache", cache.display())])
+        .args(["-v", &format!("{}:/cache", raw_cache.display())])
         .arg(&config.disktools_image)
         .args(&cmd_args)
         .status()
@@ -147,9 +139,11 @@ pub fn update_disk(
 
 /// Generate API token and embed hash in disk.
 /// Returns the API token string.
+#[allow(dead_code)]
 pub fn generate_token(
     config: &Config,
     disk_path: &Path,
+    raw_cache: &Path,
 ) -> Result<String> {
     info!("Generating API token...");
 
@@ -159,12 +153,10 @@ pub fn generate_token(
         .context("Disk path has no filename")?
         .to_string_lossy();
 
-    let cache = cache_dir()?;
-
     let output = Command::new("docker")
         .args(["run", "--rm", "--privileged"])
         .args(["-v", &format!("{}:/disk", disk_dir.display())])
-        .args(["-v", &format!("{}:/cache", cache.display())])
+        .args(["-v", &format!("{}:/cache", raw_cache.display())])
         .args([&config.disktools_image])
         .args(["generate-token", &disk_name, &config.csp, &config.vm_name])
         .output()
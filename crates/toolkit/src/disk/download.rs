@@ -1,54 +1,46 @@
 use std::fs::{self, File};
-use std::io::Write;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 
-use anyhow::{Context, Result};
-use indicatif::{ProgressBar, ProgressStyle};
-use tracing::info;
+use anyhow::{bail, Context, Result};
+use tracing::{info, warn};
 
 use crate::config::Config;
+use crate::types::{GitHubAsset, GitHubRelease};
+use super::cache::{self, CacheManifest, CachedDisk, DigestSource};
+use super::transfer;
 
 const REPO: &str = "automata-network/automata-linux";
 
-/// Download disk image from GitHub releases.
-/// Returns the path to the downloaded disk file.
-pub fn download_disk(config: &Config) -> Result<PathBuf> {
-    let disk_dir = Config::disk_cache_dir()?;
-    let filename = config.disk_filename();
-    let disk_path = disk_dir.join(filename);
-
-    // Check if already cached
-    if disk_path.exists() {
-        info!(path = %disk_path.display(), "Disk image already cached");
-        return Ok(disk_path);
-    }
+/// Download disk image from GitHub releases into the per-tag cache.
+/// Returns the verified cached disk.
+pub fn download_disk(config: &Config) -> Result<CachedDisk> {
+    download_release_disk(&config.release_tag, config.disk_filename(), config.download_connections)
+}
 
-    let tag = &config.release_tag;
-    info!(tag, filename, "Downloading disk image from GitHub...");
+/// Download `filename` from release `tag` (or `latest`) into the per-tag cache.
+pub fn download_release_disk(tag: &str, filename: &str, connections: u32) -> Result<CachedDisk> {
+
+    // Pinned tags can be served from cache without asking GitHub
+    if tag != "latest" {
+        if let Some(cached) = cache::lookup(tag, filename)? {
+            info!(path = %cached.path.display(), tag, "Disk image already cached");
+            return Ok(cached);
+        }
+    }
 
     let client = reqwest::blocking::Client::builder()
         .timeout(std::time::Duration::from_secs(3600))
         .build()?;
 
-    // Fetch release info
-    let api_url = if tag == "latest" {
-        format!("https://api.github.com/repos/{}/releases/latest", REPO)
-    } else {
-        format!("https://api.github.com/repos/{}/releases/tags/{}", REPO, tag)
-    };
-
-    let mut req = client.get(&api_url)
-        .header("User-Agent", "toolkit");
+    let release = fetch_release(&client, tag)?;
+    let tag = &release.tag_name;
 
-    if let Ok(token) = std::env::var("GITHUB_TOKEN") {
-        req = req.header("Authorization", format!("Bearer {}", token));
+    if let Some(cached) = cache::lookup(tag, filename)? {
+        info!(path = %cached.path.display(), tag, "Disk image already cached");
+        return Ok(cached);
     }
 
-    let release: crate::types::GitHubRelease = req
-        .send()
-        .context("Failed to fetch release info from GitHub")?
-        .json()
-        .context("Failed to parse release info")?;
+    info!(tag, filename, "Downloading disk image from GitHub...");
 
     // Find the disk asset
     let asset = release.assets.iter()
@@ -60,47 +52,117 @@ pub fn download_disk(config: &Config) -> Result<PathBuf> {
             release.assets.iter().map(|a| a.name.as_str()).collect::<Vec<_>>().join(", ")
         ))?;
 
-    // Download with progress bar
-    let mut req = client.get(&asset.browser_download_url)
-        .header("User-Agent", "toolkit");
+    let expected = expected_digest(&client, &release, asset)?;
 
-    if let Ok(token) = std::env::var("GITHUB_TOKEN") {
-        req = req.header("Authorization", format!("Bearer {}", token));
-    }
+    let dir = cache::tag_dir(tag)?;
+    let disk_path = dir.join(filename);
+    let partial_path = dir.join(format!("{}.partial", filename));
 
-    let resp = req.send().context("Failed to start download")?;
-    let total_size = resp.content_length().unwrap_or(asset.size);
+    // Stream into the partial file; an interrupted download resumes from there
+    let url = &asset.browser_download_url;
+    transfer::fetch(&|| github_get(&client, url), &partial_path, asset.size, connections)
+        .context("Failed to download disk image")?;
 
-    let pb = ProgressBar::new(total_size);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{spinner:.green} [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-            .unwrap()
-            .progress_chars("#>-"),
-    );
+    // Verify before the download becomes visible under its final name
+    let len = fs::metadata(&partial_path)?.len();
+    if len != asset.size {
+        let _ = fs::remove_file(&partial_path);
+        bail!("Downloaded disk is {} bytes, release says {}", len, asset.size);
+    }
+    let actual = cache::sha256_file(&partial_path)?;
+    let source = match expected {
+        Some((ref digest, source)) => {
+            if *digest != actual {
+                let _ = fs::remove_file(&partial_path);
+                bail!("Disk image checksum mismatch: expected {}, got {}", digest, actual);
+            }
+            source
+        }
+        None => {
+            warn!(tag, filename, "Release publishes no checksum; recording local SHA-256");
+            DigestSource::Local
+        }
+    };
 
-    let mut file = File::create(&disk_path)
-        .with_context(|| format!("Failed to create {}", disk_path.display()))?;
+    fs::rename(&partial_path, &disk_path)
+        .with_context(|| format!("Failed to move download to {}", disk_path.display()))?;
 
-    let bytes = resp.bytes().context("Failed to read response body")?;
-    pb.set_position(bytes.len() as u64);
-    file.write_all(&bytes)?;
-    pb.finish_with_message("Downloaded");
+    let mut manifest = CacheManifest::new(tag, filename, asset.size, actual, source);
+    manifest.verified_mtime = fs::metadata(&disk_path).ok().as_ref().and_then(cache::mtime_secs);
+    manifest.save(&dir)?;
 
     info!(path = %disk_path.display(), "Disk image downloaded");
 
+    let cached = CachedDisk { tag: tag.clone(), path: disk_path };
+
     // Also download secure boot certs
-    download_secure_boot_certs(&client, &release, &disk_dir)?;
+    download_secure_boot_certs(&client, &release, &cached.secure_boot_dir())?;
+
+    Ok(cached)
+}
 
-    Ok(disk_path)
+/// Fetch release info for a tag (`latest` resolves to the newest release).
+pub fn fetch_release(client: &reqwest::blocking::Client, tag: &str) -> Result<GitHubRelease> {
+    let api_url = if tag == "latest" {
+        format!("https://api.github.com/repos/{}/releases/latest", REPO)
+    } else {
+        format!("https://api.github.com/repos/{}/releases/tags/{}", REPO, tag)
+    };
+
+    github_get(client, &api_url)
+        .send()
+        .context("Failed to fetch release info from GitHub")?
+        .error_for_status()
+        .with_context(|| format!("Release '{}' not found", tag))?
+        .json()
+        .context("Failed to parse release info")
+}
+
+/// GET request with the toolkit user agent and optional `GITHUB_TOKEN`.
+pub fn github_get(client: &reqwest::blocking::Client, url: &str) -> reqwest::blocking::RequestBuilder {
+    let mut req = client.get(url)
+        .header("User-Agent", "toolkit");
+
+    if let Ok(token) = std::env::var("GITHUB_TOKEN") {
+        req = req.header("Authorization", format!("Bearer {}", token));
+    }
+    req
+}
+
+/// Expected SHA-256 of an asset: the release's asset digest, or a `<name>.sha256` asset.
+fn expected_digest(
+    client: &reqwest::blocking::Client,
+    release: &GitHubRelease,
+    asset: &GitHubAsset,
+) -> Result<Option<(String, DigestSource)>> {
+    if let Some(digest) = asset.digest.as_deref().and_then(|d| d.strip_prefix("sha256:")) {
+        return Ok(Some((digest.to_lowercase(), DigestSource::Release)));
+    }
+
+    let checksum_name = format!("{}.sha256", asset.name);
+    let Some(checksum_asset) = release.assets.iter().find(|a| a.name == checksum_name) else {
+        return Ok(None);
+    };
+
+    let content = github_get(client, &checksum_asset.browser_download_url)
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.text())
+        .with_context(|| format!("Failed to download {}", checksum_name))?;
+    let digest = content
+        .split_whitespace()
+        .next()
+        .filter(|d| d.len() == 64 && d.chars().all(|c| c.is_ascii_hexdigit()))
+        .with_context(|| format!("{} does not contain a SHA-256", checksum_name))?;
+
+    Ok(Some((digest.to_lowercase(), DigestSource::ChecksumAsset)))
 }
 
 fn download_secure_boot_certs(
     client: &reqwest::blocking::Client,
-    release: &crate::types::GitHubRelease,
-    disk_dir: &Path,
+    release: &GitHubRelease,
+    cert_dir: &Path,
 ) -> Result<()> {
-    let cert_dir = disk_dir.join("secure_boot");
     if cert_dir.join("PK.crt").exists() {
         info!("Secure boot certs already present");
         return Ok(());
@@ -116,24 +178,20 @@ fn download_secure_boot_certs(
 
     info!("Downloading secure boot certificates...");
 
-    let mut req = client.get(&asset.browser_download_url)
-        .header("User-Agent", "toolkit");
-
-    if let Ok(token) = std::env::var("GITHUB_TOKEN") {
-        req = req.header("Authorization", format!("Bearer {}", token));
-    }
-
-    let resp = req.send().context("Failed to download certs")?;
+    let resp = github_get(client, &asset.browser_download_url)
+        .send()
+        .context("Failed to download certs")?;
     let bytes = resp.bytes()?;
 
+    let disk_dir = cert_dir.parent().context("Cert dir has no parent")?;
     let zip_path = disk_dir.join("secure-boot-certs.zip");
     fs::write(&zip_path, &bytes)?;
 
     // Extract
-    fs::create_dir_all(&cert_dir)?;
+    fs::create_dir_all(cert_dir)?;
     let file = File::open(&zip_path)?;
     let mut archive = zip::ZipArchive::new(file)?;
-    archive.extract(&cert_dir)?;
+    archive.extract(cert_dir)?;
 
     // Cleanup zip
     let _ = fs::remove_file(&zip_path);
@@ -0,0 +1,384 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use anyhow::{bail, Context, Result};
+use tracing::info;
+
+/// Where e2fsprogs lives when it is not on PATH (Homebrew installs it keg-only).
+const EXTRA_DIRS: &[&str] = &[
+    "/sbin",
+    "/usr/sbin",
+    "/opt/homebrew/opt/e2fsprogs/sbin",
+    "/usr/local/opt/e2fsprogs/sbin",
+];
+
+/// Locate an e2fsprogs binary.
+pub fn tool(name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path)
+        .chain(EXTRA_DIRS.iter().map(PathBuf::from))
+        .map(|dir| dir.join(name))
+        .find(|p| p.is_file())
+}
+
+/// Whether debugfs, e2fsck and resize2fs are all available.
+pub fn available() -> bool {
+    ["debugfs", "e2fsck", "resize2fs"].iter().all(|t| tool(t).is_some())
+}
+
+/// An entry from `debugfs ls -p`.
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+}
+
+impl DirEntry {
+    pub fn is_dir(&self) -> bool {
+        self.mode & 0o170000 == 0o040000
+    }
+}
+
+/// An ext2/3/4 filesystem inside a disk image, accessed rootless through
+/// e2fsprogs with the `image?offset=N` device syntax (no loop devices).
+pub struct Ext4 {
+    image: PathBuf,
+    offset: u64,
+    device: String,
+    /// Fixed clock (unix seconds) for reproducible images
+    clock: Option<i64>,
+}
+
+impl Ext4 {
+    pub fn new(image: &Path, offset: u64) -> Self {
+        Self {
+            image: image.to_path_buf(),
+            offset,
+            device: format!("{}?offset={}", image.display(), offset),
+            clock: None,
+        }
+    }
+
+    /// Run e2fsprogs with a fixed clock and give written files that mtime,
+    /// so the same inputs produce the same filesystem bytes.
+    pub fn with_clock(mut self, secs: i64) -> Self {
+        self.clock = Some(secs);
+        self
+    }
+
+    /// Latest time recorded in the superblock (mount, write, check, mkfs).
+    /// e2fsck flags a superblock from the future, so a fixed clock must not
+    /// be earlier than this.
+    pub fn superblock_time(&self) -> Result<i64> {
+        let mut sb = [0u8; 1024];
+        let mut file = std::fs::File::open(&self.image)?;
+        file.seek(SeekFrom::Start(self.offset + 1024))?;
+        file.read_exact(&mut sb)
+            .with_context(|| format!("No ext4 superblock in {}", self.device))?;
+        let at = |off: usize| i64::from(u32::from_le_bytes(sb[off..off + 4].try_into().unwrap()));
+        Ok([0x2c, 0x30, 0x40, 0x108].into_iter().map(at).max().unwrap_or(0))
+    }
+
+    fn run(&self, tool_name: &str, args: &[&str]) -> Result<Output> {
+        let bin = tool(tool_name)
+            .with_context(|| format!("{} not found (install e2fsprogs)", tool_name))?;
+        let mut cmd = Command::new(&bin);
+        if let Some(clock) = self.clock {
+            cmd.env("E2FSPROGS_FAKE_TIME", clock.to_string())
+                .env("E2FSCK_TIME", clock.to_string());
+        }
+        cmd.args(args)
+            .output()
+            .with_context(|| format!("Failed to run {}", bin.display()))
+    }
+
+    /// Run a debugfs request script (`-f`) or a single request (`-R`).
+    /// debugfs exits 0 even when a request fails, so anything on stderr
+    /// besides its version banner is an error.
+    fn debugfs(&self, writable: bool, script: &str) -> Result<Vec<u8>> {
+        let mut file = tempfile::NamedTempFile::new()?;
+        let mut args = Vec::new();
+        if writable {
+            args.push("-w");
+        }
+        let script_path;
+        match script.trim_end().split_once('\n') {
+            // -f echoes every request to stdout, which would corrupt `cat` output
+            None => args.extend(["-R", script.trim_end()]),
+            Some(_) => {
+                file.write_all(script.as_bytes())?;
+                script_path = file.path().to_string_lossy().to_string();
+                args.extend(["-f", &script_path]);
+            }
+        }
+        args.push(&self.device);
+        let output = self.run("debugfs", &args)?;
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let errors: Vec<&str> = stderr
+            .lines()
+            .filter(|l| !l.trim().is_empty() && !l.starts_with("debugfs ") && !l.starts_with("\tUsing EXT2FS"))
+            .collect();
+        if !output.status.success() || !errors.is_empty() {
+            bail!("debugfs failed on {}: {}", self.device, errors.join("; "));
+        }
+        Ok(output.stdout)
+    }
+
+    /// List a directory (without `.` and `..`).
+    pub fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>> {
+        let out = self.debugfs(false, &format!("ls -p {}\n", quote(path)?))?;
+        Ok(parse_ls(&String::from_utf8_lossy(&out)))
+    }
+
+    /// List several directories with a single debugfs run.
+    fn read_dirs(&self, paths: &[String]) -> Result<Vec<Vec<DirEntry>>> {
+        if let [path] = paths {
+            return Ok(vec![self.read_dir(path)?]);
+        }
+        let mut script = String::new();
+        for path in paths {
+            script.push_str(&format!("ls -p {}\n", quote(path)?));
+        }
+        // With -f, debugfs echoes each request ahead of its output
+        let out = format!("\n{}", String::from_utf8_lossy(&self.debugfs(false, &script)?));
+        let listings: Vec<Vec<DirEntry>> = out.split("\ndebugfs: ").skip(1).map(parse_ls).collect();
+        if listings.len() != paths.len() {
+            bail!("Unexpected debugfs output listing {} directories", paths.len());
+        }
+        Ok(listings)
+    }
+
+    /// Whether `path` exists.
+    pub fn exists(&self, path: &str) -> Result<bool> {
+        let (parent, name) = split(path);
+        if parent != "/" && !self.exists(parent)? {
+            return Ok(false);
+        }
+        Ok(self.read_dir(parent)?.iter().any(|e| e.name == name))
+    }
+
+    /// Read a regular file.
+    pub fn read_file(&self, path: &str) -> Result<Vec<u8>> {
+        if !self.exists(path)? {
+            bail!("{} not found in {}", path, self.device);
+        }
+        self.debugfs(false, &format!("cat {}\n", quote(path)?))
+    }
+
+    /// Superblock fields from `stats -h` (e.g. "Block count", "Free blocks").
+    pub fn stats(&self) -> Result<Vec<(String, String)>> {
+        let out = self.debugfs(false, "stats -h\n")?;
+        Ok(String::from_utf8_lossy(&out)
+            .lines()
+            .filter_map(|l| l.split_once(':'))
+            .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+            .collect())
+    }
+
+    /// Remove `path` and everything below it, if present. Lists the tree one
+    /// level per debugfs run, then removes it all in a single script.
+    pub fn remove_tree(&self, path: &str) -> Result<()> {
+        let (parent, name) = split(path);
+        if parent != "/" && !self.exists(parent)? {
+            return Ok(());
+        }
+        let Some(entry) = self.read_dir(parent)?.into_iter().find(|e| e.name == name) else {
+            return Ok(());
+        };
+        if !entry.is_dir() {
+            self.debugfs(true, &format!("rm {}\n", quote(path)?))?;
+            return Ok(());
+        }
+
+        let mut files = Vec::new();
+        let mut levels = Vec::new();
+        let mut dirs = vec![path.to_string()];
+        while !dirs.is_empty() {
+            let mut subdirs = Vec::new();
+            for (dir, entries) in dirs.iter().zip(self.read_dirs(&dirs)?) {
+                for entry in entries {
+                    let child = format!("{}/{}", dir, entry.name);
+                    if entry.is_dir() {
+                        subdirs.push(child);
+                    } else {
+                        files.push(child);
+                    }
+                }
+            }
+            levels.push(std::mem::replace(&mut dirs, subdirs));
+        }
+
+        let mut script = String::new();
+        for file in &files {
+            script.push_str(&format!("rm {}\n", quote(file)?));
+        }
+        // Deepest directories first, so each one is empty by the time it goes
+        for dir in levels.iter().rev().flatten() {
+            script.push_str(&format!("rmdir {}\n", quote(dir)?));
+        }
+        self.debugfs(true, &script)?;
+        Ok(())
+    }
+
+    /// Copy a local directory tree to `dest` (which must not exist), owned by `uid:gid`.
+    /// Modification times are kept, unless the clock is fixed.
+    pub fn write_tree(&self, src: &Path, dest: &str, uid: u32, gid: u32) -> Result<()> {
+        let mut script = String::new();
+        let mut mtimes = Vec::new();
+        for entry in walkdir::WalkDir::new(src).sort_by_file_name() {
+            let entry = entry?;
+            let rel = entry.path().strip_prefix(src)?;
+            let target = if rel.as_os_str().is_empty() {
+                dest.to_string()
+            } else {
+                format!("{}/{}", dest, rel.to_string_lossy())
+            };
+            let target_q = quote(&target)?;
+            let meta = entry.path().symlink_metadata()?;
+
+            if meta.is_dir() {
+                script.push_str(&format!("mkdir {}\n", target_q));
+            } else if meta.file_type().is_symlink() {
+                let link = std::fs::read_link(entry.path())?;
+                script.push_str(&format!("symlink {} {}\n", target_q, quote(&link.to_string_lossy())?));
+            } else {
+                script.push_str(&format!("write {} {}\n", quote(&entry.path().to_string_lossy())?, target_q));
+            }
+            if !meta.file_type().is_symlink() {
+                script.push_str(&format!("sif {} mode 0{:o}\n", target_q, meta.mode()));
+            }
+            script.push_str(&format!("sif {} uid {}\nsif {} gid {}\n", target_q, uid, target_q, gid));
+            mtimes.push(format!("sif {} mtime @{}\n", target_q, self.clock.unwrap_or(meta.mtime())));
+        }
+        // Last, since creating entries touches their parent directory
+        for line in mtimes.iter().rev() {
+            script.push_str(line);
+        }
+        self.debugfs(true, &script)?;
+        Ok(())
+    }
+
+    /// Write a single file, replacing any existing one.
+    pub fn write_file(&self, dest: &str, content: &[u8], uid: u32, gid: u32) -> Result<()> {
+        let mut local = tempfile::NamedTempFile::new()?;
+        local.write_all(content)?;
+        let dest_q = quote(dest)?;
+
+        let mut script = String::new();
+        if self.exists(dest)? {
+            script.push_str(&format!("rm {}\n", dest_q));
+        }
+        script.push_str(&format!(
+            "write {} {}\nsif {} mode 0100644\nsif {} uid {}\nsif {} gid {}\n",
+            quote(&local.path().to_string_lossy())?,
+            dest_q,
+            dest_q,
+            dest_q,
+            uid,
+            dest_q,
+            gid
+        ));
+        self.debugfs(true, &script)?;
+        Ok(())
+    }
+
+    /// `e2fsck -fy`: repair and mark clean (required before resizing).
+    pub fn check(&self) -> Result<()> {
+        let output = self.run("e2fsck", &["-fy", &self.device])?;
+        // 0 = clean, 1 = errors corrected; anything else is a failure
+        match output.status.code() {
+            Some(0) | Some(1) => Ok(()),
+            code => bail!(
+                "e2fsck failed on {} ({:?}): {}",
+                self.device,
+                code,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        }
+    }
+
+    /// Grow the filesystem to fill its partition of `size` bytes.
+    pub fn resize(&self, size: u64) -> Result<()> {
+        info!(size, "Resizing filesystem...");
+        // resize2fs truncates regular files to the filesystem size, ignoring
+        // the offset, which cuts the image short. Restore its length and have
+        // a read-only e2fsck confirm nothing past the cut was lost.
+        let len = std::fs::metadata(&self.image)?.len();
+
+        let size_arg = format!("{}K", size / 1024);
+        let output = self.run("resize2fs", &[&self.device, &size_arg])?;
+        if !output.status.success() {
+            bail!(
+                "resize2fs failed on {}: {}",
+                self.device,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        std::fs::File::options().write(true).open(&self.image)?.set_len(len)?;
+        self.check_clean()
+    }
+
+    /// `e2fsck -fn`: fail on any problem instead of repairing it.
+    fn check_clean(&self) -> Result<()> {
+        let output = self.run("e2fsck", &["-fn", &self.device])?;
+        if !output.status.success() {
+            bail!(
+                "Filesystem on {} is damaged after resizing ({:?}): {}",
+                self.device,
+                output.status.code(),
+                String::from_utf8_lossy(&output.stdout).trim()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Parse `ls -p` output. Lines look like /inode/mode/uid/gid/name/size/
+/// (size is empty for directories).
+fn parse_ls(out: &str) -> Vec<DirEntry> {
+    let mut entries = Vec::new();
+    for line in out.lines() {
+        let Some(line) = line.strip_prefix('/').and_then(|l| l.strip_suffix('/')) else {
+            continue;
+        };
+        let fields: Vec<&str> = line.split('/').collect();
+        if fields.len() < 6 {
+            continue;
+        }
+        let name = fields[4..fields.len() - 1].join("/");
+        if name == "." || name == ".." || fields[0] == "0" {
+            continue;
+        }
+        entries.push(DirEntry {
+            name,
+            mode: u32::from_str_radix(fields[1], 8).unwrap_or(0),
+            uid: fields[2].parse().unwrap_or(0),
+            gid: fields[3].parse().unwrap_or(0),
+            size: fields[fields.len() - 1].parse().unwrap_or(0),
+        });
+    }
+    entries
+}
+
+/// Quote a path for a debugfs request line.
+fn quote(path: &str) -> Result<String> {
+    if path.contains('"') || path.contains('\n') {
+        bail!("Unsupported file name for disk image: {:?}", path);
+    }
+    Ok(format!("\"{}\"", path))
+}
+
+fn split(path: &str) -> (&str, &str) {
+    match path.trim_end_matches('/').rsplit_once('/') {
+        Some(("", name)) => ("/", name),
+        Some((parent, name)) => (parent, name),
+        None => ("/", path),
+    }
+}
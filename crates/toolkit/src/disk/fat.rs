@@ -0,0 +1,241 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+/// Largest file read into memory (UKIs are tens of MiB).
+const MAX_FILE_SIZE: u32 = 512 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+/// An entry from a FAT directory.
+#[derive(Debug, Clone)]
+pub struct FatEntry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u32,
+    cluster: u32,
+}
+
+/// Read-only FAT12/16/32 filesystem inside a disk image (EFI System Partition).
+pub struct Fat {
+    file: File,
+    kind: Kind,
+    cluster_size: u64,
+    fat_offset: u64,
+    /// FAT12/16 fixed root directory: (offset, length)
+    root_dir: Option<(u64, u64)>,
+    root_cluster: u32,
+    data_offset: u64,
+    cluster_count: u32,
+}
+
+/// Whether the partition at `offset` looks like a FAT filesystem.
+pub fn is_fat(image: &Path, offset: u64) -> bool {
+    let mut boot = [0u8; 512];
+    let read = File::open(image).and_then(|mut f| {
+        f.seek(SeekFrom::Start(offset))?;
+        f.read_exact(&mut boot)
+    });
+    read.is_ok() && boot[510..512] == [0x55, 0xAA] && (&boot[54..57] == b"FAT" || &boot[82..85] == b"FAT")
+}
+
+impl Fat {
+    pub fn open(image: &Path, offset: u64) -> Result<Self> {
+        let mut file = File::open(image)
+            .with_context(|| format!("Failed to open disk image {}", image.display()))?;
+        let mut boot = [0u8; 512];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut boot)?;
+
+        let bytes_per_sector = u16::from_le_bytes([boot[11], boot[12]]) as u64;
+        let sectors_per_cluster = boot[13] as u64;
+        let reserved = u16::from_le_bytes([boot[14], boot[15]]) as u64;
+        let fats = boot[16] as u64;
+        let root_entries = u16::from_le_bytes([boot[17], boot[18]]) as u64;
+        let total16 = u16::from_le_bytes([boot[19], boot[20]]) as u64;
+        let fat16_size = u16::from_le_bytes([boot[22], boot[23]]) as u64;
+        let total32 = u32::from_le_bytes(boot[32..36].try_into().unwrap()) as u64;
+        let fat32_size = u32::from_le_bytes(boot[36..40].try_into().unwrap()) as u64;
+
+        if bytes_per_sector == 0 || sectors_per_cluster == 0 || fats == 0 {
+            bail!("Partition at offset {} is not a FAT filesystem", offset);
+        }
+        let fat_size = if fat16_size != 0 { fat16_size } else { fat32_size };
+        let total = if total16 != 0 { total16 } else { total32 };
+        let root_sectors = (root_entries * 32).div_ceil(bytes_per_sector);
+        let first_data = reserved + fats * fat_size + root_sectors;
+        let cluster_count = u32::try_from(total.saturating_sub(first_data) / sectors_per_cluster).unwrap_or(u32::MAX);
+
+        // The FAT type is defined by the cluster count alone
+        let kind = match cluster_count {
+            0..=4084 => Kind::Fat12,
+            4085..=65524 => Kind::Fat16,
+            _ => Kind::Fat32,
+        };
+        let root_dir = (kind != Kind::Fat32).then(|| {
+            (offset + (reserved + fats * fat_size) * bytes_per_sector, root_sectors * bytes_per_sector)
+        });
+
+        Ok(Self {
+            file,
+            kind,
+            cluster_size: sectors_per_cluster * bytes_per_sector,
+            fat_offset: offset + reserved * bytes_per_sector,
+            root_dir,
+            root_cluster: u32::from_le_bytes(boot[44..48].try_into().unwrap()),
+            data_offset: offset + first_data * bytes_per_sector,
+            cluster_count,
+        })
+    }
+
+    /// List a directory by `/`-separated path (case-insensitive).
+    pub fn read_dir(&mut self, path: &str) -> Result<Vec<FatEntry>> {
+        let mut raw = match self.root_dir {
+            Some((offset, len)) => self.read_at(offset, len)?,
+            None => self.read_chain(self.root_cluster, None)?,
+        };
+        for part in path.split('/').filter(|p| !p.is_empty()) {
+            let entry = parse_dir(&raw)
+                .into_iter()
+                .find(|e| e.is_dir && e.name.eq_ignore_ascii_case(part))
+                .with_context(|| format!("Directory {} not found on EFI partition", path))?;
+            raw = self.read_chain(entry.cluster, None)?;
+        }
+        Ok(parse_dir(&raw))
+    }
+
+    /// Read a file by `/`-separated path (case-insensitive).
+    pub fn read_file(&mut self, path: &str) -> Result<Vec<u8>> {
+        let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let entry = self
+            .read_dir(dir)?
+            .into_iter()
+            .find(|e| !e.is_dir && e.name.eq_ignore_ascii_case(name))
+            .with_context(|| format!("{} not found on EFI partition", path))?;
+        if entry.size > MAX_FILE_SIZE {
+            bail!("{} is too large to read ({} bytes)", path, entry.size);
+        }
+        let mut data = self.read_chain(entry.cluster, Some(entry.size as u64))?;
+        data.truncate(entry.size as usize);
+        Ok(data)
+    }
+
+    fn read_at(&mut self, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; len as usize];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Follow a cluster chain, stopping after `limit` bytes when given.
+    fn read_chain(&mut self, start: u32, limit: Option<u64>) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut cluster = start;
+        while (2..self.cluster_count.saturating_add(2)).contains(&cluster) {
+            if limit.is_some_and(|l| data.len() as u64 >= l) {
+                break;
+            }
+            if data.len() as u64 > MAX_FILE_SIZE as u64 {
+                bail!("FAT cluster chain starting at {} is too long", start);
+            }
+            let offset = self.data_offset + (cluster as u64 - 2) * self.cluster_size;
+            data.extend(self.read_at(offset, self.cluster_size)?);
+            cluster = self.next_cluster(cluster)?;
+        }
+        Ok(data)
+    }
+
+    fn next_cluster(&mut self, cluster: u32) -> Result<u32> {
+        let c = cluster as u64;
+        Ok(match self.kind {
+            Kind::Fat12 => {
+                let b = self.read_at(self.fat_offset + c + c / 2, 2)?;
+                let v = u16::from_le_bytes([b[0], b[1]]) as u32;
+                let v = if c.is_multiple_of(2) { v & 0xFFF } else { v >> 4 };
+                if v >= 0xFF8 { 0 } else { v }
+            }
+            Kind::Fat16 => {
+                let b = self.read_at(self.fat_offset + c * 2, 2)?;
+                let v = u16::from_le_bytes([b[0], b[1]]) as u32;
+                if v >= 0xFFF8 { 0 } else { v }
+            }
+            Kind::Fat32 => {
+                let b = self.read_at(self.fat_offset + c * 4, 4)?;
+                let v = u32::from_le_bytes(b[..4].try_into().unwrap()) & 0x0FFF_FFFF;
+                if v >= 0x0FFF_FFF8 { 0 } else { v }
+            }
+        })
+    }
+}
+
+/// Parse 32-byte directory records, joining VFAT long names.
+fn parse_dir(raw: &[u8]) -> Vec<FatEntry> {
+    let mut entries = Vec::new();
+    let mut long_name: Vec<(u8, Vec<u16>)> = Vec::new();
+
+    for rec in raw.chunks_exact(32) {
+        match rec[0] {
+            0x00 => break,
+            0xE5 => {
+                long_name.clear();
+                continue;
+            }
+            _ => {}
+        }
+        let attr = rec[11];
+        if attr == 0x0F {
+            let units = [&rec[1..11], &rec[14..26], &rec[28..32]]
+                .concat()
+                .chunks(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .take_while(|&u| u != 0 && u != 0xFFFF)
+                .collect();
+            long_name.push((rec[0] & 0x1F, units));
+            continue;
+        }
+        if attr & 0x08 != 0 {
+            // Volume label
+            long_name.clear();
+            continue;
+        }
+
+        let name = if long_name.is_empty() {
+            short_name(rec)
+        } else {
+            long_name.sort_by_key(|(seq, _)| *seq);
+            let units: Vec<u16> = long_name.iter().flat_map(|(_, u)| u.iter().copied()).collect();
+            String::from_utf16_lossy(&units)
+        };
+        long_name.clear();
+        if name == "." || name == ".." {
+            continue;
+        }
+
+        let hi = u16::from_le_bytes([rec[20], rec[21]]) as u32;
+        let lo = u16::from_le_bytes([rec[26], rec[27]]) as u32;
+        entries.push(FatEntry {
+            name,
+            is_dir: attr & 0x10 != 0,
+            size: u32::from_le_bytes(rec[28..32].try_into().unwrap()),
+            cluster: (hi << 16) | lo,
+        });
+    }
+    entries
+}
+
+fn short_name(rec: &[u8]) -> String {
+    let base = String::from_utf8_lossy(&rec[0..8]).trim_end().to_string();
+    let ext = String::from_utf8_lossy(&rec[8..11]).trim_end().to_string();
+    if ext.is_empty() {
+        base
+    } else {
+        format!("{}.{}", base, ext)
+    }
+}
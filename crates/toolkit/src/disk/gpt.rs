@@ -0,0 +1,242 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+pub const SECTOR_SIZE: u64 = 512;
+const SIGNATURE: &[u8; 8] = b"EFI PART";
+
+/// A partition from the GUID partition table. `index` is 1-based like `/dev/sdaN`.
+#[derive(Debug, Clone)]
+pub struct Partition {
+    pub index: u32,
+    pub type_guid: [u8; 16],
+    pub first_lba: u64,
+    pub last_lba: u64,
+    pub name: String,
+}
+
+impl Partition {
+    /// Byte offset of the partition in the image.
+    pub fn offset(&self) -> u64 {
+        self.first_lba * SECTOR_SIZE
+    }
+
+    pub fn size(&self) -> u64 {
+        (self.last_lba - self.first_lba + 1) * SECTOR_SIZE
+    }
+
+    /// Well-known name for the partition type, if any.
+    pub fn type_name(&self) -> &'static str {
+        match format_guid(&self.type_guid).as_str() {
+            "C12A7328-F81F-11D2-BA4B-00A0C93EC93B" => "EFI System",
+            "0FC63DAF-8483-4772-8E79-3D69D8477DE4" => "Linux filesystem",
+            "4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709" => "Linux root (x86-64)",
+            "2C7357ED-EBD2-46D9-AEC1-23D437EC2BF5" => "Linux root verity (x86-64)",
+            "BC13C2FF-59E6-4262-A352-B275FD6F7172" => "Linux extended boot",
+            "21686148-6449-6E6F-744E-656564454649" => "BIOS boot",
+            _ => "unknown",
+        }
+    }
+}
+
+/// Primary GPT header plus its partition entry array.
+#[derive(Debug)]
+pub struct Gpt {
+    header: Vec<u8>,
+    entries: Vec<u8>,
+    pub partitions: Vec<Partition>,
+}
+
+impl Gpt {
+    pub fn read(path: &Path) -> Result<Self> {
+        let mut file = File::open(path)
+            .with_context(|| format!("Failed to open disk image {}", path.display()))?;
+
+        let mut header = vec![0u8; SECTOR_SIZE as usize];
+        file.seek(SeekFrom::Start(SECTOR_SIZE))?;
+        file.read_exact(&mut header)
+            .with_context(|| format!("{} is too small to hold a GPT", path.display()))?;
+        if &header[0..8] != SIGNATURE {
+            bail!("{} has no GUID partition table", path.display());
+        }
+        let header_size = u32_at(&header, 12) as usize;
+        if !(92..=SECTOR_SIZE as usize).contains(&header_size) {
+            bail!("Invalid GPT header size {}", header_size);
+        }
+        header.truncate(header_size);
+
+        let mut check = header.clone();
+        check[16..20].fill(0);
+        if crc32fast::hash(&check) != u32_at(&header, 16) {
+            bail!("GPT header checksum mismatch in {}", path.display());
+        }
+
+        let entries_lba = u64_at(&header, 72);
+        let count = u32_at(&header, 80) as usize;
+        let entry_size = u32_at(&header, 84) as usize;
+        if !(128..=512).contains(&entry_size) || !entry_size.is_multiple_of(8) || count > 1024 {
+            bail!("Unsupported GPT layout ({} entries of {} bytes)", count, entry_size);
+        }
+        let disk_len = file.metadata()?.len();
+        let entries_end = entries_lba
+            .checked_mul(SECTOR_SIZE)
+            .and_then(|start| start.checked_add((count * entry_size) as u64));
+        if entries_end.is_none_or(|end| end > disk_len) {
+            bail!("GPT partition entries lie outside {}", path.display());
+        }
+
+        let mut entries = vec![0u8; count * entry_size];
+        file.seek(SeekFrom::Start(entries_lba * SECTOR_SIZE))?;
+        file.read_exact(&mut entries)?;
+        if crc32fast::hash(&entries) != u32_at(&header, 88) {
+            bail!("GPT partition entries checksum mismatch in {}", path.display());
+        }
+
+        let partitions = entries
+            .chunks(entry_size)
+            .enumerate()
+            .filter(|(_, e)| e[0..16].iter().any(|&b| b != 0))
+            .map(|(i, e)| Partition {
+                index: i as u32 + 1,
+                type_guid: e[0..16].try_into().unwrap(),
+                first_lba: u64_at(e, 32),
+                last_lba: u64_at(e, 40),
+                name: utf16_name(&e[56..128]),
+            })
+            .collect::<Vec<_>>();
+        // offset() and size() are plain arithmetic on these
+        for p in &partitions {
+            if p.last_lba < p.first_lba || p.last_lba >= u64::MAX / SECTOR_SIZE {
+                bail!(
+                    "GPT partition {} has an invalid range ({}..={}) in {}",
+                    p.index,
+                    p.first_lba,
+                    p.last_lba,
+                    path.display()
+                );
+            }
+        }
+
+        Ok(Self { header, entries, partitions })
+    }
+
+    pub fn partition(&self, index: u32) -> Result<&Partition> {
+        self.partitions
+            .iter()
+            .find(|p| p.index == index)
+            .with_context(|| format!("Disk image has no partition {}", index))
+    }
+
+    /// Size partition `index` will have once grown to fill a disk of `disk_len` bytes.
+    pub fn grown_size(&self, index: u32, disk_len: u64) -> Result<u64> {
+        let part = self.partition(index)?;
+        if self.partitions.iter().any(|p| p.last_lba > part.last_lba) {
+            bail!("Partition {} is not the last partition on the disk", index);
+        }
+        let last_usable = self.layout(disk_len).2;
+        if last_usable < part.last_lba {
+            bail!("Disk image is smaller than its partition table");
+        }
+        Ok((last_usable - part.first_lba + 1) * SECTOR_SIZE)
+    }
+
+    /// Backup header LBA, backup entries LBA and last usable LBA for a disk of `disk_len` bytes.
+    fn layout(&self, disk_len: u64) -> (u64, u64, u64) {
+        let entries_lba = (self.entries.len() as u64).div_ceil(SECTOR_SIZE);
+        let backup_lba = disk_len / SECTOR_SIZE - 1;
+        let backup_entries_lba = backup_lba - entries_lba;
+        (backup_lba, backup_entries_lba, backup_entries_lba - 1)
+    }
+
+    /// After the image file was enlarged, move the backup GPT to the new end
+    /// and grow partition `index` (which must be the last one) to fill the disk.
+    pub fn grow_to_end(mut self, path: &Path, index: u32) -> Result<()> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)
+            .with_context(|| format!("Failed to open disk image {}", path.display()))?;
+        let disk_len = file.metadata()?.len();
+        let total_lba = disk_len / SECTOR_SIZE;
+
+        let entry_size = u32_at(&self.header, 84) as u64;
+        self.grown_size(index, disk_len)?;
+        let (backup_lba, backup_entries_lba, last_usable) = self.layout(disk_len);
+
+        // Partition entry: new last LBA
+        let at = (index as u64 - 1) * entry_size + 40;
+        self.entries[at as usize..at as usize + 8].copy_from_slice(&last_usable.to_le_bytes());
+        let entries_crc = crc32fast::hash(&self.entries);
+
+        // Primary header
+        put_u64(&mut self.header, 32, backup_lba);
+        put_u64(&mut self.header, 48, last_usable);
+        put_u32(&mut self.header, 88, entries_crc);
+        seal(&mut self.header);
+
+        // Backup header mirrors the primary with swapped locations
+        let mut backup = self.header.clone();
+        put_u64(&mut backup, 24, backup_lba);
+        put_u64(&mut backup, 32, 1);
+        put_u64(&mut backup, 72, backup_entries_lba);
+        seal(&mut backup);
+
+        let primary_entries_lba = u64_at(&self.header, 72);
+        write_at(&mut file, primary_entries_lba * SECTOR_SIZE, &self.entries)?;
+        write_at(&mut file, SECTOR_SIZE, &self.header)?;
+        write_at(&mut file, backup_entries_lba * SECTOR_SIZE, &self.entries)?;
+        write_at(&mut file, backup_lba * SECTOR_SIZE, &backup)?;
+
+        // Protective MBR covers the whole disk (capped at 32 bits)
+        let mbr_sectors = u32::try_from(total_lba - 1).unwrap_or(u32::MAX);
+        write_at(&mut file, 446 + 12, &mbr_sectors.to_le_bytes())?;
+
+        file.sync_all()?;
+        Ok(())
+    }
+}
+
+fn seal(header: &mut [u8]) {
+    put_u32(header, 16, 0);
+    let crc = crc32fast::hash(header);
+    put_u32(header, 16, crc);
+}
+
+fn write_at(file: &mut File, offset: u64, data: &[u8]) -> Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(data)?;
+    Ok(())
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(buf[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(buf: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(buf[at..at + 8].try_into().unwrap())
+}
+
+fn put_u32(buf: &mut [u8], at: usize, value: u32) {
+    buf[at..at + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buf: &mut [u8], at: usize, value: u64) {
+    buf[at..at + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+fn utf16_name(raw: &[u8]) -> String {
+    let units: Vec<u16> = raw
+        .chunks(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|&u| u != 0)
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// GUID in its canonical mixed-endian text form.
+pub fn format_guid(g: &[u8; 16]) -> String {
+    format!(
+        "{:02X}{:02X}{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
+        g[3], g[2], g[1], g[0], g[5], g[4], g[7], g[6],
+        g[8], g[9], g[10], g[11], g[12], g[13], g[14], g[15]
+    )
+}
@@ -0,0 +1,311 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::Result;
+use tracing::warn;
+
+use crate::disk::e2fs::{DirEntry, Ext4};
+use crate::disk::fat::{self, Fat};
+use crate::disk::gpt::{Gpt, Partition};
+use crate::disk::prepare::DATA_PARTITION;
+
+/// How deep to look for boot files on the EFI partition.
+const MAX_EFI_DEPTH: usize = 4;
+
+/// Everything `disk inspect` reports about a raw image.
+pub struct Inspection {
+    pub size: u64,
+    pub partitions: Vec<(Partition, &'static str)>,
+    pub boot: Vec<BootEntry>,
+    pub data: Option<DataPartition>,
+}
+
+/// A kernel found in a boot partition.
+pub struct BootEntry {
+    /// Partition index and path of the file it was found in
+    pub source: String,
+    pub kind: &'static str,
+    pub kernel: Option<String>,
+    pub os: Option<String>,
+    pub cmdline: Option<String>,
+}
+
+pub struct DataPartition {
+    pub index: u32,
+    pub size: u64,
+    pub used: Option<u64>,
+    pub token_hash: Option<String>,
+    /// Workload tree in depth-first order as (depth, entry)
+    pub workload: Vec<(usize, DirEntry)>,
+}
+
+/// Inspect a raw disk image without booting or mounting it.
+pub fn inspect(raw: &Path) -> Result<Inspection> {
+    let size = std::fs::metadata(raw)?.len();
+    let gpt = Gpt::read(raw)?;
+
+    let mut partitions = Vec::new();
+    let mut boot = Vec::new();
+    for part in &gpt.partitions {
+        let fs = filesystem(raw, part.offset());
+        let found = match fs {
+            "vfat" => efi_boot_entries(raw, part),
+            "ext2" | "ext3" | "ext4" if part.index != DATA_PARTITION => ext_boot_entries(raw, part),
+            _ => Ok(Vec::new()),
+        };
+        match found {
+            Ok(entries) => boot.extend(entries),
+            Err(e) => warn!(partition = part.index, error = %e, "Failed to read boot files"),
+        }
+        partitions.push((part.clone(), fs));
+    }
+
+    let data = match gpt.partitions.iter().find(|p| p.index == DATA_PARTITION) {
+        Some(part) if filesystem(raw, part.offset()).starts_with("ext") => {
+            Some(data_partition(raw, part)?)
+        }
+        _ => None,
+    };
+
+    Ok(Inspection { size, partitions, boot, data })
+}
+
+/// Identify the filesystem at `offset` by its superblock magic.
+fn filesystem(raw: &Path, offset: u64) -> &'static str {
+    if fat::is_fat(raw, offset) {
+        return "vfat";
+    }
+    let mut head = [0u8; 2048];
+    let read = File::open(raw).and_then(|mut f| {
+        f.seek(SeekFrom::Start(offset))?;
+        f.read_exact(&mut head)
+    });
+    if read.is_err() {
+        return "-";
+    }
+
+    if head[1080..1082] == [0x53, 0xEF] {
+        let compat = u32::from_le_bytes(head[1116..1120].try_into().unwrap());
+        let incompat = u32::from_le_bytes(head[1120..1124].try_into().unwrap());
+        // INCOMPAT_EXTENTS / COMPAT_HAS_JOURNAL
+        return if incompat & 0x40 != 0 {
+            "ext4"
+        } else if compat & 0x4 != 0 {
+            "ext3"
+        } else {
+            "ext2"
+        };
+    }
+    if &head[0..4] == b"hsqs" {
+        return "squashfs";
+    }
+    if head[1024..1028] == 0xE0F5E1E2u32.to_le_bytes() {
+        return "erofs";
+    }
+    if &head[0..8] == b"verity\0\0" {
+        return "verity";
+    }
+    "-"
+}
+
+fn data_partition(raw: &Path, part: &Partition) -> Result<DataPartition> {
+    let fs = Ext4::new(raw, part.offset());
+
+    let stats = fs.stats()?;
+    let field = |name: &str| {
+        stats
+            .iter()
+            .find(|(k, _)| k == name)
+            .and_then(|(_, v)| v.parse::<u64>().ok())
+    };
+    let used = match (field("Block count"), field("Free blocks"), field("Block size")) {
+        (Some(total), Some(free), Some(bs)) => total.checked_sub(free).and_then(|n| n.checked_mul(bs)),
+        _ => None,
+    };
+
+    let token_hash = if fs.exists("/token_hash")? {
+        Some(String::from_utf8_lossy(&fs.read_file("/token_hash")?).trim().to_string())
+    } else {
+        None
+    };
+
+    let mut workload = Vec::new();
+    if fs.exists("/workload")? {
+        walk(&fs, "/workload", 0, &mut workload)?;
+    }
+
+    Ok(DataPartition { index: part.index, size: part.size(), used, token_hash, workload })
+}
+
+fn walk(fs: &Ext4, dir: &str, depth: usize, out: &mut Vec<(usize, DirEntry)>) -> Result<()> {
+    let mut entries = fs.read_dir(dir)?;
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    for entry in entries {
+        let is_dir = entry.is_dir();
+        let path = format!("{}/{}", dir, entry.name);
+        out.push((depth, entry));
+        if is_dir {
+            walk(fs, &path, depth + 1, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// UKIs, systemd-boot entries and grub configs on an EFI System Partition.
+fn efi_boot_entries(raw: &Path, part: &Partition) -> Result<Vec<BootEntry>> {
+    let mut fat = Fat::open(raw, part.offset())?;
+    let mut files = Vec::new();
+    let mut dirs = vec![(String::new(), 0)];
+    while let Some((dir, depth)) = dirs.pop() {
+        for entry in fat.read_dir(&dir)? {
+            let path = format!("{}/{}", dir, entry.name);
+            if entry.is_dir {
+                if depth < MAX_EFI_DEPTH {
+                    dirs.push((path, depth + 1));
+                }
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+
+    let mut boot = Vec::new();
+    for path in files {
+        let lower = path.to_ascii_lowercase();
+        let source = format!("p{}:{}", part.index, path);
+        if lower.ends_with(".efi") {
+            if let Some(entry) = uki(&fat.read_file(&path)?, source) {
+                boot.push(entry);
+            }
+        } else if lower.starts_with("/loader/entries/") && lower.ends_with(".conf") {
+            boot.push(loader_entry(&fat.read_file(&path)?, source));
+        } else if lower.ends_with("grub.cfg") {
+            boot.extend(grub_entries(&fat.read_file(&path)?, &source));
+        }
+    }
+    Ok(boot)
+}
+
+/// Kernels and grub configs on an ext boot or root partition.
+fn ext_boot_entries(raw: &Path, part: &Partition) -> Result<Vec<BootEntry>> {
+    let fs = Ext4::new(raw, part.offset());
+    let mut boot = Vec::new();
+
+    for cfg in ["/boot/grub/grub.cfg", "/grub/grub.cfg"] {
+        if fs.exists(cfg)? {
+            let source = format!("p{}:{}", part.index, cfg);
+            boot.extend(grub_entries(&fs.read_file(cfg)?, &source));
+        }
+    }
+    if boot.is_empty() {
+        for dir in ["/boot", "/"] {
+            if !fs.exists(dir)? {
+                continue;
+            }
+            for entry in fs.read_dir(dir)? {
+                if entry.name.starts_with("vmlinuz-") {
+                    boot.push(BootEntry {
+                        source: format!("p{}:{}/{}", part.index, dir.trim_end_matches('/'), entry.name),
+                        kind: "kernel",
+                        kernel: entry.name.strip_prefix("vmlinuz-").map(str::to_string),
+                        os: None,
+                        cmdline: None,
+                    });
+                }
+            }
+        }
+    }
+    Ok(boot)
+}
+
+/// A unified kernel image: a PE binary with `.linux`, `.cmdline`, `.uname` and `.osrel` sections.
+fn uki(data: &[u8], source: String) -> Option<BootEntry> {
+    let sections = pe_sections(data)?;
+    let section = |name: &str| {
+        sections
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, d)| String::from_utf8_lossy(d).trim_matches(char::from(0)).trim().to_string())
+    };
+    if section(".linux").is_none() && section(".cmdline").is_none() {
+        return None;
+    }
+    let os = section(".osrel").and_then(|rel| {
+        rel.lines()
+            .find_map(|l| l.strip_prefix("PRETTY_NAME="))
+            .map(|v| v.trim_matches('"').to_string())
+    });
+    Some(BootEntry {
+        source,
+        kind: "UKI",
+        kernel: section(".uname"),
+        os,
+        cmdline: section(".cmdline"),
+    })
+}
+
+/// Section names and contents of a PE image.
+fn pe_sections(data: &[u8]) -> Option<Vec<(String, &[u8])>> {
+    let u16_at = |at: usize| data.get(at..at.checked_add(2)?).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize);
+    let u32_at = |at: usize| data.get(at..at.checked_add(4)?).map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize);
+
+    if data.get(0..2)? != b"MZ" {
+        return None;
+    }
+    let pe = u32_at(0x3C)?;
+    if data.get(pe..pe.checked_add(4)?)? != b"PE\0\0" {
+        return None;
+    }
+    let count = u16_at(pe + 6)?;
+    let table = pe + 24 + u16_at(pe + 20)?;
+
+    (0..count)
+        .map(|i| {
+            let at = table + i * 40;
+            let name = String::from_utf8_lossy(data.get(at..at + 8)?)
+                .trim_end_matches(char::from(0))
+                .to_string();
+            let len = u32_at(at + 8)?.min(u32_at(at + 16)?);
+            let start = u32_at(at + 20)?;
+            Some((name, data.get(start..start.checked_add(len)?)?))
+        })
+        .collect()
+}
+
+/// A systemd-boot Type #1 entry.
+fn loader_entry(data: &[u8], source: String) -> BootEntry {
+    let text = String::from_utf8_lossy(data);
+    let key = |k: &str| {
+        text.lines()
+            .find_map(|l| l.trim().strip_prefix(k))
+            .map(|v| v.trim().to_string())
+    };
+    BootEntry {
+        source,
+        kind: "loader entry",
+        kernel: key("linux "),
+        os: key("title "),
+        cmdline: key("options "),
+    }
+}
+
+/// `linux` lines from a grub config.
+fn grub_entries(data: &[u8], source: &str) -> Vec<BootEntry> {
+    String::from_utf8_lossy(data)
+        .lines()
+        .filter_map(|l| {
+            let l = l.trim();
+            let rest = l.strip_prefix("linux ").or_else(|| l.strip_prefix("linuxefi "))?;
+            let (kernel, cmdline) = rest.trim().split_once(char::is_whitespace).unwrap_or((rest, ""));
+            Some(BootEntry {
+                source: source.to_string(),
+                kind: "grub",
+                kernel: Some(kernel.to_string()),
+                os: None,
+                cmdline: Some(cmdline.trim().to_string()),
+            })
+        })
+        .collect()
+}
@@ -1,2 +1,11 @@
+pub mod artifact;
+pub mod cache;
 pub mod docker_ops;
 pub mod download;
+pub mod e2fs;
+pub mod fat;
+pub mod gpt;
+pub mod inspect;
+pub mod prepare;
+pub mod provenance;
+pub mod transfer;
@@ -0,0 +1,264 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use indicatif::{ProgressBar, ProgressStyle};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::config::{Config, DiskBackend};
+use crate::disk::docker_ops;
+use crate::disk::e2fs::{self, Ext4};
+use crate::disk::gpt::Gpt;
+
+/// Data partition holding /workload and /token_hash.
+pub const DATA_PARTITION: u32 = 3;
+/// Raw image entry inside the GCP tarball.
+pub const RAW_NAME: &str = "disk.raw";
+/// Owner of workload files inside the VM.
+const WORKLOAD_UID: u32 = 1000;
+const WORKLOAD_GID: u32 = 1000;
+
+/// What a disk is prepared for.
+#[derive(Debug, Clone, Copy)]
+pub enum DiskMode<'a> {
+    /// Reuse the release's expanded raw disk and generate an API token.
+    Deploy,
+    /// `toolkit build`: start from the base image with a fixed clock and the
+    /// given API token, so the same inputs give the same artifact.
+    Build { token: &'a str },
+}
+
+/// Prepare disk: inject workload + generate token, natively when possible and
+/// through the privileged disktools container otherwise.
+/// Returns the API token string.
+pub fn prepare_disk(
+    config: &Config,
+    disk_path: &Path,
+    workload_dir: &Path,
+    raw_cache: &Path,
+    mode: DiskMode,
+) -> Result<String> {
+    let supported = is_tarball(disk_path);
+    let native = match config.disk_backend {
+        DiskBackend::Docker => false,
+        DiskBackend::Native => {
+            if !supported {
+                bail!("Native disk preparation only supports .tar.gz images");
+            }
+            if !e2fs::available() {
+                bail!("Native disk preparation needs e2fsprogs (debugfs, e2fsck, resize2fs)");
+            }
+            true
+        }
+        DiskBackend::Auto => {
+            if supported && !e2fs::available() {
+                info!("e2fsprogs not found, using Docker for disk preparation");
+            }
+            supported && e2fs::available()
+        }
+    };
+
+    if native {
+        return prepare_native(config, disk_path, workload_dir, raw_cache, mode);
+    }
+    if let DiskMode::Build { .. } = mode {
+        bail!("`toolkit build` prepares .tar.gz disks with e2fsprogs (debugfs, e2fsck, resize2fs), not Docker");
+    }
+    docker_ops::ensure_image(config)?;
+    docker_ops::prepare_disk(config, disk_path, workload_dir, raw_cache)
+}
+
+fn is_tarball(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(".tar.gz")
+}
+
+/// Rootless equivalent of the disktools `prepare-disk` command: works on the
+/// raw image with e2fsprogs at the data partition's offset.
+fn prepare_native(
+    config: &Config,
+    disk_path: &Path,
+    workload_dir: &Path,
+    raw_cache: &Path,
+    mode: DiskMode,
+) -> Result<String> {
+    info!(disk = %disk_path.display(), "Preparing disk natively (workload + token)...");
+
+    // The cached raw is moved aside while it is modified so an interrupted
+    // run never leaves a half-written image behind as the cache. Builds
+    // start from the base image: a reused raw keeps traces of earlier runs.
+    let cached_raw = raw_cache.join(RAW_NAME);
+    let raw = raw_cache.join(format!("{}.work", RAW_NAME));
+    let _ = fs::remove_file(&raw);
+    // Raws left by the Docker path are root-owned; re-extract those.
+    if matches!(mode, DiskMode::Deploy) && File::options().write(true).open(&cached_raw).is_ok() {
+        info!("Using cached raw disk");
+        fs::rename(&cached_raw, &raw)?;
+    } else {
+        extract_raw(disk_path, &raw)?;
+    }
+
+    let part = Gpt::read(&raw)?.partition(DATA_PARTITION)?.clone();
+    let clock = match mode {
+        DiskMode::Deploy => None,
+        DiskMode::Build { .. } => Some(Ext4::new(&raw, part.offset()).superblock_time()?),
+    };
+    if let Some(size) = config.boot_disk_size {
+        expand(&raw, u64::from(size) << 30, clock)?;
+    }
+
+    let part = Gpt::read(&raw)?.partition(DATA_PARTITION)?.clone();
+    let mut fs = Ext4::new(&raw, part.offset());
+    if let Some(clock) = clock {
+        fs = fs.with_clock(clock);
+    }
+
+    info!("Injecting workload...");
+    fs.remove_tree("/workload")?;
+    fs.write_tree(workload_dir, "/workload", WORKLOAD_UID, WORKLOAD_GID)?;
+
+    let token = match mode {
+        DiskMode::Deploy => generate_token(),
+        DiskMode::Build { token } => token.to_string(),
+    };
+    let hash = hex::encode(Sha256::digest(token.as_bytes()));
+    fs.write_file("/token_hash", hash.as_bytes(), WORKLOAD_UID, WORKLOAD_GID)?;
+
+    repack(&raw, disk_path)?;
+    match mode {
+        DiskMode::Deploy => fs::rename(&raw, &cached_raw)?,
+        DiskMode::Build { .. } => fs::remove_file(&raw)?,
+    }
+
+    info!("Disk prepared (workload injected, token generated)");
+    Ok(token)
+}
+
+/// A new random API token (hex).
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Extract `disk.raw` from the release tarball.
+pub fn extract_raw(tarball: &Path, dest: &Path) -> Result<()> {
+    info!("Extracting raw disk image...");
+    let file = File::open(tarball)
+        .with_context(|| format!("Failed to open {}", tarball.display()))?;
+    let pb = progress_bar(file.metadata()?.len());
+    let mut archive = tar::Archive::new(GzDecoder::new(pb.wrap_read(file)));
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.file_name() != Some(RAW_NAME.as_ref()) {
+            continue;
+        }
+        entry.unpack(dest)
+            .with_context(|| format!("Failed to extract {}", dest.display()))?;
+        pb.finish_and_clear();
+        return Ok(());
+    }
+    pb.finish_and_clear();
+    bail!("{} does not contain {}", tarball.display(), RAW_NAME)
+}
+
+/// Grow the image to `size` bytes and the data partition + filesystem with it.
+fn expand(raw: &Path, size: u64, clock: Option<i64>) -> Result<()> {
+    let current = fs::metadata(raw)?.len();
+    if size <= current {
+        return Ok(());
+    }
+    info!(from = current, to = size, "Expanding disk...");
+
+    File::options().write(true).open(raw)?.set_len(size)?;
+    let gpt = Gpt::read(raw)?;
+    let part = gpt.partition(DATA_PARTITION)?;
+
+    // Filesystem first: resize2fs rewrites the end of the image file, which
+    // is where the backup GPT goes.
+    let mut fs = Ext4::new(raw, part.offset());
+    if let Some(clock) = clock {
+        fs = fs.with_clock(clock);
+    }
+    fs.check()?;
+    fs.resize(gpt.grown_size(DATA_PARTITION, size)?)?;
+    gpt.grow_to_end(raw, DATA_PARTITION)
+}
+
+/// Write `raw` as `disk.raw` in a gzipped GNU tarball, the format GCP images
+/// are created from. Compresses with pigz when installed.
+fn repack(raw: &Path, tarball: &Path) -> Result<()> {
+    info!("Repacking disk image...");
+    let tmp = tarball.with_extension("tmp");
+    let out = File::create(&tmp)
+        .with_context(|| format!("Failed to create {}", tmp.display()))?;
+
+    match which("pigz") {
+        Some(pigz) => {
+            let mut child = Command::new(pigz)
+                .args(["-c", "-n"])
+                .stdin(Stdio::piped())
+                .stdout(out)
+                .spawn()
+                .context("Failed to run pigz")?;
+            let stdin = child.stdin.take().context("pigz stdin unavailable")?;
+            let written = write_tar(raw, stdin);
+            let status = child.wait()?;
+            written?;
+            if !status.success() {
+                bail!("pigz failed ({})", status);
+            }
+        }
+        None => {
+            warn!("pigz not found, compressing single-threaded");
+            let gz = flate2::write::GzEncoder::new(BufWriter::new(out), flate2::Compression::default());
+            write_tar(raw, gz)?.finish()?.flush()?;
+        }
+    }
+
+    fs::rename(&tmp, tarball)?;
+    Ok(())
+}
+
+fn write_tar<W: Write>(raw: &Path, out: W) -> Result<W> {
+    let file = File::open(raw)?;
+    let len = file.metadata()?.len();
+
+    let mut header = tar::Header::new_gnu();
+    header.set_path(RAW_NAME)?;
+    header.set_size(len);
+    header.set_mode(0o644);
+    // Fixed mtime (and no gzip name/time) so repacking is deterministic
+    header.set_mtime(0);
+    header.set_cksum();
+
+    let pb = progress_bar(len);
+    let mut builder = tar::Builder::new(out);
+    builder.append(&header, pb.wrap_read(file))?;
+    let out = builder.into_inner()?;
+    pb.finish_and_clear();
+    Ok(out)
+}
+
+fn which(name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|p| p.is_file())
+}
+
+fn progress_bar(size: u64) -> ProgressBar {
+    let pb = ProgressBar::new(size);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{bar:40.cyan/blue}] {bytes}/{total_bytes} {bytes_per_sec} ({eta})")
+            .unwrap()
+            .progress_chars("#>-"),
+    );
+    pb
+}
@@ -0,0 +1,216 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use tracing::info;
+
+use crate::config::Config;
+use crate::sigstore::{self, Bundle, Identity, TrustRoot};
+use super::cache::{self, CachedDisk};
+use super::download;
+
+const PROVENANCE_ASSET: &str = "build-provenance.zip";
+const IN_TOTO_PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+
+/// Disk images are built by the release workflow of automata-linux, at any ref.
+const BUILDER_IDENTITY_PREFIX: &str =
+    "https://github.com/automata-network/automata-linux/.github/workflows/release.yml@";
+
+/// in-toto statement carried in the bundle's DSSE payload.
+#[derive(Debug, Deserialize)]
+struct Statement {
+    subject: Vec<Subject>,
+    #[serde(rename = "predicateType", default)]
+    predicate_type: String,
+    #[serde(default)]
+    predicate: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct Subject {
+    name: String,
+    digest: std::collections::HashMap<String, String>,
+}
+
+/// Summary of verified SLSA provenance.
+#[derive(Debug)]
+pub struct Provenance {
+    pub tag: String,
+    pub sha256: String,
+    pub signer: String,
+    /// When the signature was logged in Rekor (RFC 3339).
+    pub signed_at: String,
+    pub predicate_type: String,
+    pub repository: Option<String>,
+    pub git_ref: Option<String>,
+    pub commit: Option<String>,
+    pub workflow: Option<String>,
+    pub builder: Option<String>,
+    pub started_on: Option<String>,
+    pub log_index: i64,
+}
+
+/// Bundle for a disk image, stored next to it in the tag cache.
+pub fn bundle_path(disk: &CachedDisk) -> PathBuf {
+    let mut name = disk.path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bundle");
+    disk.path.with_file_name(name)
+}
+
+/// Download `build-provenance.zip` for the configured release and store the
+/// bundle for this CSP's disk in the tag cache. Returns the bundle path.
+pub fn fetch(config: &Config) -> Result<PathBuf> {
+    let filename = config.disk_filename();
+    let client = reqwest::blocking::Client::builder()
+        .timeout(std::time::Duration::from_secs(300))
+        .build()?;
+
+    let release = download::fetch_release(&client, &config.release_tag)?;
+    let tag = &release.tag_name;
+    let dest = cache::tag_dir(tag)?.join(format!("{}.bundle", filename));
+
+    let asset = release.assets.iter()
+        .find(|a| a.name == PROVENANCE_ASSET)
+        .with_context(|| format!("Release {} has no {}", tag, PROVENANCE_ASSET))?;
+
+    info!(tag, "Downloading build provenance...");
+    let bytes = download::github_get(&client, &asset.browser_download_url)
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.bytes())
+        .with_context(|| format!("Failed to download {}", PROVENANCE_ASSET))?;
+
+    // The bundle sits at the archive root or under build-provenance/
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes))
+        .with_context(|| format!("Invalid {}", PROVENANCE_ASSET))?;
+    let bundle_name = format!("{}.bundle", filename);
+    let index = (0..archive.len())
+        .find(|&i| {
+            archive.by_index(i).ok()
+                .and_then(|f| f.enclosed_name())
+                .is_some_and(|p| p.file_name().is_some_and(|n| n == bundle_name.as_str()))
+        })
+        .with_context(|| format!("{} has no {}", PROVENANCE_ASSET, bundle_name))?;
+
+    let mut content = Vec::new();
+    archive.by_index(index)?.read_to_end(&mut content)?;
+    fs::write(&dest, content)
+        .with_context(|| format!("Failed to write {}", dest.display()))?;
+
+    info!(path = %dest.display(), "Build provenance saved");
+    Ok(dest)
+}
+
+/// Verify the cached disk against its provenance bundle, offline, with the
+/// Sigstore trust root at `trust_root`.
+pub fn verify(disk: &CachedDisk, trust_root: &Path) -> Result<Provenance> {
+    let bundle_file = bundle_path(disk);
+    if !bundle_file.exists() {
+        bail!(
+            "No build provenance for release {} (expected {}). Run: toolkit provenance fetch",
+            disk.tag,
+            bundle_file.display()
+        );
+    }
+
+    let root = TrustRoot::load(trust_root)?;
+    let bundle = Bundle::load(&bundle_file)?;
+    let verified = bundle
+        .verify(&root, &Identity {
+            san_prefix: BUILDER_IDENTITY_PREFIX,
+            issuer: sigstore::GITHUB_ACTIONS_ISSUER,
+        })
+        .with_context(|| format!("Build provenance signature invalid: {}", bundle_file.display()))?;
+
+    if bundle.payload_type != IN_TOTO_PAYLOAD_TYPE {
+        bail!("Unexpected provenance payload type: {}", bundle.payload_type);
+    }
+    let statement: Statement = serde_json::from_slice(&bundle.payload)
+        .context("Provenance payload is not an in-toto statement")?;
+
+    // Hash the disk itself: the cache manifest only rehashes when the mtime changes
+    info!(path = %disk.path.display(), "Hashing cached disk...");
+    let sha256 = cache::sha256_file(&disk.path)?;
+
+    let filename = disk.path.file_name().unwrap_or_default().to_string_lossy();
+    let subject = find_subject(&statement.subject, &filename)
+        .with_context(|| format!("Provenance has no subject for {}", filename))?;
+    let expected = subject.digest.get("sha256")
+        .with_context(|| format!("Provenance subject {} has no sha256 digest", subject.name))?;
+    if !expected.eq_ignore_ascii_case(&sha256) {
+        bail!(
+            "Disk image does not match its build provenance: expected sha256 {}, cached disk is {}",
+            expected,
+            sha256
+        );
+    }
+
+    let p = &statement.predicate;
+    let field = |v: &serde_json::Value| v.as_str().map(String::from);
+    Ok(Provenance {
+        tag: disk.tag.clone(),
+        sha256,
+        signer: verified.signer,
+        signed_at: chrono::DateTime::from_timestamp(verified.integrated_time, 0)
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_default(),
+        predicate_type: statement.predicate_type,
+        repository: field(&p["buildDefinition"]["externalParameters"]["repository"])
+            .or_else(|| field(&p["buildDefinition"]["externalParameters"]["workflow"]["repository"])),
+        git_ref: field(&p["buildDefinition"]["externalParameters"]["ref"])
+            .or_else(|| field(&p["buildDefinition"]["externalParameters"]["workflow"]["ref"])),
+        commit: field(&p["buildDefinition"]["resolvedDependencies"][0]["digest"]["gitCommit"]),
+        workflow: field(&p["buildDefinition"]["externalParameters"]["workflow"])
+            .or_else(|| field(&p["buildDefinition"]["externalParameters"]["workflow"]["path"])),
+        builder: field(&p["runDetails"]["builder"]["id"]),
+        started_on: field(&p["runDetails"]["metadata"]["startedOn"]),
+        log_index: verified.log_index,
+    })
+}
+
+/// The subject named after the disk file (possibly under a directory).
+fn find_subject<'a>(subjects: &'a [Subject], filename: &str) -> Option<&'a Subject> {
+    subjects
+        .iter()
+        .find(|s| s.name == filename || s.name.ends_with(&format!("/{}", filename)))
+}
+
+/// Gate used by deploy when `require_provenance` is set: fetch the bundle if
+/// missing, then verify.
+pub fn require(config: &Config, disk: &CachedDisk) -> Result<Provenance> {
+    let trust_root = config.trust_root_path()
+        .context("'require_provenance' needs 'trust_root' (a Sigstore trusted_root.json)")?;
+
+    if !bundle_path(disk).exists() {
+        fetch(config)?;
+    }
+    let provenance = verify(disk, &trust_root)
+        .context("Refusing to deploy a disk image without verified build provenance")?;
+    info!(
+        tag = %provenance.tag,
+        signer = %provenance.signer,
+        commit = provenance.commit.as_deref().unwrap_or("unknown"),
+        "Build provenance verified"
+    );
+    Ok(provenance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subject(name: &str) -> Subject {
+        Subject { name: name.to_string(), digest: Default::default() }
+    }
+
+    #[test]
+    fn subject_must_be_named_after_the_disk() {
+        let subjects = [subject("build/gcp_disk.tar.gz"), subject("aws_disk.vmdk")];
+        assert_eq!(find_subject(&subjects, "gcp_disk.tar.gz").unwrap().name, "build/gcp_disk.tar.gz");
+        assert!(find_subject(&subjects, "azure_disk.vhd").is_none());
+        // A lone subject for another file is not accepted either
+        assert!(find_subject(&subjects[1..], "gcp_disk.tar.gz").is_none());
+    }
+}
@@ -0,0 +1,334 @@
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use indicatif::{ProgressBar, ProgressStyle};
+use reqwest::blocking::RequestBuilder;
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+const CHUNK_SIZE: usize = 1 << 20;
+/// Consecutive failed attempts (without progress) before giving up.
+const MAX_ATTEMPTS: u32 = 5;
+/// How often (in bytes per segment) parallel progress is persisted.
+const SAVE_EVERY: u64 = 4 << 20;
+
+/// Byte range of a parallel download, with how much of it is already on disk.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+struct Segment {
+    start: u64,
+    /// Exclusive.
+    end: u64,
+    done: u64,
+}
+
+impl Segment {
+    fn remaining(&self) -> u64 {
+        self.end - self.start - self.done
+    }
+}
+
+enum Failure {
+    /// Network hiccup or 5xx/429: retry from where we are.
+    Transient(anyhow::Error),
+    Fatal(anyhow::Error),
+}
+
+/// Stream `size` bytes into `dest`, resuming whatever is already there.
+///
+/// `request` builds a fresh GET for the resource (headers such as auth included).
+/// With `connections > 1` and a server that honours `Range`, the file is fetched
+/// in that many parallel ranges; their progress is kept in `<dest>.segments` so
+/// an interrupted parallel download resumes too. On error `dest` is left in place
+/// for the next attempt.
+pub fn fetch(
+    request: &(dyn Fn() -> RequestBuilder + Sync),
+    dest: &Path,
+    size: u64,
+    connections: u32,
+) -> Result<()> {
+    let sidecar = sidecar_path(dest);
+
+    let segments = match load_segments(&sidecar, dest, size) {
+        Some(segments) => {
+            info!(segments = segments.len(), "Resuming parallel download");
+            Some(segments)
+        }
+        None => {
+            let _ = fs::remove_file(&sidecar);
+            if connections > 1 && size > 0 && supports_ranges(request) {
+                let segments = new_segments(dest, size, connections)?;
+                // Written up front so a preallocated file is never mistaken for a finished one.
+                save_segments(&sidecar, &segments)?;
+                Some(segments)
+            } else {
+                None
+            }
+        }
+    };
+
+    match segments {
+        Some(segments) => fetch_parallel(request, dest, &sidecar, size, segments),
+        None => fetch_sequential(request, dest, size),
+    }
+}
+
+fn fetch_sequential(
+    request: &(dyn Fn() -> RequestBuilder + Sync),
+    dest: &Path,
+    size: u64,
+) -> Result<()> {
+    let existing = fs::metadata(dest).map(|m| m.len()).unwrap_or(0);
+    let done = if existing <= size { existing } else { 0 };
+    if done > 0 {
+        info!(bytes = done, "Resuming partial download");
+    }
+
+    let state = Mutex::new(vec![Segment { start: 0, end: size, done }]);
+    let pb = progress_bar(size, done);
+    let result = fetch_segment(request, dest, &state, 0, &pb, None);
+    finish(&pb, &result);
+    result
+}
+
+fn fetch_parallel(
+    request: &(dyn Fn() -> RequestBuilder + Sync),
+    dest: &Path,
+    sidecar: &Path,
+    size: u64,
+    segments: Vec<Segment>,
+) -> Result<()> {
+    let done: u64 = segments.iter().map(|s| s.done).sum();
+    let count = segments.len();
+    info!(connections = count, "Downloading in parallel ranges");
+
+    let state = Mutex::new(segments);
+    let pb = progress_bar(size, done);
+
+    let results: Vec<Result<()>> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..count)
+            .map(|index| {
+                let (state, pb) = (&state, &pb);
+                scope.spawn(move || fetch_segment(request, dest, state, index, pb, Some(sidecar)))
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap_or_else(|_| Err(anyhow!("Download thread panicked"))))
+            .collect()
+    });
+
+    let result = results.into_iter().collect::<Result<Vec<()>>>().map(|_| ());
+    match &result {
+        Ok(()) => {
+            let _ = fs::remove_file(sidecar);
+        }
+        Err(_) => save_segments(sidecar, &state.lock().unwrap())?,
+    }
+    finish(&pb, &result);
+    result
+}
+
+/// Download one segment with retries. Each retry resumes at the first missing byte.
+fn fetch_segment(
+    request: &(dyn Fn() -> RequestBuilder + Sync),
+    dest: &Path,
+    state: &Mutex<Vec<Segment>>,
+    index: usize,
+    pb: &ProgressBar,
+    sidecar: Option<&Path>,
+) -> Result<()> {
+    let mut failures = 0;
+    loop {
+        let before = state.lock().unwrap()[index].done;
+        match try_segment(request, dest, state, index, pb, sidecar) {
+            Ok(()) => return Ok(()),
+            Err(Failure::Fatal(e)) => return Err(e),
+            Err(Failure::Transient(e)) => {
+                let progressed = state.lock().unwrap()[index].done > before;
+                failures = if progressed { 1 } else { failures + 1 };
+                if failures >= MAX_ATTEMPTS {
+                    return Err(e.context(format!("Download failed after {} attempts", MAX_ATTEMPTS)));
+                }
+                let delay = Duration::from_secs(1 << failures.min(5));
+                warn!(error = %format!("{:#}", e), retry_in = ?delay, "Download interrupted, retrying");
+                std::thread::sleep(delay);
+            }
+        }
+    }
+}
+
+fn try_segment(
+    request: &(dyn Fn() -> RequestBuilder + Sync),
+    dest: &Path,
+    state: &Mutex<Vec<Segment>>,
+    index: usize,
+    pb: &ProgressBar,
+    sidecar: Option<&Path>,
+) -> std::result::Result<(), Failure> {
+    let seg = state.lock().unwrap()[index];
+    if seg.remaining() == 0 {
+        return Ok(());
+    }
+    let from = seg.start + seg.done;
+    let ranged = sidecar.is_some() || from > 0;
+
+    let mut req = request();
+    if ranged {
+        req = req.header(RANGE, format!("bytes={}-{}", from, seg.end - 1));
+    }
+    let mut resp = req.send().map_err(|e| Failure::Transient(e.into()))?;
+
+    let status = resp.status();
+    let mut offset = from;
+    match status {
+        StatusCode::PARTIAL_CONTENT if ranged => {}
+        StatusCode::OK if !ranged => {}
+        StatusCode::OK | StatusCode::RANGE_NOT_SATISFIABLE if sidecar.is_none() => {
+            // Server ignored or rejected the resume: start over from byte 0.
+            warn!(%status, "Server cannot resume this download, restarting");
+            pb.set_position(0);
+            state.lock().unwrap()[index].done = 0;
+            if status != StatusCode::OK {
+                return Err(Failure::Transient(anyhow!("Range not satisfiable")));
+            }
+            offset = 0;
+        }
+        s if s.is_server_error() || s == StatusCode::TOO_MANY_REQUESTS => {
+            return Err(Failure::Transient(anyhow!("Server returned {}", s)));
+        }
+        StatusCode::OK => {
+            return Err(Failure::Fatal(anyhow!("Server ignored the range request for a parallel download")));
+        }
+        s => return Err(Failure::Fatal(anyhow!("Download failed: server returned {}", s))),
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(dest)
+        .and_then(|mut f| {
+            if sidecar.is_none() {
+                f.set_len(offset)?;
+            }
+            f.seek(SeekFrom::Start(offset))?;
+            Ok(f)
+        })
+        .with_context(|| format!("Failed to open {}", dest.display()))
+        .map_err(Failure::Fatal)?;
+
+    let mut remaining = seg.end - offset;
+    let mut unsaved = 0u64;
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    while remaining > 0 {
+        let n = match resp.read(&mut buf) {
+            Ok(0) => return Err(Failure::Transient(anyhow!("Connection closed early"))),
+            Ok(n) => n.min(remaining as usize),
+            Err(e) => return Err(Failure::Transient(e.into())),
+        };
+        file.write_all(&buf[..n])
+            .with_context(|| format!("Failed to write {}", dest.display()))
+            .map_err(Failure::Fatal)?;
+
+        remaining -= n as u64;
+        unsaved += n as u64;
+        pb.inc(n as u64);
+        let mut segments = state.lock().unwrap();
+        segments[index].done += n as u64;
+        if let Some(sidecar) = sidecar {
+            if unsaved >= SAVE_EVERY {
+                unsaved = 0;
+                file.flush().map_err(|e| Failure::Fatal(e.into()))?;
+                save_segments(sidecar, &segments).map_err(Failure::Fatal)?;
+            }
+        }
+    }
+
+    file.flush().map_err(|e| Failure::Fatal(e.into()))?;
+    Ok(())
+}
+
+/// Probe with a one-byte range request.
+fn supports_ranges(request: &(dyn Fn() -> RequestBuilder + Sync)) -> bool {
+    match request().header(RANGE, "bytes=0-0").send() {
+        Ok(resp) if resp.status() == StatusCode::PARTIAL_CONTENT => true,
+        _ => {
+            info!("Server does not support range requests, using a single connection");
+            false
+        }
+    }
+}
+
+fn new_segments(dest: &Path, size: u64, connections: u32) -> Result<Vec<Segment>> {
+    // Preallocate so every range can be written in place.
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(dest)
+        .with_context(|| format!("Failed to create {}", dest.display()))?;
+    file.set_len(size)?;
+
+    let count = u64::from(connections).min(size);
+    let step = size.div_ceil(count);
+    Ok((0..count)
+        .map(|i| Segment {
+            start: i * step,
+            end: ((i + 1) * step).min(size),
+            done: 0,
+        })
+        .filter(|s| s.start < s.end)
+        .collect())
+}
+
+fn sidecar_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".segments");
+    dest.with_file_name(name)
+}
+
+/// Load saved segment progress, if it still describes `dest`.
+fn load_segments(sidecar: &Path, dest: &Path, size: u64) -> Option<Vec<Segment>> {
+    let segments: Vec<Segment> = serde_json::from_str(&fs::read_to_string(sidecar).ok()?).ok()?;
+    let len = fs::metadata(dest).ok()?.len();
+
+    let mut expected_start = 0;
+    for seg in &segments {
+        if seg.start != expected_start || seg.end <= seg.start || seg.done > seg.end - seg.start {
+            return None;
+        }
+        expected_start = seg.end;
+    }
+    (expected_start == size && len == size).then_some(segments)
+}
+
+fn save_segments(sidecar: &Path, segments: &[Segment]) -> Result<()> {
+    fs::write(sidecar, serde_json::to_string(segments)?)
+        .with_context(|| format!("Failed to write {}", sidecar.display()))
+}
+
+fn progress_bar(size: u64, position: u64) -> ProgressBar {
+    let pb = ProgressBar::new(size);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{bar:40.cyan/blue}] {bytes}/{total_bytes} {bytes_per_sec} ({eta})")
+            .unwrap()
+            .progress_chars("#>-"),
+    );
+    pb.set_position(position);
+    pb.reset_eta();
+    pb
+}
+
+fn finish(pb: &ProgressBar, result: &Result<()>) {
+    match result {
+        Ok(()) => pb.finish_with_message("Downloaded"),
+        Err(_) => pb.abandon(),
+    }
+}
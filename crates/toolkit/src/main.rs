@@ -8,7 +8,10 @@ mod agent;
 mod cloud;
 mod commands;
 mod config;
+mod controller;
 mod disk;
+mod secret_ref;
+mod sigstore;
 mod state;
 mod types;
 mod workload;
@@ -34,6 +37,38 @@ enum Commands {
         /// Path to cvm.yaml config file
         #[arg(long, short)]
         config: PathBuf,
+
+        /// Deploy a disk artifact from `toolkit build` instead of preparing one
+        #[arg(long)]
+        image: Option<PathBuf>,
+
+        /// Deploy the artifact to another VM than it was built for, or one more
+        /// VM. They share its API token and controller secrets.
+        #[arg(long, requires = "image")]
+        allow_shared_artifact: bool,
+
+        /// Pin compose images to their registry digests (image@sha256:...)
+        #[arg(long)]
+        pin_digests: bool,
+    },
+
+    /// Build a prepared disk artifact to deploy later
+    Build {
+        /// Path to cvm.yaml config file
+        #[arg(long, short)]
+        config: PathBuf,
+
+        /// Output artifact path (.tar.gz)
+        #[arg(long, short)]
+        output: PathBuf,
+
+        /// Reuse the API token and secrets of an earlier artifact to reproduce it
+        #[arg(long)]
+        secrets_from: Option<PathBuf>,
+
+        /// Pin compose images to their registry digests (image@sha256:...)
+        #[arg(long)]
+        pin_digests: bool,
     },
 
     /// Update workload on a running CVM
@@ -41,6 +76,42 @@ enum Commands {
         /// Path to cvm.yaml config file
         #[arg(long, short)]
         config: PathBuf,
+
+        /// Show what changes since the last upload and ask before uploading
+        #[arg(long)]
+        diff: bool,
+
+        /// Don't ask for confirmation
+        #[arg(long, short)]
+        yes: bool,
+
+        /// Switch the controller to maintenance mode during the update and back
+        /// to tool-node mode once the workload is up again
+        #[arg(long)]
+        maintenance: bool,
+
+        /// Pin compose images to their registry digests (image@sha256:...)
+        #[arg(long)]
+        pin_digests: bool,
+    },
+
+    /// Re-upload a previously uploaded workload to a running CVM
+    Rollback {
+        /// Path to cvm.yaml config file
+        #[arg(long, short)]
+        config: PathBuf,
+
+        /// Workload version to restore (default: the one before the current)
+        #[arg(long)]
+        to: Option<u32>,
+
+        /// List the archived workload versions instead
+        #[arg(long, conflicts_with = "to")]
+        list: bool,
+
+        /// Switch the controller to maintenance mode during the rollback and back
+        #[arg(long, conflicts_with = "list")]
+        maintenance: bool,
     },
 
     /// Destroy a deployed CVM and all its resources
@@ -67,6 +138,30 @@ enum Commands {
         config: PathBuf,
     },
 
+    /// Query and switch the controller's mode (tool-node / internet)
+    Controller {
+        #[command(subcommand)]
+        action: ControllerAction,
+    },
+
+    /// Fetch or verify SLSA build provenance for the disk image
+    Provenance {
+        #[command(subcommand)]
+        action: ProvenanceAction,
+    },
+
+    /// Inspect and manage the local disk image cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+
+    /// Look inside disk images without booting them
+    Disk {
+        #[command(subcommand)]
+        action: DiskAction,
+    },
+
     /// Generate a config file template
     Init {
         /// Cloud service provider
@@ -80,9 +175,175 @@ enum Commands {
 
     /// Start a simulated CVM agent for local development
     SimAgent {
-        /// Port to listen on
+        /// Port for the internal agent API (plain HTTP)
         #[arg(long, default_value = "7999")]
         port: u16,
+
+        /// Port for the management API (HTTPS, self-signed)
+        #[arg(long, default_value = "8000")]
+        tls_port: u16,
+
+        /// API token clients must present (random if omitted)
+        #[arg(long)]
+        token: Option<String>,
+
+        /// Register the sim-agent as the deployment for this cvm.yaml
+        #[arg(long, short)]
+        config: Option<PathBuf>,
+
+        /// Run uploaded workloads on this host with `docker compose up`
+        #[arg(long)]
+        run_workload: bool,
+
+        /// Signing session lifetime in seconds before the key is rotated
+        #[arg(long, default_value = "3600")]
+        session_ttl: u64,
+
+        /// Inject a fault as ROUTE=SPEC, e.g. "/container-logs=status=500,count=2",
+        /// "*=delay=3000" or "/update-workload=reset" (repeatable)
+        #[arg(long = "fault", value_name = "ROUTE=SPEC")]
+        faults: Vec<String>,
+
+        /// Report "not ready" (503) for this many seconds after startup
+        #[arg(long, default_value = "0")]
+        boot_delay: u64,
+
+        /// TEE platform to simulate attestation evidence for
+        #[arg(long, value_enum, default_value = "tdx")]
+        tee: commands::sim_agent::Platform,
+
+        /// YAML file with firmware measurements (mrtd, rtmr0-2, measurement, ...)
+        #[arg(long)]
+        firmware: Option<PathBuf>,
+
+        /// Proxy to the real CVM agent at this IP and record request/response pairs
+        #[arg(long, value_name = "IP", conflicts_with = "replay")]
+        record: Option<String>,
+
+        /// File to write the recording to (with --record)
+        #[arg(long, default_value = "agent-recording.json")]
+        recording: PathBuf,
+
+        /// Serve responses from a recording made with --record
+        #[arg(long, value_name = "FILE")]
+        replay: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ControllerAction {
+    /// Show the controller URL and current mode
+    Status {
+        /// Path to cvm.yaml config file
+        #[arg(long, short)]
+        config: PathBuf,
+    },
+
+    /// Print the current mode (tool-node, internet, error or switching)
+    Mode {
+        /// Path to cvm.yaml config file
+        #[arg(long, short)]
+        config: PathBuf,
+    },
+
+    /// Enter or leave maintenance (internet) mode
+    Maintenance {
+        #[command(subcommand)]
+        action: MaintenanceAction,
+    },
+
+    /// Store the controller API key for the VM (read from stdin)
+    SetKey {
+        /// Path to cvm.yaml config file
+        #[arg(long, short)]
+        config: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum MaintenanceAction {
+    /// Switch to internet mode (WAN and SSH allowed, API feed stopped)
+    Enter {
+        /// Path to cvm.yaml config file
+        #[arg(long, short)]
+        config: PathBuf,
+    },
+
+    /// Switch back to tool-node mode
+    Leave {
+        /// Path to cvm.yaml config file
+        #[arg(long, short)]
+        config: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProvenanceAction {
+    /// Download the build provenance bundle for the configured release
+    Fetch {
+        /// Path to cvm.yaml config file
+        #[arg(long, short)]
+        config: PathBuf,
+    },
+
+    /// Verify the cached disk image against its build provenance (offline)
+    Verify {
+        /// Path to cvm.yaml config file
+        #[arg(long, short)]
+        config: PathBuf,
+
+        /// Sigstore trusted_root.json (defaults to `trust_root` in the config)
+        #[arg(long)]
+        trust_root: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum DiskAction {
+    /// Show partitions, boot kernel/cmdline, token hash and workload of an image
+    Inspect {
+        /// Disk image (.tar.gz or raw)
+        #[arg(required_unless_present = "tag")]
+        image: Option<PathBuf>,
+
+        /// Inspect the cached image of a release instead (the last prepared disk if any)
+        #[arg(long, conflicts_with = "image")]
+        tag: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// List cached releases with size, verification status and last use
+    List {
+        /// Re-hash every cached disk instead of trusting size and mtime
+        #[arg(long)]
+        verify: bool,
+    },
+
+    /// Remove all but the most recently used releases
+    Prune {
+        /// Number of releases to keep
+        #[arg(long, default_value = "1")]
+        keep: usize,
+    },
+
+    /// Remove everything from the disk cache
+    Clear,
+
+    /// Download a release's disk image into the cache (e.g. to warm CI images)
+    Prefetch {
+        /// Release tag (defaults to the config's release_tag, else latest)
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// Cloud provider whose disk image to fetch (defaults to the config's csp, else gcp)
+        #[arg(long)]
+        csp: Option<String>,
+
+        /// Path to cvm.yaml config file
+        #[arg(long, short)]
+        config: Option<PathBuf>,
     },
 }
 
@@ -97,13 +358,28 @@ fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Deploy { config } => {
-            let cfg = Config::load(&config)?;
-            commands::deploy::run(cfg)
+        Commands::Deploy { config, image, allow_shared_artifact, pin_digests } => {
+            let mut cfg = Config::load(&config)?;
+            cfg.pin_digests |= pin_digests;
+            commands::deploy::run(cfg, image, allow_shared_artifact)
+        }
+        Commands::Build { config, output, secrets_from, pin_digests } => {
+            let mut cfg = Config::load(&config)?;
+            cfg.pin_digests |= pin_digests;
+            commands::build::run(cfg, output, secrets_from)
+        }
+        Commands::Update { config, pin_digests, diff, yes, maintenance } => {
+            let mut cfg = Config::load(&config)?;
+            cfg.pin_digests |= pin_digests;
+            commands::update::run(cfg, diff, yes, maintenance)
         }
-        Commands::Update { config } => {
+        Commands::Rollback { config, to, list, maintenance } => {
             let cfg = Config::load(&config)?;
-            commands::update::run(cfg)
+            if list {
+                commands::rollback::list(cfg)
+            } else {
+                commands::rollback::run(cfg, to, maintenance)
+            }
         }
         Commands::Destroy { config } => {
             let cfg = Config::load(&config)?;
@@ -117,11 +393,86 @@ fn main() -> Result<()> {
             let cfg = Config::load(&config)?;
             commands::measurements::run(cfg)
         }
+        Commands::Provenance { action } => match action {
+            ProvenanceAction::Fetch { config } => {
+                let cfg = Config::load(&config)?;
+                commands::provenance::fetch(cfg)
+            }
+            ProvenanceAction::Verify { config, trust_root } => {
+                let cfg = Config::load(&config)?;
+                commands::provenance::verify(cfg, trust_root)
+            }
+        },
+        Commands::Controller { action } => match action {
+            ControllerAction::Status { config } => {
+                let cfg = Config::load(&config)?;
+                commands::controller::status(cfg)
+            }
+            ControllerAction::Mode { config } => {
+                let cfg = Config::load(&config)?;
+                commands::controller::mode(cfg)
+            }
+            ControllerAction::Maintenance { action } => match action {
+                MaintenanceAction::Enter { config } => {
+                    let cfg = Config::load(&config)?;
+                    commands::controller::maintenance(cfg, true)
+                }
+                MaintenanceAction::Leave { config } => {
+                    let cfg = Config::load(&config)?;
+                    commands::controller::maintenance(cfg, false)
+                }
+            },
+            ControllerAction::SetKey { config } => {
+                let cfg = Config::load(&config)?;
+                commands::controller::set_key(cfg)
+            }
+        },
+        Commands::Cache { action } => match action {
+            CacheAction::List { verify } => commands::cache::list(verify),
+            CacheAction::Prune { keep } => commands::cache::prune(keep),
+            CacheAction::Clear => commands::cache::clear(),
+            CacheAction::Prefetch { tag, csp, config } => {
+                let cfg = config.as_deref().map(Config::load).transpose()?;
+                commands::cache::prefetch(cfg, tag, csp)
+            }
+        },
+        Commands::Disk { action } => match action {
+            DiskAction::Inspect { image, tag } => commands::disk::inspect(image, tag),
+        },
         Commands::Init { csp, output } => {
             commands::init::run(&csp, &output)
         }
-        Commands::SimAgent { port } => {
-            commands::sim_agent::run(port)
+        Commands::SimAgent {
+            port,
+            tls_port,
+            token,
+            config,
+            run_workload,
+            session_ttl,
+            faults,
+            boot_delay,
+            tee,
+            firmware,
+            record,
+            recording,
+            replay,
+        } => {
+            let config = config.as_deref().map(Config::load).transpose()?;
+            commands::sim_agent::run(commands::sim_agent::Options {
+                port,
+                tls_port,
+                token,
+                config,
+                run_workload,
+                session_ttl,
+                faults,
+                boot_delay,
+                tee,
+                firmware,
+                record,
+                recording,
+                replay,
+            })
         }
     }
 }
@@ -0,0 +1,113 @@
+//! Secret references in cvm.yaml: `env`, `secret_files` and similar values of
+//! the form `<scheme>:<reference>` are resolved when the workload is built,
+//! so the config itself can be committed.
+//!
+//! - `env:NAME` - environment variable of the toolkit process
+//! - `file:path` - file content
+//! - `cmd:command` - stdout of `sh -c command`
+//! - `gcpsm:secret[/versions/N]` - GCP Secret Manager (project from the config),
+//!   or `gcpsm:projects/P/secrets/S[/versions/N]`
+
+use std::collections::HashMap;
+use std::fs;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+
+use crate::cloud;
+
+const SCHEMES: [&str; 4] = ["env:", "file:", "cmd:", "gcpsm:"];
+
+/// Points Secret Manager lookups at a local YAML map of
+/// `projects/P/secrets/S/versions/V` to values, for tests and offline use.
+const FAKE_GCPSM_ENV: &str = "TOOLKIT_FAKE_GCPSM";
+
+/// Whether a config value is a secret reference rather than a literal.
+pub fn is_reference(value: &str) -> bool {
+    SCHEMES.iter().any(|s| value.starts_with(s))
+}
+
+/// Resolve a value for `.env`: references are replaced by their content (one
+/// trailing newline dropped), literals are returned as is.
+pub fn resolve(value: &str, project_id: Option<&str>) -> Result<String> {
+    if !is_reference(value) {
+        return Ok(value.to_string());
+    }
+    let bytes = resolve_bytes(value, project_id)?;
+    let text = String::from_utf8(bytes).with_context(|| format!("{} is not UTF-8", describe(value)))?;
+    let text = text.strip_suffix('\n').unwrap_or(&text);
+    Ok(text.strip_suffix('\r').unwrap_or(text).to_string())
+}
+
+/// Resolve a reference to its raw content.
+pub fn resolve_bytes(value: &str, project_id: Option<&str>) -> Result<Vec<u8>> {
+    let (scheme, reference) = value.split_once(':').context("Not a secret reference")?;
+    match scheme {
+        "env" => std::env::var(reference)
+            .map(String::into_bytes)
+            .with_context(|| format!("Environment variable {} is not set", reference)),
+        "file" => {
+            let path = shellexpand::tilde(reference).to_string();
+            fs::read(&path).with_context(|| format!("Failed to read secret file {}", path))
+        }
+        "cmd" => run(reference),
+        "gcpsm" => {
+            let name = secret_version_name(reference, project_id)?;
+            match std::env::var(FAKE_GCPSM_ENV) {
+                Ok(fake) => fake_secret(&fake, &name),
+                Err(_) => cloud::gcp::access_secret(&name),
+            }
+        }
+        other => bail!("Unknown secret reference scheme '{}'", other),
+    }
+}
+
+/// What a reference points at, for messages (never the resolved value).
+pub fn describe(value: &str) -> String {
+    match value.split_once(':') {
+        Some(("cmd", _)) => "secret command".to_string(),
+        _ => format!("secret {}", value),
+    }
+}
+
+fn run(command: &str) -> Result<Vec<u8>> {
+    let output = Command::new("sh")
+        .args(["-c", command])
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .context("Failed to run secret command")?;
+    if !output.status.success() {
+        bail!("Secret command failed ({})", output.status);
+    }
+    Ok(output.stdout)
+}
+
+/// Full Secret Manager version name (`latest` unless given).
+fn secret_version_name(reference: &str, project_id: Option<&str>) -> Result<String> {
+    let name = if reference.starts_with("projects/") {
+        reference.to_string()
+    } else {
+        let project = project_id
+            .filter(|p| !p.is_empty())
+            .context("gcpsm: references without projects/<project>/ need 'project_id'")?;
+        format!("projects/{}/secrets/{}", project, reference)
+    };
+    let parts: Vec<&str> = name.split('/').collect();
+    match parts.as_slice() {
+        ["projects", _, "secrets", _] => Ok(format!("{}/versions/latest", name)),
+        ["projects", _, "secrets", _, "versions", _] => Ok(name),
+        _ => bail!("Invalid gcpsm: reference '{}'", reference),
+    }
+}
+
+fn fake_secret(path: &str, name: &str) -> Result<Vec<u8>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read fake Secret Manager file {}", path))?;
+    let secrets: HashMap<String, String> = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse fake Secret Manager file {}", path))?;
+    secrets
+        .get(name)
+        .map(|v| v.clone().into_bytes())
+        .with_context(|| format!("Secret {} not found in {}", name, path))
+}
@@ -0,0 +1,704 @@
+//! Offline verification of Sigstore (cosign / GitHub attestation) bundles and
+//! cosign image signatures against a supplied `trusted_root.json`.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::DateTime;
+use ring::signature::{self, UnparsedPublicKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use x509_parser::prelude::*;
+use x509_parser::x509::SubjectPublicKeyInfo;
+
+/// OIDC issuer of GitHub Actions workflow identities.
+pub const GITHUB_ACTIONS_ISSUER: &str = "https://token.actions.githubusercontent.com";
+
+const OID_EC_P256: &str = "1.2.840.10045.3.1.7";
+const OID_EC_P384: &str = "1.3.132.0.34";
+/// Fulcio OIDC issuer extension (raw string, deprecated).
+const OID_FULCIO_ISSUER_V1: &str = "1.3.6.1.4.1.57264.1.1";
+/// Fulcio OIDC issuer extension (DER UTF8String).
+const OID_FULCIO_ISSUER_V2: &str = "1.3.6.1.4.1.57264.1.8";
+
+// ---------------------------------------------------------------------------
+// Trusted root
+// ---------------------------------------------------------------------------
+
+/// Sigstore trusted root (`trusted_root.json` from sigstore/root-signing or
+/// `cosign trusted-root create`). Only the CA and transparency log keys are used.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustRoot {
+    #[serde(default)]
+    tlogs: Vec<TransparencyLog>,
+    #[serde(default)]
+    certificate_authorities: Vec<CertificateAuthority>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TransparencyLog {
+    public_key: TrustedKey,
+    log_id: LogId,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TrustedKey {
+    /// Base64 DER SubjectPublicKeyInfo.
+    raw_bytes: String,
+    #[serde(default)]
+    valid_for: Option<ValidFor>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LogId {
+    key_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CertificateAuthority {
+    cert_chain: CertChain,
+    #[serde(default)]
+    valid_for: Option<ValidFor>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CertChain {
+    certificates: Vec<RawBytes>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawBytes {
+    raw_bytes: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ValidFor {
+    #[serde(default)]
+    start: Option<String>,
+    #[serde(default)]
+    end: Option<String>,
+}
+
+impl ValidFor {
+    fn contains(&self, at: i64) -> bool {
+        let ts = |s: &Option<String>| {
+            s.as_deref()
+                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                .map(|t| t.timestamp())
+        };
+        ts(&self.start).is_none_or(|start| at >= start) && ts(&self.end).is_none_or(|end| at <= end)
+    }
+}
+
+fn valid_at(valid_for: &Option<ValidFor>, at: i64) -> bool {
+    valid_for.as_ref().is_none_or(|v| v.contains(at))
+}
+
+impl TrustRoot {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read trust root: {}", path.display()))?;
+        let root: TrustRoot = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse trust root: {}", path.display()))?;
+        if root.certificate_authorities.is_empty() || root.tlogs.is_empty() {
+            bail!(
+                "Trust root {} has no certificate authorities or transparency logs",
+                path.display()
+            );
+        }
+        Ok(root)
+    }
+
+    /// Check `leaf` chains to one of the trusted CAs valid at `at`.
+    fn verify_chain(&self, leaf: &X509Certificate, at: i64) -> Result<()> {
+        for ca in self.certificate_authorities.iter().filter(|ca| valid_at(&ca.valid_for, at)) {
+            let ders: Vec<Vec<u8>> = match ca.cert_chain.certificates.iter()
+                .map(|c| BASE64.decode(&c.raw_bytes))
+                .collect()
+            {
+                Ok(ders) => ders,
+                Err(_) => continue,
+            };
+            let chain: Vec<X509Certificate> = match ders.iter()
+                .map(|der| X509Certificate::from_der(der).map(|(_, c)| c))
+                .collect()
+            {
+                Ok(chain) => chain,
+                Err(_) => continue,
+            };
+            if chain_verifies(leaf, &chain) {
+                return Ok(());
+            }
+        }
+        bail!("Signing certificate does not chain to a trusted certificate authority")
+    }
+
+    fn tlog_key(&self, log_id_hex: &str, at: i64) -> Result<&TrustedKey> {
+        self.tlogs
+            .iter()
+            .find(|t| {
+                BASE64.decode(&t.log_id.key_id).map(hex::encode).ok().as_deref() == Some(log_id_hex)
+                    && valid_at(&t.public_key.valid_for, at)
+            })
+            .map(|t| &t.public_key)
+            .with_context(|| format!("Transparency log {} is not in the trust root", log_id_hex))
+    }
+}
+
+impl TrustRoot {
+    /// Check Rekor's signed entry timestamp: the entry was logged at `integrated_time`.
+    fn verify_tlog(&self, tlog: &TlogEntry) -> Result<()> {
+        let tlog_key = self.tlog_key(&tlog.log_id_hex, tlog.integrated_time)?;
+        let set = serde_json::to_vec(&SetPayload {
+            body: &tlog.body,
+            integrated_time: tlog.integrated_time,
+            log_id: &tlog.log_id_hex,
+            log_index: tlog.log_index,
+        })?;
+        let spki_der = BASE64.decode(&tlog_key.raw_bytes).context("Invalid transparency log key")?;
+        let (_, spki) = SubjectPublicKeyInfo::from_der(&spki_der)
+            .map_err(|e| anyhow::anyhow!("Invalid transparency log key: {}", e))?;
+        verify_ecdsa(&spki, &set, &tlog.signed_entry_timestamp)
+            .context("Rekor signed entry timestamp does not verify")
+    }
+
+    /// Check a short-lived signing certificate was valid when logged, chains
+    /// to Fulcio and belongs to `identity`. Returns the signer.
+    fn verify_signer(&self, cert: &X509Certificate, logged_at: i64, identity: &Identity) -> Result<String> {
+        let validity = cert.validity();
+        if logged_at < validity.not_before.timestamp() || logged_at > validity.not_after.timestamp() {
+            bail!("Signing certificate was not valid when the signature was logged");
+        }
+        self.verify_chain(cert, logged_at)?;
+
+        let signer = san(cert).context("Signing certificate has no URI or email SAN")?;
+        let issuer = oidc_issuer(cert).context("Signing certificate has no OIDC issuer")?;
+        if issuer != identity.issuer {
+            bail!("Signed via OIDC issuer '{}', expected '{}'", issuer, identity.issuer);
+        }
+        if !signer.starts_with(identity.san_prefix) {
+            bail!("Signed by '{}', expected an identity under '{}'", signer, identity.san_prefix);
+        }
+        Ok(signer)
+    }
+}
+
+/// `chain` is ordered issuer-first (intermediate, ..., root) as in trusted_root.json.
+fn chain_verifies(leaf: &X509Certificate, chain: &[X509Certificate]) -> bool {
+    let mut child = leaf;
+    for issuer in chain {
+        if child.issuer() != issuer.subject() || child.verify_signature(Some(issuer.public_key())).is_err() {
+            return false;
+        }
+        child = issuer;
+    }
+    !chain.is_empty()
+}
+
+// ---------------------------------------------------------------------------
+// Bundles
+// ---------------------------------------------------------------------------
+
+/// Who is expected to have signed.
+pub struct Identity<'a> {
+    /// Prefix the certificate's URI/email SAN must start with.
+    pub san_prefix: &'a str,
+    pub issuer: &'a str,
+}
+
+/// A DSSE-signed payload with its signing certificate and Rekor entry,
+/// normalised from either the legacy cosign bundle or the Sigstore bundle format.
+pub struct Bundle {
+    pub payload_type: String,
+    pub payload: Vec<u8>,
+    signature: Vec<u8>,
+    cert_der: Vec<u8>,
+    tlog: TlogEntry,
+}
+
+struct TlogEntry {
+    body: String,
+    integrated_time: i64,
+    log_index: i64,
+    log_id_hex: String,
+    signed_entry_timestamp: Vec<u8>,
+}
+
+/// Result of a successful verification.
+#[derive(Debug)]
+pub struct Verified {
+    pub signer: String,
+    pub integrated_time: i64,
+    pub log_index: i64,
+}
+
+/// Rekor's signed entry timestamp covers this, serialised as canonical JSON
+/// (keys sorted, no whitespace).
+#[derive(Serialize)]
+struct SetPayload<'a> {
+    body: &'a str,
+    #[serde(rename = "integratedTime")]
+    integrated_time: i64,
+    #[serde(rename = "logID")]
+    log_id: &'a str,
+    #[serde(rename = "logIndex")]
+    log_index: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DsseEnvelope {
+    payload_type: String,
+    payload: String,
+    signatures: Vec<DsseSignature>,
+}
+
+#[derive(Deserialize)]
+struct DsseSignature {
+    sig: String,
+}
+
+impl Bundle {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read bundle: {}", path.display()))?;
+        Self::parse(&content).with_context(|| format!("Invalid bundle: {}", path.display()))
+    }
+
+    pub fn parse(json: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        if value.get("dsseEnvelope").is_some() {
+            Self::parse_sigstore(&value)
+        } else if value.get("base64Signature").is_some() {
+            Self::parse_cosign(&value)
+        } else {
+            bail!("Unrecognised bundle format (expected a DSSE Sigstore or cosign bundle)")
+        }
+    }
+
+    /// `application/vnd.dev.sigstore.bundle+json` (v0.1 - v0.3).
+    fn parse_sigstore(value: &serde_json::Value) -> Result<Self> {
+        let envelope: DsseEnvelope = serde_json::from_value(value["dsseEnvelope"].clone())?;
+        let material = &value["verificationMaterial"];
+        let cert = material["certificate"]["rawBytes"]
+            .as_str()
+            .or_else(|| material["x509CertificateChain"]["certificates"][0]["rawBytes"].as_str())
+            .context("Bundle has no signing certificate")?;
+        let entry = &material["tlogEntries"][0];
+
+        let number = |v: &serde_json::Value| {
+            v.as_i64().or_else(|| v.as_str().and_then(|s| s.parse().ok()))
+        };
+        let tlog = TlogEntry {
+            body: entry["canonicalizedBody"].as_str().context("Bundle has no tlog entry")?.to_string(),
+            integrated_time: number(&entry["integratedTime"]).context("tlog entry has no integratedTime")?,
+            log_index: number(&entry["logIndex"]).context("tlog entry has no logIndex")?,
+            log_id_hex: hex::encode(BASE64.decode(
+                entry["logId"]["keyId"].as_str().context("tlog entry has no logId")?,
+            )?),
+            signed_entry_timestamp: BASE64.decode(
+                entry["inclusionPromise"]["signedEntryTimestamp"]
+                    .as_str()
+                    .context("tlog entry has no inclusion promise")?,
+            )?,
+        };
+
+        Self::from_parts(envelope, BASE64.decode(cert)?, tlog)
+    }
+
+    /// Legacy cosign bundle (`base64Signature`, `cert`, `rekorBundle`).
+    fn parse_cosign(value: &serde_json::Value) -> Result<Self> {
+        let envelope: DsseEnvelope = serde_json::from_slice(&BASE64.decode(
+            value["base64Signature"].as_str().context("Bundle has no signature")?,
+        )?)?;
+
+        let cert_pem = BASE64.decode(value["cert"].as_str().context("Bundle has no signing certificate")?)?;
+        let (_, pem) = x509_parser::pem::parse_x509_pem(&cert_pem)
+            .map_err(|e| anyhow::anyhow!("Invalid certificate PEM: {}", e))?;
+
+        let rekor = &value["rekorBundle"];
+        let payload = &rekor["Payload"];
+        let tlog = TlogEntry {
+            body: payload["body"].as_str().context("Bundle has no Rekor entry")?.to_string(),
+            integrated_time: payload["integratedTime"].as_i64().context("Rekor entry has no integratedTime")?,
+            log_index: payload["logIndex"].as_i64().context("Rekor entry has no logIndex")?,
+            log_id_hex: payload["logID"].as_str().context("Rekor entry has no logID")?.to_string(),
+            signed_entry_timestamp: BASE64.decode(
+                rekor["SignedEntryTimestamp"].as_str().context("Rekor entry has no SignedEntryTimestamp")?,
+            )?,
+        };
+
+        Self::from_parts(envelope, pem.contents, tlog)
+    }
+
+    fn from_parts(envelope: DsseEnvelope, cert_der: Vec<u8>, tlog: TlogEntry) -> Result<Self> {
+        let signature = envelope.signatures.first().context("DSSE envelope has no signatures")?;
+        Ok(Self {
+            payload_type: envelope.payload_type,
+            payload: BASE64.decode(&envelope.payload).context("Invalid DSSE payload")?,
+            signature: BASE64.decode(&signature.sig).context("Invalid DSSE signature")?,
+            cert_der,
+            tlog,
+        })
+    }
+
+    /// Verify the certificate chain and identity, the DSSE signature, and the
+    /// Rekor inclusion promise, all offline against `root`.
+    pub fn verify(&self, root: &TrustRoot, identity: &Identity) -> Result<Verified> {
+        let (_, cert) = X509Certificate::from_der(&self.cert_der)
+            .map_err(|e| anyhow::anyhow!("Invalid signing certificate: {}", e))?;
+
+        // 1. Rekor promise: the entry was logged at integrated_time.
+        root.verify_tlog(&self.tlog)?;
+
+        // 2. The logged entry is for this payload.
+        self.check_tlog_body()?;
+
+        // 3-4. Signing certificate and signer identity.
+        let signer = root.verify_signer(&cert, self.tlog.integrated_time, identity)?;
+
+        // 5. DSSE signature over the payload.
+        verify_ecdsa(cert.public_key(), &dsse_pae(&self.payload_type, &self.payload), &self.signature)
+            .context("DSSE signature does not verify")?;
+
+        Ok(Verified {
+            signer,
+            integrated_time: self.tlog.integrated_time,
+            log_index: self.tlog.log_index,
+        })
+    }
+
+    /// The Rekor entry (intoto or dsse kind) must record our payload hash.
+    fn check_tlog_body(&self) -> Result<()> {
+        let body: serde_json::Value = serde_json::from_slice(&BASE64.decode(&self.tlog.body)?)
+            .context("Invalid Rekor entry body")?;
+        let spec = &body["spec"];
+        let recorded = spec["content"]["payloadHash"]["value"]
+            .as_str()
+            .or_else(|| spec["payloadHash"]["value"].as_str())
+            .with_context(|| format!("Unsupported Rekor entry kind: {}", body["kind"]))?;
+
+        let actual = hex::encode(Sha256::digest(&self.payload));
+        if recorded != actual {
+            bail!("Rekor entry is for a different payload ({} != {})", recorded, actual);
+        }
+        Ok(())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Image signatures
+// ---------------------------------------------------------------------------
+
+/// Cosign signature layer annotations.
+const COSIGN_SIGNATURE: &str = "dev.cosignproject.cosign/signature";
+const COSIGN_CERTIFICATE: &str = "dev.sigstore.cosign/certificate";
+const COSIGN_BUNDLE: &str = "dev.sigstore.cosign/bundle";
+
+/// A cosign image signature: a simple-signing payload signed directly (no
+/// DSSE), as stored in an image's `sha256-<digest>.sig` tag. Keyless
+/// signatures carry the signing certificate and Rekor entry.
+pub struct ImageSignature {
+    pub payload: Vec<u8>,
+    signature: Vec<u8>,
+    cert_der: Option<Vec<u8>>,
+    tlog: Option<TlogEntry>,
+}
+
+impl ImageSignature {
+    /// From a signature layer's blob and its manifest annotations.
+    pub fn from_layer(payload: Vec<u8>, annotations: &serde_json::Value) -> Result<Self> {
+        let signature = BASE64.decode(
+            annotations[COSIGN_SIGNATURE].as_str().context("Layer has no cosign signature")?,
+        )?;
+        let cert_der = match annotations[COSIGN_CERTIFICATE].as_str() {
+            Some(pem) => {
+                let (_, pem) = x509_parser::pem::parse_x509_pem(pem.as_bytes())
+                    .map_err(|e| anyhow::anyhow!("Invalid certificate PEM: {}", e))?;
+                Some(pem.contents)
+            }
+            None => None,
+        };
+        let tlog = match annotations[COSIGN_BUNDLE].as_str() {
+            Some(bundle) => {
+                let rekor: serde_json::Value = serde_json::from_str(bundle).context("Invalid cosign bundle")?;
+                let payload = &rekor["Payload"];
+                Some(TlogEntry {
+                    body: payload["body"].as_str().context("Bundle has no Rekor entry")?.to_string(),
+                    integrated_time: payload["integratedTime"].as_i64().context("Rekor entry has no integratedTime")?,
+                    log_index: payload["logIndex"].as_i64().context("Rekor entry has no logIndex")?,
+                    log_id_hex: payload["logID"].as_str().context("Rekor entry has no logID")?.to_string(),
+                    signed_entry_timestamp: BASE64.decode(
+                        rekor["SignedEntryTimestamp"].as_str().context("Rekor entry has no SignedEntryTimestamp")?,
+                    )?,
+                })
+            }
+            None => None,
+        };
+        Ok(Self { payload, signature, cert_der, tlog })
+    }
+
+    /// Verify against a PEM public key, as `cosign verify --key`.
+    pub fn verify_key(&self, key_pem: &str) -> Result<()> {
+        let (_, pem) = x509_parser::pem::parse_x509_pem(key_pem.as_bytes())
+            .map_err(|e| anyhow::anyhow!("Invalid public key PEM: {}", e))?;
+        let (_, spki) = SubjectPublicKeyInfo::from_der(&pem.contents)
+            .map_err(|e| anyhow::anyhow!("Invalid public key: {}", e))?;
+        verify_ecdsa(&spki, &self.payload, &self.signature).context("Signature does not verify with the key")
+    }
+
+    /// Verify a keyless signature offline: Rekor promise, logged hash and
+    /// signature, certificate chain and identity, then the signature itself.
+    pub fn verify_keyless(&self, root: &TrustRoot, identity: &Identity) -> Result<Verified> {
+        let cert_der = self.cert_der.as_ref().context("Signature has no signing certificate (signed with a key?)")?;
+        let tlog = self.tlog.as_ref().context("Signature has no Rekor bundle")?;
+        let (_, cert) = X509Certificate::from_der(cert_der)
+            .map_err(|e| anyhow::anyhow!("Invalid signing certificate: {}", e))?;
+
+        root.verify_tlog(tlog)?;
+
+        let body: serde_json::Value = serde_json::from_slice(&BASE64.decode(&tlog.body)?)
+            .context("Invalid Rekor entry body")?;
+        let spec = &body["spec"];
+        let recorded = spec["data"]["hash"]["value"]
+            .as_str()
+            .with_context(|| format!("Unsupported Rekor entry kind: {}", body["kind"]))?;
+        if recorded != hex::encode(Sha256::digest(&self.payload)) {
+            bail!("Rekor entry is for a different payload");
+        }
+        if spec["signature"]["content"].as_str() != Some(BASE64.encode(&self.signature).as_str()) {
+            bail!("Rekor entry records a different signature");
+        }
+
+        let signer = root.verify_signer(&cert, tlog.integrated_time, identity)?;
+        verify_ecdsa(cert.public_key(), &self.payload, &self.signature)
+            .context("Signature does not verify with the signing certificate")?;
+
+        Ok(Verified {
+            signer,
+            integrated_time: tlog.integrated_time,
+            log_index: tlog.log_index,
+        })
+    }
+}
+
+/// DSSE pre-authentication encoding.
+fn dsse_pae(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut out = format!("DSSEv1 {} {} {} ", payload_type.len(), payload_type, payload.len()).into_bytes();
+    out.extend_from_slice(payload);
+    out
+}
+
+/// Verify an ASN.1 ECDSA signature with a P-256 or P-384 key.
+fn verify_ecdsa(spki: &SubjectPublicKeyInfo, message: &[u8], sig: &[u8]) -> Result<()> {
+    let curve = spki
+        .algorithm
+        .parameters
+        .as_ref()
+        .and_then(|p| p.as_oid().ok())
+        .map(|oid| oid.to_id_string());
+    let alg: &dyn signature::VerificationAlgorithm = match curve.as_deref() {
+        Some(OID_EC_P256) => &signature::ECDSA_P256_SHA256_ASN1,
+        Some(OID_EC_P384) => &signature::ECDSA_P384_SHA384_ASN1,
+        other => bail!("Unsupported public key (curve {:?})", other),
+    };
+    UnparsedPublicKey::new(alg, &spki.subject_public_key.data)
+        .verify(message, sig)
+        .map_err(|_| anyhow::anyhow!("Bad signature"))
+}
+
+fn san(cert: &X509Certificate) -> Option<String> {
+    let ext = cert.subject_alternative_name().ok()??;
+    ext.value.general_names.iter().find_map(|name| match name {
+        GeneralName::URI(uri) => Some(uri.to_string()),
+        GeneralName::RFC822Name(email) => Some(email.to_string()),
+        _ => None,
+    })
+}
+
+fn oidc_issuer(cert: &X509Certificate) -> Option<String> {
+    cert.extensions().iter().find_map(|ext| match ext.oid.to_id_string().as_str() {
+        OID_FULCIO_ISSUER_V2 => {
+            let (_, any) = x509_parser::der_parser::asn1_rs::Any::from_der(ext.value).ok()?;
+            String::from_utf8(any.data.to_vec()).ok()
+        }
+        OID_FULCIO_ISSUER_V1 => String::from_utf8(ext.value.to_vec()).ok(),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    //! Bundles signed by a throwaway Fulcio CA and Rekor key, with the same
+    //! layout as GitHub's SLSA provenance bundles.
+
+    use super::*;
+    use rcgen::{CertificateParams, CustomExtension, DistinguishedName, IsCa, KeyPair, SanType};
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+    use serde_json::json;
+
+    const SIGNER: &str =
+        "https://github.com/automata-network/automata-linux/.github/workflows/release.yml@refs/tags/v1.0.0";
+    const IDENTITY: Identity = Identity {
+        san_prefix: "https://github.com/automata-network/automata-linux/.github/workflows/release.yml@",
+        issuer: GITHUB_ACTIONS_ISSUER,
+    };
+    const PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+    const INTEGRATED_TIME: i64 = 1_700_000_000;
+
+    struct Fixture {
+        root: String,
+        bundle: serde_json::Value,
+    }
+
+    fn p256() -> (EcdsaKeyPair, KeyPair) {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+        let signing = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        (signing, KeyPair::try_from(pkcs8.as_ref()).unwrap())
+    }
+
+    fn sign(key: &EcdsaKeyPair, message: &[u8]) -> Vec<u8> {
+        key.sign(&SystemRandom::new(), message).unwrap().as_ref().to_vec()
+    }
+
+    /// A trust root and a valid bundle over `payload`, signed as `signer`.
+    fn fixture(payload: &[u8], signer: &str) -> Fixture {
+        let (_, ca_key) = p256();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.distinguished_name = DistinguishedName::new();
+        ca_params.distinguished_name.push(rcgen::DnType::CommonName, "test fulcio");
+        ca_params.is_ca = IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+
+        // Fulcio issuer extension v2 holds a DER UTF8String
+        let mut issuer = vec![0x0c, GITHUB_ACTIONS_ISSUER.len() as u8];
+        issuer.extend_from_slice(GITHUB_ACTIONS_ISSUER.as_bytes());
+        let (leaf_signing, leaf_key) = p256();
+        let mut leaf_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        leaf_params.subject_alt_names = vec![SanType::URI(signer.try_into().unwrap())];
+        leaf_params.custom_extensions =
+            vec![CustomExtension::from_oid_content(&[1, 3, 6, 1, 4, 1, 57264, 1, 8], issuer)];
+        let leaf = leaf_params.signed_by(&leaf_key, &ca, &ca_key).unwrap();
+
+        let (rekor_signing, rekor_key) = p256();
+        let rekor_spki = rekor_key.public_key_der();
+        let log_id = Sha256::digest(&rekor_spki);
+
+        let body = BASE64.encode(serde_json::to_vec(&json!({
+            "kind": "dsse",
+            "spec": { "payloadHash": { "algorithm": "sha256", "value": hex::encode(Sha256::digest(payload)) } }
+        })).unwrap());
+        let set = serde_json::to_vec(&SetPayload {
+            body: &body,
+            integrated_time: INTEGRATED_TIME,
+            log_id: &hex::encode(log_id),
+            log_index: 42,
+        })
+        .unwrap();
+
+        let root = json!({
+            "tlogs": [{
+                "publicKey": { "rawBytes": BASE64.encode(&rekor_spki) },
+                "logId": { "keyId": BASE64.encode(log_id) }
+            }],
+            "certificateAuthorities": [{
+                "certChain": { "certificates": [{ "rawBytes": BASE64.encode(ca.der()) }] }
+            }]
+        });
+        let bundle = json!({
+            "mediaType": "application/vnd.dev.sigstore.bundle.v0.3+json",
+            "dsseEnvelope": {
+                "payloadType": PAYLOAD_TYPE,
+                "payload": BASE64.encode(payload),
+                "signatures": [{ "sig": BASE64.encode(sign(&leaf_signing, &dsse_pae(PAYLOAD_TYPE, payload))) }]
+            },
+            "verificationMaterial": {
+                "certificate": { "rawBytes": BASE64.encode(leaf.der()) },
+                "tlogEntries": [{
+                    "logIndex": "42",
+                    "logId": { "keyId": BASE64.encode(log_id) },
+                    "integratedTime": INTEGRATED_TIME.to_string(),
+                    "inclusionPromise": { "signedEntryTimestamp": BASE64.encode(sign(&rekor_signing, &set)) },
+                    "canonicalizedBody": body
+                }]
+            }
+        });
+        Fixture { root: root.to_string(), bundle }
+    }
+
+    fn verify(fixture: &Fixture, identity: &Identity) -> Result<Verified> {
+        let root: TrustRoot = serde_json::from_str(&fixture.root)?;
+        Bundle::parse(&fixture.bundle.to_string())?.verify(&root, identity)
+    }
+
+    const STATEMENT: &[u8] = br#"{"subject":[{"name":"gcp_disk.tar.gz","digest":{"sha256":"aa"}}]}"#;
+
+    #[test]
+    fn verifies_known_good_bundle() {
+        let verified = verify(&fixture(STATEMENT, SIGNER), &IDENTITY).unwrap();
+        assert_eq!(verified.signer, SIGNER);
+        assert_eq!(verified.integrated_time, INTEGRATED_TIME);
+        assert_eq!(verified.log_index, 42);
+    }
+
+    #[test]
+    fn rejects_tampered_payload() {
+        let mut fixture = fixture(STATEMENT, SIGNER);
+        let tampered = br#"{"subject":[{"name":"gcp_disk.tar.gz","digest":{"sha256":"bb"}}]}"#;
+        fixture.bundle["dsseEnvelope"]["payload"] = json!(BASE64.encode(tampered));
+        let err = verify(&fixture, &IDENTITY).unwrap_err();
+        assert!(format!("{:#}", err).contains("different payload"), "{:#}", err);
+    }
+
+    #[test]
+    fn rejects_tampered_signature() {
+        let mut fixture = fixture(STATEMENT, SIGNER);
+        let other = fixture_signature(b"something else");
+        fixture.bundle["dsseEnvelope"]["signatures"][0]["sig"] = json!(other);
+        let err = verify(&fixture, &IDENTITY).unwrap_err();
+        assert!(format!("{:#}", err).contains("DSSE signature"), "{:#}", err);
+    }
+
+    #[test]
+    fn rejects_tampered_log_entry() {
+        let mut fixture = fixture(STATEMENT, SIGNER);
+        fixture.bundle["verificationMaterial"]["tlogEntries"][0]["integratedTime"] = json!("1700000001");
+        let err = verify(&fixture, &IDENTITY).unwrap_err();
+        assert!(format!("{:#}", err).contains("signed entry timestamp"), "{:#}", err);
+    }
+
+    #[test]
+    fn rejects_other_workflow() {
+        let other = "https://github.com/automata-network/other-repo/.github/workflows/release.yml@refs/heads/main";
+        let err = verify(&fixture(STATEMENT, other), &IDENTITY).unwrap_err();
+        assert!(format!("{:#}", err).contains("expected an identity"), "{:#}", err);
+    }
+
+    #[test]
+    fn rejects_untrusted_ca() {
+        // Same Rekor log, signing certificate from another CA
+        let mut fixture = fixture(STATEMENT, SIGNER);
+        let other: serde_json::Value = serde_json::from_str(&self::fixture(STATEMENT, SIGNER).root).unwrap();
+        let mut root: serde_json::Value = serde_json::from_str(&fixture.root).unwrap();
+        root["certificateAuthorities"] = other["certificateAuthorities"].clone();
+        fixture.root = root.to_string();
+        let err = verify(&fixture, &IDENTITY).unwrap_err();
+        assert!(format!("{:#}", err).contains("does not chain"), "{:#}", err);
+    }
+
+    /// A well-formed signature by an unrelated key.
+    fn fixture_signature(message: &[u8]) -> String {
+        BASE64.encode(sign(&p256().0, message))
+    }
+}
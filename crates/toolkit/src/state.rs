@@ -1,11 +1,25 @@
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
 use chrono::Utc;
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
 use crate::config::Config;
+use crate::controller::secrets::ControllerSecrets;
+use crate::workload::snapshot::WorkloadSnapshot;
+
+/// Archived workload zips kept per VM for `rollback`.
+const KEEP_WORKLOAD_VERSIONS: usize = 10;
+
+/// Per-VM secrets kept next to the state file.
+pub const CONTROLLER_KEY: &str = "controller-key";
+pub const JWT_SECRET: &str = "jwtsecret";
+pub const SNAPSHOT_KEY: &str = "snapshot-key";
+const SECRET_FILES: [&str; 3] = [CONTROLLER_KEY, JWT_SECRET, SNAPSHOT_KEY];
 
 /// Deployment state persisted to disk.
 /// Replaces the _artifacts/ flat file approach.
@@ -41,6 +55,45 @@ pub struct DeployState {
 
     #[serde(default)]
     pub created_at: Option<String>,
+
+    /// Digest-pinned image reference per compose service (`--pin-digests`)
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub image_digests: IndexMap<String, String>,
+
+    /// The workload last put on the VM (by deploy or update)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workload: Option<WorkloadSnapshot>,
+
+    /// Archived workload versions, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub workload_versions: Vec<WorkloadVersion>,
+
+    /// Version currently on the VM
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workload_version: Option<u32>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rollbacks: Vec<Rollback>,
+
+    /// Key the VM's workload secrets were last sealed to (`seal_secrets`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealing_key: Option<String>,
+}
+
+/// A workload put on the VM, archived under `workloads_dir`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WorkloadVersion {
+    pub version: u32,
+    /// Workload measurement (hex SHA-384)
+    pub hash: String,
+    pub uploaded_at: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Rollback {
+    pub from: Option<u32>,
+    pub to: u32,
+    pub at: String,
 }
 
 impl DeployState {
@@ -59,6 +112,12 @@ impl DeployState {
             disk_name: config.attach_disk.clone(),
             static_ip_name: config.create_ip_name.clone(),
             created_at: Some(Utc::now().to_rfc3339()),
+            image_digests: IndexMap::new(),
+            workload: None,
+            workload_versions: Vec::new(),
+            workload_version: None,
+            rollbacks: Vec::new(),
+            sealing_key: None,
         }
     }
 
@@ -68,6 +127,123 @@ impl DeployState {
         Ok(dir.join(format!("{}.yaml", vm_name)))
     }
 
+    /// Where a per-VM secret (see `SECRET_FILES`) is kept, next to the state
+    /// file with owner-only permissions.
+    pub fn secret_path(vm_name: &str, name: &str) -> Result<PathBuf> {
+        Ok(Config::state_dir()?.join(format!("{}.{}", vm_name, name)))
+    }
+
+    pub fn save_secret(vm_name: &str, name: &str, value: &str) -> Result<PathBuf> {
+        let path = Self::secret_path(vm_name, name)?;
+        write_private(&path, format!("{}\n", value).as_bytes())?;
+        Ok(path)
+    }
+
+    /// Forget a per-VM secret.
+    pub fn remove_secret(vm_name: &str, name: &str) -> Result<()> {
+        let path = Self::secret_path(vm_name, name)?;
+        if path.exists() {
+            fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// A stored per-VM secret, if any.
+    pub fn load_secret(vm_name: &str, name: &str) -> Result<Option<String>> {
+        let path = Self::secret_path(vm_name, name)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let value = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(Some(value.trim().to_string()).filter(|v| !v.is_empty()))
+    }
+
+    /// Directory holding the archived workload zips of a VM.
+    pub fn workloads_dir(vm_name: &str) -> Result<PathBuf> {
+        Ok(Config::state_dir()?.join("workloads").join(vm_name))
+    }
+
+    /// Archive path of a workload version.
+    pub fn workload_zip_path(&self, version: u32) -> Result<PathBuf> {
+        Ok(Self::workloads_dir(&self.vm_name)?.join(format!("v{}.zip", version)))
+    }
+
+    /// Where the controller secrets a workload version was provisioned with
+    /// are archived (`name` is one of `SECRET_FILES`).
+    pub fn workload_secret_path(&self, version: u32, name: &str) -> Result<PathBuf> {
+        Ok(Self::workloads_dir(&self.vm_name)?.join(format!("v{}.{}", version, name)))
+    }
+
+    /// Controller secrets archived with a workload version, `None` for
+    /// versions archived without them.
+    pub fn archived_controller_secrets(&self, version: u32) -> Result<Option<ControllerSecrets>> {
+        let read = |name: &str| -> Result<Option<String>> {
+            let path = self.workload_secret_path(version, name)?;
+            if !path.exists() {
+                return Ok(None);
+            }
+            let value = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            Ok(Some(value.trim().to_string()))
+        };
+        match (read(CONTROLLER_KEY)?, read(JWT_SECRET)?) {
+            (Some(api_key), Some(jwt_secret)) => Ok(Some(ControllerSecrets { api_key, jwt_secret })),
+            _ => Ok(None),
+        }
+    }
+
+    /// Archive a workload zip that was just put on the VM, with the controller
+    /// secrets it was provisioned with, and make it the current version. Only
+    /// the last `KEEP_WORKLOAD_VERSIONS` are kept. The archive holds `.env` and
+    /// `secrets/`, so it is owner-only.
+    pub fn archive_workload(
+        &mut self,
+        zip_data: &[u8],
+        hash: &str,
+        controller: Option<&ControllerSecrets>,
+    ) -> Result<u32> {
+        let version = self.workload_versions.last().map_or(1, |v| v.version + 1);
+        let path = self.workload_zip_path(version)?;
+        let dir = Self::workloads_dir(&self.vm_name)?;
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        // Also tightens archives written before they were kept private
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+        for entry in fs::read_dir(&dir)? {
+            fs::set_permissions(entry?.path(), fs::Permissions::from_mode(0o600))?;
+        }
+        write_private(&path, zip_data)
+            .with_context(|| format!("Failed to archive workload to {}", path.display()))?;
+        if let Some(controller) = controller {
+            write_private(&self.workload_secret_path(version, CONTROLLER_KEY)?, controller.api_key.as_bytes())?;
+            write_private(&self.workload_secret_path(version, JWT_SECRET)?, controller.jwt_secret.as_bytes())?;
+        }
+
+        self.workload_versions.push(WorkloadVersion {
+            version,
+            hash: hash.to_string(),
+            uploaded_at: Utc::now().to_rfc3339(),
+        });
+        self.workload_version = Some(version);
+
+        while self.workload_versions.len() > KEEP_WORKLOAD_VERSIONS {
+            let old = self.workload_versions.remove(0);
+            let mut old_paths = vec![self.workload_zip_path(old.version)?];
+            for name in SECRET_FILES {
+                old_paths.push(self.workload_secret_path(old.version, name)?);
+            }
+            for old_path in old_paths.into_iter().filter(|p| p.exists()) {
+                fs::remove_file(&old_path)
+                    .with_context(|| format!("Failed to remove {}", old_path.display()))?;
+            }
+        }
+        Ok(version)
+    }
+
     /// Load state from disk.
     pub fn load(vm_name: &str) -> Result<Self> {
         let path = Self::state_path(vm_name)?;
@@ -87,12 +263,35 @@ impl DeployState {
         Ok(())
     }
 
-    /// Remove state file.
+    /// Remove state file, secrets and archived workloads.
     pub fn remove(vm_name: &str) -> Result<()> {
-        let path = Self::state_path(vm_name)?;
-        if path.exists() {
-            fs::remove_file(&path)?;
+        let mut paths = vec![Self::state_path(vm_name)?];
+        for name in SECRET_FILES {
+            paths.push(Self::secret_path(vm_name, name)?);
+        }
+        for path in paths {
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+        }
+        let workloads = Self::workloads_dir(vm_name)?;
+        if workloads.exists() {
+            fs::remove_dir_all(&workloads)?;
         }
         Ok(())
     }
 }
+
+/// Create or replace a file with owner-only permissions.
+fn write_private(path: &Path, content: &[u8]) -> Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    // `mode` only applies to new files
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    file.write_all(content).with_context(|| format!("Failed to write {}", path.display()))
+}
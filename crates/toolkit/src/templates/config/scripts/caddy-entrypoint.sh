@@ -1,6 +1,7 @@
 #!/bin/sh
 # Generate Caddyfile from env vars. Uses domains with auto-TLS if set,
-# otherwise falls back to port-based with self-signed certs.
+# otherwise falls back to port-based with self-signed certs. The controller
+# is always served on :8081 with the certificate the toolkit pins.
 
 RPC="${CADDY_RPC_DOMAIN:-}"
 CVM="${CADDY_CVM_DOMAIN:-}"
@@ -26,11 +27,6 @@ if [ -z "$RPC" ] && [ -z "$CVM" ] && [ -z "$CTRL" ]; then
     }
   }
 }
-
-:8081 {
-  tls internal
-  reverse_proxy controller:8080
-}
 NODOMAINS
 else
   # Domains set — auto-TLS via Let's Encrypt
@@ -53,4 +49,12 @@ ${CTRL} {
 EOF
 fi
 
+cat >> /tmp/Caddyfile <<'CONTROLLER'
+
+:8081 {
+  tls /secrets/controller_tls.crt /secrets/controller_tls.key
+  reverse_proxy controller:8080
+}
+CONTROLLER
+
 exec caddy run --config /tmp/Caddyfile --adapter caddyfile
@@ -25,16 +25,58 @@ pub struct ComposeService {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub build: Option<serde_yaml::Value>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
-    pub volumes: Vec<String>,
+    pub volumes: Vec<ComposeVolume>,
     #[serde(default, skip_serializing_if = "EnvFileEntry::is_none")]
     pub env_file: EnvFileEntry,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
-    pub ports: Vec<String>,
+    pub ports: Vec<ComposePort>,
     /// Preserves unknown service-level keys.
     #[serde(flatten)]
     pub extra: IndexMap<String, serde_yaml::Value>,
 }
 
+/// A service volume: short syntax (`./data:/data:ro`) or long syntax (a map
+/// with `type`, `source`, `target`, ...).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ComposeVolume {
+    Short(String),
+    Long(LongVolume),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LongVolume {
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Preserves the other keys (`target`, `read_only`, `bind`, ...).
+    #[serde(flatten)]
+    pub extra: IndexMap<String, serde_yaml::Value>,
+}
+
+/// A service port: short syntax (`"8080:80/tcp"`, or just the container port
+/// as a number) or long syntax (a map with `target`, `published`, ...).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ComposePort {
+    Number(u16),
+    Short(String),
+    Long(LongPort),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LongPort {
+    /// Host port or range, a string or a number
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published: Option<serde_yaml::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_ip: Option<String>,
+    /// Preserves the other keys (`target`, `protocol`, `mode`, ...).
+    #[serde(flatten)]
+    pub extra: IndexMap<String, serde_yaml::Value>,
+}
+
 /// `env_file` can be a single string or a list of strings.
 #[derive(Debug, Default, Deserialize, Serialize)]
 #[serde(untagged)]
@@ -63,7 +105,7 @@ impl EnvFileEntry {
 // Workload manifest (embedded inside the tar.gz package)
 // ---------------------------------------------------------------------------
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct WorkloadManifest {
     pub name: String,
     pub docker_compose: String,
@@ -72,13 +114,30 @@ pub struct WorkloadManifest {
     pub docker_images: Vec<DockerImageEntry>,
 }
 
-#[derive(Debug, Serialize)]
+impl WorkloadManifest {
+    /// Image reference of each compose service that has one (the registry
+    /// reference for bundled images).
+    pub fn images(&self) -> IndexMap<String, String> {
+        self.docker_images
+            .iter()
+            .filter_map(|e| Some((e.service.clone(), e.bundled_from.clone().or(e.image_tag.clone())?)))
+            .collect()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct DockerImageEntry {
     pub service: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub image_tag: Option<String>,
+    /// Manifest digest when the image is pinned (`image@sha256:...`) or bundled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_digest: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub image_tar: Option<String>,
+    /// Registry reference a `bundle_images` tar was pulled from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bundled_from: Option<String>,
 }
 
 // ---------------------------------------------------------------------------
@@ -97,6 +156,9 @@ pub struct GitHubAsset {
     pub url: String,
     pub browser_download_url: String,
     pub size: u64,
+    /// Content digest published by GitHub, e.g. `sha256:<hex>`.
+    #[serde(default)]
+    pub digest: Option<String>,
 }
 
 // ---------------------------------------------------------------------------
@@ -0,0 +1,125 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use tracing::info;
+
+use crate::config::Config;
+use crate::types::DockerCompose;
+use super::registry::{ImageRef, Registry};
+use super::{digests, manifest};
+
+const COMPOSE_FILE: &str = "docker-compose.yml";
+
+/// An image bundled into the workload root.
+pub struct BundledImage {
+    /// The `docker save` tar
+    pub tar: PathBuf,
+    /// Registry reference it was pulled from (`name@sha256:...`)
+    pub source: String,
+}
+
+/// Pull the compose images selected by `bundle_images` by their manifest
+/// digest and `docker save` them into the workload root, so a CVM without
+/// registry access can load them. The saved tars are treated like `image_tars`.
+pub fn bundle(config: &Config, dir: &Path) -> Result<Vec<BundledImage>> {
+    if config.bundle_images.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let compose_path = dir.join(COMPOSE_FILE);
+    let content = fs::read_to_string(&compose_path)
+        .with_context(|| format!("Failed to read {}", compose_path.display()))?;
+    let compose: DockerCompose = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", compose_path.display()))?;
+    let tar_tags: Vec<String> = manifest::image_tars(config, dir)
+        .into_iter()
+        .flat_map(|(_, tags)| tags)
+        .collect();
+
+    let registry = Registry::new()?;
+    let mut bundled = Vec::new();
+    let mut done = HashSet::new();
+    let mut retagged = HashMap::new();
+    for (service, spec) in &compose.services {
+        let Some(image) = spec.image.as_deref() else {
+            continue;
+        };
+        let selected = config.bundle_images.iter()
+            .any(|s| s == "*" || s == service || manifest::same_image(s, image));
+        if !selected || tar_tags.iter().any(|t| manifest::same_image(t, image)) || !done.insert(image) {
+            continue;
+        }
+
+        // Pull by digest, so the tar holds exactly what signatures and pins refer to
+        let reference = ImageRef::parse(image);
+        let digest = match &reference.digest {
+            Some(digest) => digest.clone(),
+            None => digests::resolve_digest(&registry, &reference)
+                .with_context(|| format!("Failed to resolve {} (service {})", image, service))?,
+        };
+        let name = image.split('@').next().unwrap_or(image);
+        let source = format!("{}@{}", name, digest);
+        info!(service, image = %source, "Pulling image to bundle...");
+        docker(&["pull", &source])?;
+        let id = docker_output(&["image", "inspect", "--format", "{{.Id}}", &source])?;
+
+        // `docker load` does not restore repo digests, so a digest reference
+        // would not resolve on the CVM: save it under a tag instead
+        let save_ref = match (&reference.tag, &reference.digest) {
+            (None, Some(_)) => format!("{}:{}", name, digest.replace(':', "-").chars().take(19).collect::<String>()),
+            _ => name.to_string(),
+        };
+        docker(&["tag", &source, &save_ref])?;
+        if save_ref != image {
+            retagged.insert(image.to_string(), save_ref.clone());
+        }
+
+        let path = dir.join(tar_name(&save_ref));
+        info!(image = %save_ref, path = %path.display(), "Saving image into workload...");
+        docker(&["save", "-o", &path.to_string_lossy(), &save_ref])?;
+        let saved = manifest::image_tar_ids(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        if !saved.contains(&id) {
+            bail!("{} does not hold {} (image {}, saved {})", path.display(), source, id, saved.join(", "));
+        }
+        bundled.push(BundledImage { tar: path, source });
+    }
+
+    if !retagged.is_empty() {
+        let rewritten = digests::rewrite_images(&content, &retagged);
+        fs::write(&compose_path, rewritten)
+            .with_context(|| format!("Failed to write {}", compose_path.display()))?;
+    }
+    Ok(bundled)
+}
+
+/// File name for a bundled image, e.g. `sigp_lighthouse_latest-unstable.tar`.
+fn tar_name(image: &str) -> String {
+    let name: String = image
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect();
+    format!("{}.tar", name)
+}
+
+fn docker(args: &[&str]) -> Result<()> {
+    docker_output(args).map(drop)
+}
+
+fn docker_output(args: &[&str]) -> Result<String> {
+    let output = Command::new("docker")
+        .args(args)
+        .output()
+        .context("Failed to run docker (needed to bundle images)")?;
+    if !output.status.success() {
+        bail!(
+            "docker {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use indexmap::IndexMap;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::types::DockerCompose;
+use super::manifest;
+use super::registry::{ImageRef, Registry};
+
+const COMPOSE_FILE: &str = "docker-compose.yml";
+
+/// Pin every compose image to `image@sha256:...`, rewriting docker-compose.yml
+/// in place. Images provided by `image_tars` are loaded on the CVM rather than
+/// pulled, so they keep their tag. Returns the pinned reference per service.
+pub fn pin(config: &Config, dir: &Path) -> Result<IndexMap<String, String>> {
+    let compose_path = dir.join(COMPOSE_FILE);
+    let content = fs::read_to_string(&compose_path)
+        .with_context(|| format!("Failed to read {}", compose_path.display()))?;
+    let compose: DockerCompose = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", compose_path.display()))?;
+    let tar_tags: Vec<String> = manifest::image_tars(config, dir)
+        .into_iter()
+        .flat_map(|(_, tags)| tags)
+        .collect();
+
+    let registry = Registry::new()?;
+    let mut resolved: HashMap<String, String> = HashMap::new();
+    let mut pinned = IndexMap::new();
+    for (service, spec) in &compose.services {
+        let Some(image) = spec.image.as_deref() else {
+            continue;
+        };
+        if tar_tags.iter().any(|t| manifest::same_image(t, image)) {
+            info!(service, image, "Image comes from an image tar, not pinning");
+            continue;
+        }
+        if !resolved.contains_key(image) {
+            let reference = ImageRef::parse(image);
+            let pinned_ref = match reference.digest {
+                Some(_) => image.to_string(),
+                None => {
+                    let digest = resolve_digest(&registry, &reference)
+                        .with_context(|| format!("Failed to pin {} (service {})", image, service))?;
+                    format!("{}@{}", image, digest)
+                }
+            };
+            info!(image, pinned = %pinned_ref, "Pinned image");
+            resolved.insert(image.to_string(), pinned_ref);
+        }
+        pinned.insert(service.clone(), resolved[image].clone());
+    }
+
+    let rewritten = rewrite_images(&content, &resolved);
+    if rewritten != content {
+        fs::write(&compose_path, rewritten)
+            .with_context(|| format!("Failed to write {}", compose_path.display()))?;
+    }
+    Ok(pinned)
+}
+
+/// Services whose image reference carries a digest.
+pub fn pinned(images: &IndexMap<String, String>) -> IndexMap<String, String> {
+    images
+        .iter()
+        .filter(|(_, image)| image.contains("@sha256:"))
+        .map(|(service, image)| (service.clone(), image.clone()))
+        .collect()
+}
+
+/// Replace `image:` values line by line so comments and layout survive.
+pub fn rewrite_images(content: &str, resolved: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        let body = line.trim_end_matches(['\n', '\r']);
+        let ending = &line[body.len()..];
+        let indent = body.len() - body.trim_start().len();
+        let replaced = body.trim_start().strip_prefix("image:").and_then(|rest| {
+            let (value, comment) = match rest.find(" #") {
+                Some(i) => rest.split_at(i),
+                None => (rest, ""),
+            };
+            let gap = &value[value.trim_end().len()..];
+            let value = value.trim();
+            let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'');
+            let bare = match quote {
+                Some(q) => value.trim_matches(q),
+                None => value,
+            };
+            resolved.get(bare).map(|pinned| {
+                let q = quote.map(String::from).unwrap_or_default();
+                format!("{}image: {}{}{}{}{}", &body[..indent], q, pinned, q, gap, comment)
+            })
+        });
+        match replaced {
+            Some(new) => {
+                out.push_str(&new);
+                out.push_str(ending);
+            }
+            None => out.push_str(line),
+        }
+    }
+    out
+}
+
+/// Digest of the image's manifest (list), from its registry or else from the
+/// local Docker daemon for images pulled before.
+pub fn resolve_digest(registry: &Registry, image: &ImageRef) -> Result<String> {
+    let tag = image.tag.as_deref().unwrap_or("latest");
+    let found = registry
+        .manifest(image, tag)
+        .and_then(|m| m.with_context(|| format!("{} not found in registry", image.canonical())));
+    match found {
+        Ok(manifest) => Ok(manifest.digest),
+        Err(e) => {
+            warn!(image = %image.canonical(), error = %e, "Registry lookup failed, trying local Docker");
+            local_digest(image).map_err(|local| e.context(local))
+        }
+    }
+}
+
+/// Repo digest recorded by the local Docker daemon when the image was pulled.
+fn local_digest(image: &ImageRef) -> Result<String> {
+    let name = image.canonical();
+    let output = Command::new("docker")
+        .args(["image", "inspect", "--format", "{{json .RepoDigests}}", &name])
+        .output()
+        .context("Failed to run docker")?;
+    if !output.status.success() {
+        bail!("Image {} is not available locally either", name);
+    }
+    let digests: Vec<String> = serde_json::from_slice(&output.stdout).unwrap_or_default();
+    digests
+        .iter()
+        .map(|d| ImageRef::parse(d))
+        .find(|d| d.same_repository(image))
+        .and_then(|d| d.digest)
+        .with_context(|| format!("Local image {} has no repo digest (built or loaded locally?)", name))
+}
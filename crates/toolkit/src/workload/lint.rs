@@ -0,0 +1,181 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use tracing::warn;
+
+use crate::config::Config;
+use crate::types::{ComposePort, ComposeVolume, DockerCompose};
+
+const COMPOSE_FILE: &str = "docker-compose.yml";
+
+/// Check the workload's docker-compose.yml for mistakes that would otherwise
+/// only show up once the CVM boots. Errors fail resolution, warnings are logged.
+pub fn lint(config: &Config, dir: &Path) -> Result<()> {
+    let compose_path = dir.join(COMPOSE_FILE);
+    let content = fs::read_to_string(&compose_path)
+        .with_context(|| format!("Failed to read {}", compose_path.display()))?;
+
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+
+    // Checked on the raw text: an unquoted `{{...}}` is not valid YAML either
+    for (n, line) in content.lines().enumerate() {
+        if let Some(start) = line.find("{{") {
+            let placeholder = line[start..].split("}}").next().unwrap_or_default();
+            errors.push(format!("line {}: unresolved placeholder {}}}}}", n + 1, placeholder));
+        }
+    }
+    let compose: DockerCompose = match serde_yaml::from_str(&content) {
+        Ok(compose) => compose,
+        Err(_) if !errors.is_empty() => return Err(problems(&compose_path, &errors)),
+        Err(e) => return Err(e).with_context(|| format!("Failed to parse {}", compose_path.display())),
+    };
+
+    let open_ports: Vec<u16> = config.ports.iter().chain(&config.operator_ports).copied().collect();
+    for (name, service) in &compose.services {
+        if service.build.is_some() {
+            errors.push(format!("{}: `build:` cannot run in the CVM, use an image (or image_tars)", name));
+        }
+        if service.image.is_none() && service.build.is_none() {
+            errors.push(format!("{}: no image", name));
+        }
+
+        for env_file in service.env_file.to_paths() {
+            match inside(dir, &env_file) {
+                Some(path) if path.is_file() => {}
+                Some(_) => errors.push(format!("{}: env_file {} is not in the workload", name, env_file)),
+                None => errors.push(format!("{}: env_file {} is outside the workload", name, env_file)),
+            }
+        }
+
+        for volume in &service.volumes {
+            match mount(volume) {
+                Mount::Bind(source) if source.starts_with('~') => {
+                    errors.push(format!("{}: volume {} refers to a home directory on this machine", name, source));
+                }
+                Mount::Bind(source) if source.starts_with('/') => {}
+                Mount::Bind(source) => match inside(dir, source) {
+                    Some(path) if path.exists() => {}
+                    Some(_) => warnings.push(format!("{}: bind mount source {} is not in the workload", name, source)),
+                    None => errors.push(format!("{}: bind mount source {} is outside the workload", name, source)),
+                },
+                Mount::Named(source) => {
+                    let defined = compose.volumes.as_ref().is_some_and(|v| v.contains_key(source));
+                    if !defined {
+                        errors.push(format!("{}: named volume {} is not defined under top-level `volumes:`", name, source));
+                    }
+                }
+                Mount::Other => {}
+            }
+        }
+
+        // tcp and udp mappings of one port are reported once
+        let mut closed: Vec<u16> = service.ports.iter()
+            .filter_map(published_port)
+            .filter(|p| !open_ports.contains(p))
+            .collect();
+        closed.dedup();
+        for port in closed {
+            warnings.push(format!("{}: port {} is published but not in `ports` (firewall)", name, port));
+        }
+    }
+
+    for warning in &warnings {
+        warn!("{}: {}", COMPOSE_FILE, warning);
+    }
+    if !errors.is_empty() {
+        return Err(problems(&compose_path, &errors));
+    }
+    Ok(())
+}
+
+fn problems(compose_path: &Path, errors: &[String]) -> anyhow::Error {
+    anyhow!(
+        "{} has {} problem(s):\n  - {}",
+        compose_path.display(),
+        errors.len(),
+        errors.join("\n  - ")
+    )
+}
+
+/// Resolve a compose-relative path, `None` if it escapes the workload.
+fn inside(dir: &Path, relative: &str) -> Option<PathBuf> {
+    let mut depth = 0i32;
+    for component in Path::new(relative).components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::ParentDir => depth -= 1,
+            Component::CurDir => {}
+            _ => return None,
+        }
+        if depth < 0 {
+            return None;
+        }
+    }
+    Some(dir.join(relative))
+}
+
+/// What a service volume mounts.
+enum Mount<'a> {
+    /// Host path, as written
+    Bind(&'a str),
+    /// Named volume
+    Named(&'a str),
+    /// Anonymous volumes, tmpfs and interpolated sources
+    Other,
+}
+
+/// Short syntax: `source:target[:mode]`, where a source starting with `.`,
+/// `/` or `~` is a host path. Long syntax: `type` and `source`.
+fn mount(volume: &ComposeVolume) -> Mount<'_> {
+    let (bind, source) = match volume {
+        ComposeVolume::Short(spec) => {
+            let source = spec.split(':').next().unwrap_or_default();
+            (source.starts_with(['.', '/', '~']), source)
+        }
+        ComposeVolume::Long(long) => match (long.kind.as_deref(), long.source.as_deref()) {
+            (Some("bind"), Some(source)) => (true, source),
+            (None | Some("volume"), Some(source)) => (false, source),
+            _ => return Mount::Other,
+        },
+    };
+    if source.is_empty() || source.contains('$') {
+        Mount::Other
+    } else if bind {
+        Mount::Bind(source)
+    } else {
+        Mount::Named(source)
+    }
+}
+
+/// Host port of a mapping reachable from outside: short syntax
+/// (`[ip:]host:container[/proto]`) or long syntax (`published`, `host_ip`).
+/// Loopback bindings, ranges and container-only ports are skipped.
+fn published_port(port: &ComposePort) -> Option<u16> {
+    let mapping = match port {
+        ComposePort::Number(_) => return None,
+        ComposePort::Short(mapping) => mapping,
+        ComposePort::Long(long) => {
+            if long.host_ip.as_deref().is_some_and(is_loopback) {
+                return None;
+            }
+            return match long.published.as_ref()? {
+                serde_yaml::Value::Number(n) => n.as_u64().and_then(|n| u16::try_from(n).ok()),
+                serde_yaml::Value::String(s) => s.parse().ok(),
+                _ => None,
+            };
+        }
+    };
+    let mapping = mapping.split('/').next().unwrap_or(mapping);
+    let parts: Vec<&str> = mapping.rsplitn(3, ':').collect();
+    match parts.as_slice() {
+        [_, host] => host.parse().ok(),
+        [_, host, ip] if !is_loopback(ip) => host.parse().ok(),
+        _ => None,
+    }
+}
+
+fn is_loopback(ip: &str) -> bool {
+    ip.starts_with("127.") || ip == "localhost"
+}
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::types::{DockerCompose, DockerImageEntry, WorkloadManifest};
+use super::registry::ImageRef;
+use super::measure;
+
+/// File name of the manifest inside the workload package.
+pub const MANIFEST_FILE: &str = "manifest.json";
+const COMPOSE_FILE: &str = "docker-compose.yml";
+
+/// Describe a resolved workload: its compose file, measured vs data files,
+/// and where each service's image comes from. `bundled` maps the tars of
+/// `bundle_images` to the registry reference they were pulled from.
+pub fn generate(config: &Config, dir: &Path, bundled: &HashMap<String, String>) -> Result<WorkloadManifest> {
+    let compose_path = dir.join(COMPOSE_FILE);
+    let content = fs::read_to_string(&compose_path)
+        .with_context(|| format!("Failed to read {}", compose_path.display()))?;
+    let compose: DockerCompose = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", compose_path.display()))?;
+
+    let tars = image_tars(config, dir);
+
+    let mut docker_images = Vec::new();
+    for (service, spec) in &compose.services {
+        let image_tar = spec.image.as_ref().and_then(|image| {
+            tars.iter()
+                .find(|(_, tags)| tags.iter().any(|t| same_image(t, image)))
+                .map(|(name, _)| name.clone())
+        });
+        let bundled_from = image_tar.as_ref().and_then(|t| bundled.get(t)).cloned();
+        docker_images.push(DockerImageEntry {
+            service: service.clone(),
+            image_tag: spec.image.clone(),
+            image_digest: bundled_from.as_deref().or(spec.image.as_deref()).and_then(|i| ImageRef::parse(i).digest),
+            image_tar,
+            bundled_from,
+        });
+    }
+    for (name, tags) in &tars {
+        if !docker_images.iter().any(|e| e.image_tar.as_ref() == Some(name)) {
+            warn!(tar = %name, ?tags, "Image tar is not used by any compose service");
+        }
+    }
+
+    let mut measured_files = Vec::new();
+    let mut additional_data_files = Vec::new();
+    for file in measure::files(dir)? {
+        if file == MANIFEST_FILE {
+            continue;
+        }
+        if measure::is_measured(&file) {
+            measured_files.push(file);
+        } else {
+            additional_data_files.push(file);
+        }
+    }
+
+    Ok(WorkloadManifest {
+        name: config.vm_name.clone(),
+        docker_compose: COMPOSE_FILE.to_string(),
+        measured_files,
+        additional_data_files,
+        docker_images,
+    })
+}
+
+/// Generate the manifest and write it into the workload directory.
+pub fn write(config: &Config, dir: &Path, bundled: &HashMap<String, String>) -> Result<WorkloadManifest> {
+    let manifest = generate(config, dir, bundled)?;
+    let path = dir.join(MANIFEST_FILE);
+    fs::write(&path, serde_json::to_string_pretty(&manifest)? + "\n")
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    info!(
+        path = %path.display(),
+        measured = manifest.measured_files.len(),
+        data = manifest.additional_data_files.len(),
+        images = manifest.docker_images.len(),
+        "Generated workload manifest"
+    );
+    Ok(manifest)
+}
+
+/// The `image_tars` archives copied into the workload root (by file name),
+/// with the image references each one provides.
+pub fn image_tars(config: &Config, dir: &Path) -> Vec<(String, Vec<String>)> {
+    let mut tars = Vec::new();
+    for tar_path in &config.image_tars {
+        let path = Path::new(tar_path);
+        let Some(name) = path.file_name().map(|n| n.to_string_lossy().to_string()) else {
+            continue;
+        };
+        let tags = image_tar_tags(&dir.join(&name)).unwrap_or_else(|e| {
+            warn!(tar = %name, error = %e, "Failed to read image tags from tar");
+            Vec::new()
+        });
+        tars.push((name, tags));
+    }
+    tars
+}
+
+/// Image references saved in a `docker save` (manifest.json RepoTags) or
+/// OCI layout (index.json ref annotations) tarball, optionally gzipped.
+fn image_tar_tags(path: &Path) -> Result<Vec<String>> {
+    let mut tags = Vec::new();
+    for (name, json) in tar_indexes(path)? {
+        if name == "manifest.json" {
+            for image in json.as_array().into_iter().flatten() {
+                for tag in image["RepoTags"].as_array().into_iter().flatten() {
+                    tags.extend(tag.as_str().map(str::to_string));
+                }
+            }
+        } else {
+            for m in json["manifests"].as_array().into_iter().flatten() {
+                let annotations = &m["annotations"];
+                tags.extend(
+                    ["io.containerd.image.name", "org.opencontainers.image.ref.name"]
+                        .iter()
+                        .find_map(|k| annotations[*k].as_str())
+                        .map(str::to_string),
+                );
+            }
+        }
+    }
+    Ok(tags)
+}
+
+/// Image IDs in an image tarball: the config digests of `docker save`
+/// (manifest.json) and the OCI manifest digests (index.json), as
+/// `sha256:...`. Docker reports one of them as the image ID, depending on
+/// its image store.
+pub fn image_tar_ids(path: &Path) -> Result<Vec<String>> {
+    let mut ids = Vec::new();
+    for (name, json) in tar_indexes(path)? {
+        if name == "manifest.json" {
+            for image in json.as_array().into_iter().flatten() {
+                let config = image["Config"].as_str().unwrap_or_default();
+                let hex = config.rsplit('/').next().unwrap_or_default().trim_end_matches(".json");
+                if !hex.is_empty() {
+                    ids.push(format!("sha256:{}", hex));
+                }
+            }
+        } else {
+            for m in json["manifests"].as_array().into_iter().flatten() {
+                ids.extend(m["digest"].as_str().map(str::to_string));
+            }
+        }
+    }
+    Ok(ids)
+}
+
+/// The manifest.json and index.json of an image tarball, by name.
+fn tar_indexes(path: &Path) -> Result<Vec<(String, serde_json::Value)>> {
+    let mut magic = [0u8; 2];
+    fs::File::open(path)?.read_exact(&mut magic)?;
+    let file = fs::File::open(path)?;
+    let reader: Box<dyn Read> = if magic == [0x1f, 0x8b] {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+
+    let mut indexes = Vec::new();
+    for entry in tar::Archive::new(reader).entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().trim_start_matches("./").to_string();
+        if name != "manifest.json" && name != "index.json" {
+            continue;
+        }
+        let mut content = String::new();
+        entry.read_to_string(&mut content)?;
+        let json: serde_json::Value = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid {} in {}", name, path.display()))?;
+        indexes.push((name, json));
+    }
+    Ok(indexes)
+}
+
+/// Compare image references ignoring the implicit `docker.io/`, `library/` and `:latest`.
+pub fn same_image(a: &str, b: &str) -> bool {
+    ImageRef::parse(a).canonical() == ImageRef::parse(b).canonical()
+}
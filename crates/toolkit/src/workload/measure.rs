@@ -0,0 +1,60 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256, Sha384};
+
+/// Workload files relative to `dir`, sorted.
+pub fn files(dir: &Path) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    for entry in walkdir::WalkDir::new(dir) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            let relative = entry.path().strip_prefix(dir)?;
+            files.push(relative.to_string_lossy().into_owned());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Measure an unpacked workload the way PCR 23 / RTMR3 would see it: SHA-384 over
+/// each file's path and content hash, in path order. `.env` and `secrets/` are
+/// runtime data and not measured, sealed or not.
+pub fn measure(dir: &Path, files: &[String]) -> Result<[u8; 48]> {
+    let mut hasher = Sha384::new();
+    for file in files.iter().filter(|f| is_measured(f)) {
+        let mut content = Sha384::new();
+        File::open(dir.join(file))
+            .and_then(|mut f| io::copy(&mut f, &mut content))
+            .with_context(|| format!("Failed to read {}", file))?;
+        hasher.update(file.as_bytes());
+        hasher.update([0u8]);
+        hasher.update(content.finalize());
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// Whether a workload file is measured: `.env` and `secrets/` are runtime data.
+pub fn is_measured(file: &str) -> bool {
+    file != ".env" && file != ".env.sealed" && !file.starts_with("secrets/")
+}
+
+/// RTMR3 after extending the workload measurement once (zero without a workload).
+pub fn rtmr3(workload: Option<&[u8; 48]>) -> [u8; 48] {
+    match workload {
+        Some(m) => {
+            let mut hasher = Sha384::new();
+            hasher.update([0u8; 48]);
+            hasher.update(m);
+            hasher.finalize().into()
+        }
+        None => [0u8; 48],
+    }
+}
+
+/// SNP host data carrying the workload measurement (zero without a workload).
+pub fn host_data(workload: Option<&[u8; 48]>) -> [u8; 32] {
+    workload.map_or([0u8; 32], |m| Sha256::digest(m).into())
+}
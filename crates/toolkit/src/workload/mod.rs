@@ -1,2 +1,11 @@
+pub mod bundle;
+pub mod digests;
+pub mod lint;
+pub mod manifest;
+pub mod measure;
+pub mod registry;
 pub mod resolve;
+pub mod seal;
+pub mod signatures;
+pub mod snapshot;
 pub mod templates;
@@ -0,0 +1,222 @@
+//! Minimal OCI distribution (registry v2) client: anonymous or `docker login`
+//! pulls of manifests and blobs, verified against their digests.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use reqwest::blocking::Response;
+use reqwest::header::{ACCEPT, WWW_AUTHENTICATE};
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+
+/// Manifest media types accepted from registries. Multi-arch indexes come
+/// first so the digest is the same one `docker pull` records.
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.index.v1+json, \
+    application/vnd.docker.distribution.manifest.list.v2+json, \
+    application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.docker.distribution.manifest.v2+json";
+
+/// A parsed container image reference (`[registry/]repository[:tag][@digest]`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageRef {
+    pub registry: String,
+    pub repository: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+}
+
+impl ImageRef {
+    pub fn parse(image: &str) -> Self {
+        let (name, digest) = match image.split_once('@') {
+            Some((name, digest)) => (name, Some(digest.to_string())),
+            None => (image, None),
+        };
+        let (name, tag) = match name.rsplit_once(':') {
+            Some((n, t)) if !t.contains('/') => (n, Some(t.to_string())),
+            _ => (name, None),
+        };
+        let (registry, repository) = match name.split_once('/') {
+            Some((first, rest)) if first.contains('.') || first.contains(':') || first == "localhost" => {
+                (first.to_string(), rest.to_string())
+            }
+            _ => ("docker.io".to_string(), name.to_string()),
+        };
+        let repository = if registry == "docker.io" && !repository.contains('/') {
+            format!("library/{}", repository)
+        } else {
+            repository
+        };
+        Self { registry, repository, tag, digest }
+    }
+
+    /// Fully qualified form, with the implicit `docker.io/library/` and `:latest` spelled out.
+    pub fn canonical(&self) -> String {
+        let name = format!("{}/{}", self.registry, self.repository);
+        match (&self.tag, &self.digest) {
+            (_, Some(digest)) => format!("{}@{}", name, digest),
+            (Some(tag), None) => format!("{}:{}", name, tag),
+            (None, None) => format!("{}:latest", name),
+        }
+    }
+
+    /// Host serving the registry API.
+    pub fn api_host(&self) -> &str {
+        if self.registry == "docker.io" {
+            "registry-1.docker.io"
+        } else {
+            &self.registry
+        }
+    }
+
+    pub fn same_repository(&self, other: &ImageRef) -> bool {
+        self.registry == other.registry && self.repository == other.repository
+    }
+}
+
+/// A manifest as served by the registry.
+pub struct Manifest {
+    pub digest: String,
+    pub body: Vec<u8>,
+}
+
+pub struct Registry {
+    client: reqwest::blocking::Client,
+}
+
+impl Registry {
+    pub fn new() -> Result<Self> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(std::time::Duration::from_secs(30))
+            .build()?;
+        Ok(Self { client })
+    }
+
+    /// Fetch a manifest by tag or digest, `None` when the registry doesn't have it.
+    pub fn manifest(&self, image: &ImageRef, reference: &str) -> Result<Option<Manifest>> {
+        let resp = self.get(image, &format!("manifests/{}", reference), MANIFEST_TYPES)?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let header = resp
+            .headers()
+            .get("Docker-Content-Digest")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let body = resp.bytes()?.to_vec();
+        let digest = sha256_digest(&body);
+        if let Some(header) = header.filter(|h| *h != digest) {
+            bail!("Registry digest {} does not match the manifest it served ({})", header, digest);
+        }
+        if reference.starts_with("sha256:") && reference != digest {
+            bail!("Registry served manifest {} for {}", digest, reference);
+        }
+        Ok(Some(Manifest { digest, body }))
+    }
+
+    /// Fetch a blob and check it against its digest.
+    pub fn blob(&self, image: &ImageRef, digest: &str) -> Result<Vec<u8>> {
+        let resp = self.get(image, &format!("blobs/{}", digest), "*/*")?;
+        if !resp.status().is_success() {
+            bail!("Blob {} of {} returned {}", digest, image.canonical(), resp.status());
+        }
+        let body = resp.bytes()?.to_vec();
+        if sha256_digest(&body) != digest {
+            bail!("Blob {} of {} does not match its digest", digest, image.canonical());
+        }
+        Ok(body)
+    }
+
+    /// GET `/v2/<repository>/<path>`, answering a bearer challenge once.
+    fn get(&self, image: &ImageRef, path: &str, accept: &str) -> Result<Response> {
+        let url = format!("https://{}/v2/{}/{}", image.api_host(), image.repository, path);
+        let mut resp = self.client.get(&url).header(ACCEPT, accept).send()?;
+        if resp.status() == StatusCode::UNAUTHORIZED {
+            let challenge = resp
+                .headers()
+                .get(WWW_AUTHENTICATE)
+                .and_then(|v| v.to_str().ok())
+                .context("Registry requires authentication but sent no challenge")?
+                .to_string();
+            let token = self.bearer_token(image, &challenge)?;
+            resp = self.client.get(&url).header(ACCEPT, accept).bearer_auth(token).send()?;
+        }
+        if !resp.status().is_success() && resp.status() != StatusCode::NOT_FOUND {
+            bail!("GET {} returned {}", url, resp.status());
+        }
+        Ok(resp)
+    }
+
+    /// Fetch an anonymous (or docker-login) pull token for a `Bearer` challenge.
+    fn bearer_token(&self, image: &ImageRef, challenge: &str) -> Result<String> {
+        let params = challenge
+            .strip_prefix("Bearer ")
+            .map(parse_challenge)
+            .with_context(|| format!("Unsupported registry auth challenge: {}", challenge))?;
+        let realm = params.get("realm").context("Registry auth challenge has no realm")?;
+        let scope = params
+            .get("scope")
+            .cloned()
+            .unwrap_or_else(|| format!("repository:{}:pull", image.repository));
+
+        let mut query = vec![("scope", scope)];
+        if let Some(service) = params.get("service") {
+            query.push(("service", service.clone()));
+        }
+        let mut req = self.client.get(realm).query(&query);
+        if let Some((user, password)) = docker_credentials(&image.registry) {
+            req = req.basic_auth(user, Some(password));
+        }
+        let resp = req.send()?;
+        if !resp.status().is_success() {
+            bail!("Token request to {} returned {} (private image? run `docker login {}`)", realm, resp.status(), image.registry);
+        }
+        let json: serde_json::Value = resp.json()?;
+        json["token"]
+            .as_str()
+            .or_else(|| json["access_token"].as_str())
+            .map(str::to_string)
+            .context("Registry token response has no token")
+    }
+}
+
+/// `key="value",key="value"` parameters of a WWW-Authenticate challenge.
+fn parse_challenge(params: &str) -> HashMap<String, String> {
+    let mut out = HashMap::new();
+    let mut rest = params.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_string();
+        let (value, next) = match after.strip_prefix('"') {
+            Some(quoted) => match quoted.split_once('"') {
+                Some((v, next)) => (v, next),
+                None => (quoted, ""),
+            },
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+        out.insert(key, value.to_string());
+        rest = next.trim_start_matches(',').trim();
+    }
+    out
+}
+
+/// Basic-auth credentials stored by `docker login` (credential helpers are not consulted).
+fn docker_credentials(registry: &str) -> Option<(String, String)> {
+    let dir = std::env::var_os("DOCKER_CONFIG")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|h| h.join(".docker")))?;
+    let config: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.join("config.json")).ok()?).ok()?;
+    let keys = match registry {
+        "docker.io" => vec!["https://index.docker.io/v1/".to_string(), "docker.io".to_string()],
+        other => vec![other.to_string(), format!("https://{}", other)],
+    };
+    let auth = keys.iter().find_map(|k| config["auths"][k]["auth"].as_str())?;
+    let decoded = base64::engine::general_purpose::STANDARD.decode(auth).ok()?;
+    let (user, password) = String::from_utf8(decoded).ok()?.split_once(':').map(|(u, p)| (u.to_string(), p.to_string()))?;
+    Some((user, password))
+}
+
+fn sha256_digest(data: &[u8]) -> String {
+    format!("sha256:{}", hex::encode(Sha256::digest(data)))
+}
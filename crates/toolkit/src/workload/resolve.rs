@@ -6,23 +6,28 @@ use tempfile::TempDir;
 use tracing::info;
 
 use crate::config::Config;
-use super::templates;
+use crate::controller::secrets::{self, ControllerSecrets};
+use crate::secret_ref;
+use crate::types::WorkloadManifest;
+use super::{bundle, digests, lint, manifest, templates};
 
 /// Resolved workload directory with optional temp dir handle.
 pub struct ResolvedWorkload {
     pub path: PathBuf,
+    /// The manifest.json written into the workload
+    pub manifest: WorkloadManifest,
     /// Hold this to keep the temp dir alive.
-    _temp_dir: Option<TempDir>,
+    _temp_dir: TempDir,
 }
 
 /// Resolve the workload source: custom dir or embedded template.
 /// `ip` is passed when known (e.g., during update) for identity.env.
-pub fn resolve(config: &Config) -> Result<ResolvedWorkload> {
-    resolve_with_ip(config, None)
+pub fn resolve(config: &Config, controller: &ControllerSecrets) -> Result<ResolvedWorkload> {
+    resolve_with_ip(config, None, controller)
 }
 
 /// Resolve workload with a known IP address (for update command).
-pub fn resolve_with_ip(config: &Config, ip: Option<&str>) -> Result<ResolvedWorkload> {
+pub fn resolve_with_ip(config: &Config, ip: Option<&str>, controller: &ControllerSecrets) -> Result<ResolvedWorkload> {
     if let Some(ref workload_dir) = config.workload_dir {
         let path = PathBuf::from(workload_dir);
         if !path.exists() {
@@ -34,14 +39,24 @@ pub fn resolve_with_ip(config: &Config, ip: Option<&str>) -> Result<ResolvedWork
 
         info!(path = %path.display(), "Using custom workload directory");
 
+        // Resolution writes .env and secrets/ (with resolved secret references),
+        // and pinning, bundling and sealing rewrite files, so work on a copy
+        // and leave the user's directory alone
+        let temp_dir = TempDir::new().context("Failed to create temp directory")?;
+        copy_dir(&path, temp_dir.path())?;
+        let path = temp_dir.path().to_path_buf();
+
         write_dotenv(config, &path)?;
         write_identity_env(config, &path, ip)?;
+        write_controller_secrets(&path, controller)?;
         copy_image_tars(config, &path)?;
         copy_secret_files(config, &path)?;
+        let manifest = finish(config, &path)?;
 
         Ok(ResolvedWorkload {
             path,
-            _temp_dir: None,
+            manifest,
+            _temp_dir: temp_dir,
         })
     } else {
         info!("Using built-in workload template");
@@ -52,16 +67,53 @@ pub fn resolve_with_ip(config: &Config, ip: Option<&str>) -> Result<ResolvedWork
         templates::write_all(&workload_path, config)?;
         write_dotenv(config, &workload_path)?;
         write_identity_env(config, &workload_path, ip)?;
+        write_controller_secrets(&workload_path, controller)?;
         copy_image_tars(config, &workload_path)?;
         copy_secret_files(config, &workload_path)?;
+        let manifest = finish(config, &workload_path)?;
 
         Ok(ResolvedWorkload {
             path: workload_path,
-            _temp_dir: Some(temp_dir),
+            manifest,
+            _temp_dir: temp_dir,
         })
     }
 }
 
+/// Lint the compose file, bundle and pin images, then write the workload manifest.
+fn finish(config: &Config, workload_dir: &Path) -> Result<WorkloadManifest> {
+    lint::lint(config, workload_dir)?;
+
+    // Bundled images are loaded from the workload like `image_tars`
+    let bundled = bundle::bundle(config, workload_dir)?;
+    let mut config = config.clone();
+    config.image_tars.extend(bundled.iter().map(|b| b.tar.to_string_lossy().to_string()));
+
+    if config.pin_digests {
+        digests::pin(&config, workload_dir)?;
+    }
+    let sources = bundled
+        .into_iter()
+        .filter_map(|b| Some((b.tar.file_name()?.to_string_lossy().into_owned(), b.source)))
+        .collect();
+    manifest::write(&config, workload_dir, &sources)
+}
+
+/// Copy a workload directory tree (following symlinks).
+fn copy_dir(src: &Path, dest: &Path) -> Result<()> {
+    for entry in walkdir::WalkDir::new(src).follow_links(true) {
+        let entry = entry?;
+        let target = dest.join(entry.path().strip_prefix(src)?);
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)?;
+        } else {
+            fs::copy(entry.path(), &target)
+                .with_context(|| format!("Failed to copy {}", entry.path().display()))?;
+        }
+    }
+    Ok(())
+}
+
 /// Copy image tar archives into workload directory.
 fn copy_image_tars(config: &Config, workload_dir: &Path) -> Result<()> {
     if config.image_tars.is_empty() {
@@ -84,7 +136,7 @@ fn copy_image_tars(config: &Config, workload_dir: &Path) -> Result<()> {
     Ok(())
 }
 
-/// Copy secret files into workload/secrets/.
+/// Copy secret files into workload/secrets/ (or write resolved secret references).
 fn copy_secret_files(config: &Config, workload_dir: &Path) -> Result<()> {
     if config.secret_files.is_empty() {
         return Ok(());
@@ -94,11 +146,19 @@ fn copy_secret_files(config: &Config, workload_dir: &Path) -> Result<()> {
     fs::create_dir_all(&secrets_dir)?;
 
     for (filename, src_path) in &config.secret_files {
+        let dest = secrets_dir.join(filename);
+        if secret_ref::is_reference(src_path) {
+            let content = secret_ref::resolve_bytes(src_path, config.project_id.as_deref())
+                .with_context(|| format!("Failed to resolve secret file {}", filename))?;
+            fs::write(&dest, content)
+                .with_context(|| format!("Failed to write {}", dest.display()))?;
+            info!(filename, source = %secret_ref::describe(src_path), "Wrote secret file");
+            continue;
+        }
         let src = PathBuf::from(src_path);
         if !src.exists() {
             bail!("Secret file not found: {}", src.display());
         }
-        let dest = secrets_dir.join(filename);
         fs::copy(&src, &dest)
             .with_context(|| format!("Failed to copy secret: {}", src.display()))?;
         info!(filename, "Copied secret file");
@@ -109,7 +169,7 @@ fn copy_secret_files(config: &Config, workload_dir: &Path) -> Result<()> {
 
 /// Write .env file from config's env map.
 fn write_dotenv(config: &Config, workload_dir: &Path) -> Result<()> {
-    let content = config.generate_dotenv();
+    let content = config.generate_dotenv()?;
     let env_path = workload_dir.join(".env");
     fs::write(&env_path, &content)
         .with_context(|| format!("Failed to write .env to {}", env_path.display()))?;
@@ -139,3 +199,25 @@ fn write_identity_env(config: &Config, workload_dir: &Path, ip: Option<&str>) ->
     info!(path = %path.display(), "Generated identity.env");
     Ok(())
 }
+
+/// Write the controller API key hash, the engine API JWT secret and the
+/// controller's TLS identity into workload/secrets/. `secret_files` with the
+/// same names take precedence.
+fn write_controller_secrets(workload_dir: &Path, controller: &ControllerSecrets) -> Result<()> {
+    let secrets_dir = workload_dir.join("secrets");
+    fs::create_dir_all(&secrets_dir)?;
+
+    let (tls_cert, tls_key) = controller.tls_identity()?;
+    for (filename, content) in [
+        (secrets::API_KEY_HASH_FILE, controller.api_key_hash() + "\n"),
+        (secrets::JWT_SECRET_FILE, controller.jwt_secret.clone() + "\n"),
+        (secrets::TLS_CERT_FILE, tls_cert),
+        (secrets::TLS_KEY_FILE, tls_key),
+    ] {
+        let path = secrets_dir.join(filename);
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    info!("Wrote controller API key hash, JWT secret and TLS identity");
+    Ok(())
+}
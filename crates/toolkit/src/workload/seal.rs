@@ -0,0 +1,167 @@
+//! Sealing workload secrets to a CVM (`seal_secrets`): `.env` and `secrets/*`
+//! are replaced by `<file>.sealed`, encrypted to the secp256k1 sealing key the
+//! CVM's agent publishes with attestation evidence (`GET /sealing-key`). The
+//! agent unseals them before starting the workload, so the disk image in the
+//! bucket, the update zip and the local archives only carry ciphertext.
+//!
+//! A sealed file is `MAGIC || ephemeral public key (33) || nonce (12) ||
+//! AES-256-GCM ciphertext`. The key is HKDF-SHA256 over the ECDH x-coordinate,
+//! salted with both public keys, and the file path is the associated data so
+//! one sealed file can't be passed off as another.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use k256::{PublicKey, SecretKey};
+use rand::rngs::OsRng;
+use ring::{aead, hkdf};
+use tracing::info;
+
+use crate::config::Config;
+use crate::state::DeployState;
+use super::measure;
+
+/// Suffix of a sealed workload file.
+pub const SEALED_SUFFIX: &str = ".sealed";
+
+const MAGIC: &[u8; 8] = b"TKSEAL01";
+const HKDF_INFO: &[u8] = b"toolkit workload seal v1";
+const KEY_LEN: usize = 33;
+
+/// Parse a SEC1 secp256k1 public key (hex, `0x` optional).
+pub fn parse_key(key: &str) -> Result<PublicKey> {
+    let bytes = hex::decode(key.trim().trim_start_matches("0x")).context("Sealing key is not hex")?;
+    PublicKey::from_sec1_bytes(&bytes).map_err(|_| anyhow!("Sealing key is not a secp256k1 public key"))
+}
+
+/// Compressed SEC1 hex (`0x...`), as recorded in state and artifact manifests.
+pub fn key_hex(key: &PublicKey) -> String {
+    format!("0x{}", hex::encode(key.to_encoded_point(true).as_bytes()))
+}
+
+/// The key to seal a workload baked into a disk (`build`, `deploy`) to:
+/// `sealing_key` from the config, else the one recorded for the VM by an
+/// earlier deploy or update. `None` unless `seal_secrets` is set.
+pub fn configured_key(config: &Config) -> Result<Option<PublicKey>> {
+    if !config.seal_secrets {
+        return Ok(None);
+    }
+    if let Some(ref key) = config.sealing_key {
+        return parse_key(key).context("Invalid 'sealing_key' in config").map(Some);
+    }
+    let recorded = DeployState::load(&config.vm_name).ok().and_then(|s| s.sealing_key);
+    match recorded {
+        Some(key) => parse_key(&key).map(Some),
+        None => bail!(
+            "seal_secrets needs 'sealing_key' in the config (no key recorded for '{}' yet)",
+            config.vm_name
+        ),
+    }
+}
+
+/// Replace `.env` and everything under `secrets/` in `dir` with sealed copies.
+/// Returns the files that were sealed.
+pub fn seal(dir: &Path, key: &PublicKey) -> Result<Vec<String>> {
+    let mut sealed = Vec::new();
+    for file in measure::files(dir)?.into_iter().filter(|f| is_secret(f)) {
+        let path = dir.join(&file);
+        let content = fs::read(&path).with_context(|| format!("Failed to read {}", file))?;
+        let target = dir.join(format!("{}{}", file, SEALED_SUFFIX));
+        fs::write(&target, seal_bytes(&content, &file, key)?)
+            .with_context(|| format!("Failed to write {}", target.display()))?;
+        fs::remove_file(&path)?;
+        sealed.push(file);
+    }
+    info!(files = sealed.len(), key = %key_hex(key), "Sealed workload secrets");
+    Ok(sealed)
+}
+
+/// Replace every `<file>.sealed` in `dir` with its plaintext, as the agent
+/// does before starting the workload. Returns the files that were unsealed.
+pub fn unseal(dir: &Path, key: &SecretKey) -> Result<Vec<String>> {
+    let mut unsealed = Vec::new();
+    for file in measure::files(dir)? {
+        let Some(original) = file.strip_suffix(SEALED_SUFFIX) else {
+            continue;
+        };
+        let path = dir.join(&file);
+        let content = unseal_bytes(&fs::read(&path)?, original, key)
+            .with_context(|| format!("Failed to unseal {}", file))?;
+        fs::write(dir.join(original), content)
+            .with_context(|| format!("Failed to write {}", original))?;
+        fs::remove_file(&path)?;
+        unsealed.push(original.to_string());
+    }
+    Ok(unsealed)
+}
+
+/// Files sealed with `seal_secrets`: the runtime data `measure` skips.
+fn is_secret(file: &str) -> bool {
+    (file == ".env" || file.starts_with("secrets/")) && !file.ends_with(SEALED_SUFFIX)
+}
+
+fn seal_bytes(plaintext: &[u8], path: &str, recipient: &PublicKey) -> Result<Vec<u8>> {
+    let ephemeral = SecretKey::random(&mut OsRng);
+    let ephemeral_public = ephemeral.public_key().to_encoded_point(true);
+    let recipient_public = recipient.to_encoded_point(true);
+    let key = derive_key(&ephemeral, recipient, ephemeral_public.as_bytes(), recipient_public.as_bytes())?;
+
+    let nonce: [u8; aead::NONCE_LEN] = rand::random();
+    let mut ciphertext = plaintext.to_vec();
+    key.seal_in_place_append_tag(
+        aead::Nonce::assume_unique_for_key(nonce),
+        aead::Aad::from(path.as_bytes()),
+        &mut ciphertext,
+    )
+    .map_err(|_| anyhow!("Failed to seal {}", path))?;
+
+    let mut out = MAGIC.to_vec();
+    out.extend_from_slice(ephemeral_public.as_bytes());
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+fn unseal_bytes(sealed: &[u8], path: &str, key: &SecretKey) -> Result<Vec<u8>> {
+    let header = MAGIC.len() + KEY_LEN + aead::NONCE_LEN;
+    if sealed.len() < header + aead::AES_256_GCM.tag_len() || !sealed.starts_with(MAGIC) {
+        bail!("Not a sealed file");
+    }
+    let ephemeral_public = &sealed[MAGIC.len()..MAGIC.len() + KEY_LEN];
+    let ephemeral = PublicKey::from_sec1_bytes(ephemeral_public)
+        .map_err(|_| anyhow!("Invalid ephemeral key"))?;
+    let recipient_public = key.public_key().to_encoded_point(true);
+    let cipher = derive_key(key, &ephemeral, ephemeral_public, recipient_public.as_bytes())?;
+
+    let nonce: [u8; aead::NONCE_LEN] = sealed[MAGIC.len() + KEY_LEN..header].try_into()?;
+    let mut buf = sealed[header..].to_vec();
+    let plaintext = cipher
+        .open_in_place(
+            aead::Nonce::assume_unique_for_key(nonce),
+            aead::Aad::from(path.as_bytes()),
+            &mut buf,
+        )
+        .map_err(|_| anyhow!("Sealed to another key, or corrupted"))?;
+    Ok(plaintext.to_vec())
+}
+
+/// AES-256-GCM key from ECDH between `secret` and `peer`.
+fn derive_key(
+    secret: &SecretKey,
+    peer: &PublicKey,
+    ephemeral_public: &[u8],
+    recipient_public: &[u8],
+) -> Result<aead::LessSafeKey> {
+    let shared = (peer.to_projective() * *secret.to_nonzero_scalar()).to_affine();
+    let shared = shared.to_encoded_point(false);
+    let x = shared.x().context("ECDH produced the point at infinity")?;
+
+    let salt = hkdf::Salt::new(hkdf::HKDF_SHA256, &[ephemeral_public, recipient_public].concat());
+    let prk = salt.extract(x);
+    let okm = prk
+        .expand(&[HKDF_INFO], &aead::AES_256_GCM)
+        .map_err(|_| anyhow!("Failed to derive the sealing key"))?;
+    Ok(aead::LessSafeKey::new(aead::UnboundKey::from(okm)))
+}
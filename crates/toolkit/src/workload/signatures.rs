@@ -0,0 +1,142 @@
+use std::collections::HashSet;
+use std::fs;
+
+use anyhow::{bail, Context, Result};
+use tracing::{info, warn};
+
+use crate::config::{Config, ImageVerifyPolicy};
+use crate::sigstore::{self, Identity, ImageSignature, TrustRoot};
+use crate::types::WorkloadManifest;
+use super::registry::{ImageRef, Registry};
+
+const SIMPLE_SIGNING_TYPE: &str = "cosign container image signature";
+
+/// Check the cosign signature of every compose image that has a policy in
+/// `images.verify`, failing on an unsigned or mismatched image. Bundled
+/// images are checked by the registry digest they were pulled from.
+pub fn verify(config: &Config, workload: &WorkloadManifest) -> Result<()> {
+    let policies = &config.images.verify;
+    if policies.is_empty() {
+        return Ok(());
+    }
+
+    let registry = Registry::new()?;
+    let mut root: Option<TrustRoot> = None;
+    let mut seen = HashSet::new();
+    for entry in &workload.docker_images {
+        let Some(image) = entry.bundled_from.as_deref().or(entry.image_tag.as_deref()) else {
+            continue;
+        };
+        if !seen.insert(image) {
+            continue;
+        }
+        let reference = ImageRef::parse(image);
+        let Some((pattern, policy)) = policies.iter().find(|(p, _)| matches(p, &reference)) else {
+            info!(image, "No signature policy for image");
+            continue;
+        };
+        if entry.image_tar.is_some() && entry.bundled_from.is_none() {
+            bail!("{} comes from an image tar and cannot be checked against registry signatures (policy '{}')", image, pattern);
+        }
+
+        if policy.identity.is_some() && root.is_none() {
+            let path = config.trust_root_path().context("Keyless image signatures need 'trust_root'")?;
+            root = Some(TrustRoot::load(&path)?);
+        }
+        let signer = verify_image(&registry, &reference, policy, root.as_ref())
+            .with_context(|| format!("Signature verification failed for {}", image))?;
+        info!(image, signer = %signer, "Image signature verified");
+    }
+    Ok(())
+}
+
+/// Policy keys are an image name (any tag or digest) or a prefix ending in `*`.
+fn matches(pattern: &str, image: &ImageRef) -> bool {
+    let name = format!("{}/{}", image.registry, image.repository);
+    match pattern.strip_suffix('*') {
+        Some("") => true,
+        Some(prefix) => {
+            let p = ImageRef::parse(prefix);
+            name.starts_with(&format!("{}/{}", p.registry, p.repository)) || name.starts_with(prefix)
+        }
+        None => ImageRef::parse(pattern).same_repository(image),
+    }
+}
+
+/// Find a signature for the image's manifest digest that satisfies `policy`.
+/// Returns who signed it.
+fn verify_image(
+    registry: &Registry,
+    image: &ImageRef,
+    policy: &ImageVerifyPolicy,
+    root: Option<&TrustRoot>,
+) -> Result<String> {
+    let digest = match &image.digest {
+        Some(digest) => digest.clone(),
+        None => {
+            warn!(image = %image.canonical(), "Verifying a tag, which can move after the check (use --pin-digests)");
+            let tag = image.tag.as_deref().unwrap_or("latest");
+            registry.manifest(image, tag)?.context("Image not found in registry")?.digest
+        }
+    };
+
+    let sig_tag = format!("{}.sig", digest.replace(':', "-"));
+    let sig_manifest = registry
+        .manifest(image, &sig_tag)?
+        .with_context(|| format!("Image is not signed (no {} in the registry)", sig_tag))?;
+    let sig_manifest: serde_json::Value = serde_json::from_slice(&sig_manifest.body)
+        .context("Invalid signature manifest")?;
+
+    let key = match &policy.key {
+        Some(path) => {
+            let path = shellexpand::tilde(path).to_string();
+            Some(fs::read_to_string(&path).with_context(|| format!("Failed to read public key {}", path))?)
+        }
+        None => None,
+    };
+
+    let mut errors = Vec::new();
+    for layer in sig_manifest["layers"].as_array().into_iter().flatten() {
+        let result = layer["digest"]
+            .as_str()
+            .context("Signature layer has no digest")
+            .and_then(|d| registry.blob(image, d))
+            .and_then(|payload| ImageSignature::from_layer(payload, &layer["annotations"]))
+            .and_then(|signature| {
+                check_payload(&signature.payload, &digest)?;
+                match (&key, &policy.identity, root) {
+                    (Some(key), _, _) => signature.verify_key(key).map(|_| "key".to_string()),
+                    (None, Some(identity), Some(root)) => {
+                        let identity = Identity {
+                            san_prefix: identity,
+                            issuer: policy.issuer.as_deref().unwrap_or(sigstore::GITHUB_ACTIONS_ISSUER),
+                        };
+                        signature.verify_keyless(root, &identity).map(|v| v.signer)
+                    }
+                    _ => bail!("Signature policy has neither a key nor a keyless identity"),
+                }
+            });
+        match result {
+            Ok(signer) => return Ok(signer),
+            Err(e) => errors.push(format!("{:#}", e)),
+        }
+    }
+    if errors.is_empty() {
+        bail!("Signature manifest {} has no signatures", sig_tag);
+    }
+    bail!("No signature matches the policy: {}", errors.join("; "))
+}
+
+/// The simple-signing payload must be for exactly this manifest digest.
+fn check_payload(payload: &[u8], digest: &str) -> Result<()> {
+    let payload: serde_json::Value = serde_json::from_slice(payload).context("Invalid signature payload")?;
+    let critical = &payload["critical"];
+    if critical["type"].as_str() != Some(SIMPLE_SIGNING_TYPE) {
+        bail!("Unexpected signature payload type {}", critical["type"]);
+    }
+    let signed = critical["image"]["docker-manifest-digest"].as_str().unwrap_or_default();
+    if signed != digest {
+        bail!("Signature is for {}, not {}", signed, digest);
+    }
+    Ok(())
+}
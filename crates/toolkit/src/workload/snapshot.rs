@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use rand::RngCore;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::state::{self, DeployState};
+use super::measure;
+
+const COMPOSE_FILE: &str = "docker-compose.yml";
+const DOTENV_FILE: &str = ".env";
+/// Generated from the VM's name, region and IP. Deploy writes it before the
+/// IP is known and update after, so it is left out rather than always
+/// showing as changed.
+const IDENTITY_FILE: &str = "secrets/identity.env";
+
+/// What was uploaded to a CVM, kept in the deployment state to preview the
+/// next update. Holds hashes only, never secret values. Runtime data is
+/// hashed with the VM's snapshot key, so low-entropy secrets can't be
+/// guessed from the state file.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WorkloadSnapshot {
+    /// Workload measurement (hex SHA-384, see `measure`)
+    pub hash: String,
+    /// SHA-256 of each measured file and HMAC-SHA256 of runtime data files,
+    /// by path relative to the workload root
+    pub files: BTreeMap<String, String>,
+    /// HMAC-SHA256 of each `.env` value, by variable name
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// docker-compose.yml as uploaded
+    pub compose: String,
+    pub uploaded_at: String,
+}
+
+impl WorkloadSnapshot {
+    /// Snapshot an unpacked workload, hashing runtime data with `key` (see `vm_key`).
+    pub fn capture(dir: &Path, key: &str) -> Result<Self> {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, key.as_bytes());
+        let paths = measure::files(dir)?;
+        let hash = hex::encode(measure::measure(dir, &paths)?);
+
+        let mut files = BTreeMap::new();
+        for path in paths.into_iter().filter(|p| p != IDENTITY_FILE) {
+            let mut file = File::open(dir.join(&path)).with_context(|| format!("Failed to read {}", path))?;
+            let digest = if measure::is_measured(&path) {
+                let mut hasher = Sha256::new();
+                io::copy(&mut file, &mut hasher).with_context(|| format!("Failed to read {}", path))?;
+                hex::encode(hasher.finalize())
+            } else {
+                let mut mac = HmacWriter(hmac::Context::with_key(&key));
+                io::copy(&mut file, &mut mac).with_context(|| format!("Failed to read {}", path))?;
+                hex::encode(mac.0.sign())
+            };
+            files.insert(path, digest);
+        }
+        let env = fs::read_to_string(dir.join(DOTENV_FILE))
+            .map(|content| {
+                content
+                    .lines()
+                    .filter(|l| !l.trim_start().starts_with('#'))
+                    .filter_map(|l| l.split_once('='))
+                    .map(|(k, v)| (k.trim().to_string(), hex::encode(hmac::sign(&key, v.as_bytes()))))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let compose = fs::read_to_string(dir.join(COMPOSE_FILE)).unwrap_or_default();
+
+        Ok(Self {
+            hash,
+            files,
+            env,
+            compose,
+            uploaded_at: chrono::Utc::now().to_rfc3339(),
+        })
+    }
+}
+
+/// The random key a VM's snapshots hash runtime data with, generated on
+/// first use and kept with its other secrets.
+pub fn vm_key(vm_name: &str) -> Result<String> {
+    if let Some(key) = DeployState::load_secret(vm_name, state::SNAPSHOT_KEY)? {
+        return Ok(key);
+    }
+    let key = generate_key();
+    DeployState::save_secret(vm_name, state::SNAPSHOT_KEY, &key)?;
+    Ok(key)
+}
+
+/// A fresh snapshot key (hex).
+pub fn generate_key() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Feeds `io::copy` into an HMAC.
+struct HmacWriter(hmac::Context);
+
+impl Write for HmacWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Changes between the last uploaded workload and a new one.
+#[derive(Debug, Default)]
+pub struct WorkloadDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+    /// `.env` variables: (name, "added" | "removed" | "changed")
+    pub env: Vec<(String, &'static str)>,
+    /// Per compose service, a description of each change
+    pub services: BTreeMap<String, Vec<String>>,
+    pub measurement_changed: bool,
+}
+
+impl WorkloadDiff {
+    pub fn new(old: &WorkloadSnapshot, new: &WorkloadSnapshot) -> Self {
+        let mut diff = WorkloadDiff {
+            measurement_changed: old.hash != new.hash,
+            ..Default::default()
+        };
+
+        for (path, hash) in &new.files {
+            match old.files.get(path) {
+                None => diff.added.push(path.clone()),
+                Some(old_hash) if old_hash != hash => diff.changed.push(path.clone()),
+                _ => {}
+            }
+        }
+        diff.removed = old.files.keys().filter(|p| !new.files.contains_key(*p)).cloned().collect();
+
+        for (name, hash) in &new.env {
+            match old.env.get(name) {
+                None => diff.env.push((name.clone(), "added")),
+                Some(old_hash) if old_hash != hash => diff.env.push((name.clone(), "changed")),
+                _ => {}
+            }
+        }
+        for name in old.env.keys().filter(|k| !new.env.contains_key(*k)) {
+            diff.env.push((name.clone(), "removed"));
+        }
+
+        diff.services = compose_changes(&old.compose, &new.compose);
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    pub fn print(&self) {
+        if self.is_empty() {
+            println!("No changes since the last upload.");
+            return;
+        }
+        println!("Files:");
+        for (sign, paths) in [("+", &self.added), ("-", &self.removed), ("~", &self.changed)] {
+            for path in paths {
+                let note = if measure::is_measured(path) { "" } else { "  (runtime data, not measured)" };
+                println!("  {} {}{}", sign, path, note);
+            }
+        }
+        if !self.env.is_empty() {
+            println!(".env:");
+            for (name, change) in &self.env {
+                println!("  {} {} (value masked)", change, name);
+            }
+        }
+        if !self.services.is_empty() {
+            println!("Services:");
+            for (service, changes) in &self.services {
+                println!("  {}:", service);
+                for change in changes {
+                    println!("    {}", change);
+                }
+            }
+        }
+        if self.measurement_changed {
+            println!("The workload measurement changes (RTMR3 / host data will differ).");
+        } else {
+            println!("Only runtime data changes; the workload measurement stays the same.");
+        }
+    }
+}
+
+/// Service-level differences between two compose files. Only image references
+/// are shown in full; other keys may carry secrets and are named only.
+fn compose_changes(old: &str, new: &str) -> BTreeMap<String, Vec<String>> {
+    let services = |content: &str| -> BTreeMap<String, serde_yaml::Mapping> {
+        serde_yaml::from_str::<serde_yaml::Value>(content)
+            .ok()
+            .and_then(|v| v.get("services").and_then(|s| s.as_mapping()).cloned())
+            .into_iter()
+            .flatten()
+            .filter_map(|(k, v)| Some((k.as_str()?.to_string(), v.as_mapping()?.clone())))
+            .collect()
+    };
+    let (old, new) = (services(old), services(new));
+
+    let mut changes = BTreeMap::new();
+    for (name, spec) in &new {
+        let Some(old_spec) = old.get(name) else {
+            changes.insert(name.clone(), vec!["added".to_string()]);
+            continue;
+        };
+        let mut service = Vec::new();
+        for (key, value) in spec {
+            let key_name = key.as_str().unwrap_or_default();
+            match old_spec.get(key) {
+                None => service.push(format!("+ {}", key_name)),
+                Some(old_value) if old_value != value => {
+                    if key_name == "image" {
+                        service.push(format!(
+                            "~ image: {} -> {}",
+                            old_value.as_str().unwrap_or_default(),
+                            value.as_str().unwrap_or_default()
+                        ));
+                    } else {
+                        service.push(format!("~ {}", key_name));
+                    }
+                }
+                _ => {}
+            }
+        }
+        for key in old_spec.keys().filter(|k| !spec.contains_key(*k)) {
+            service.push(format!("- {}", key.as_str().unwrap_or_default()));
+        }
+        if !service.is_empty() {
+            changes.insert(name.clone(), service);
+        }
+    }
+    for name in old.keys().filter(|n| !new.contains_key(*n)) {
+        changes.insert(name.clone(), vec!["removed".to_string()]);
+    }
+    changes
+}